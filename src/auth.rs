@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+
+/// Bearer-token check for expensive and mutating requests: Ollama answers,
+/// any non-GET route, and (with `require_for_search`) every `/api/` route.
+pub struct ApiAuth {
+    token: String,
+    require_for_search: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnswerFlag {
+    answer: Option<bool>,
+}
+
+impl ApiAuth {
+    pub fn new(token: String, require_for_search: bool) -> Self {
+        Self {
+            token,
+            require_for_search,
+        }
+    }
+
+    fn protects(&self, request: &Request) -> bool {
+        if !matches!(
+            *request.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS
+        ) {
+            return true;
+        }
+
+        let path = request.uri().path();
+        if self.require_for_search && path.starts_with("/api/") {
+            return true;
+        }
+
+        is_answer_request(request)
+    }
+
+    fn accepts(&self, headers: &HeaderMap) -> bool {
+        let Some(presented) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+
+        // Compare every byte so the time taken doesn't reveal the prefix.
+        let (presented, expected) = (presented.trim().as_bytes(), self.token.as_bytes());
+        presented.len() == expected.len()
+            && presented
+                .iter()
+                .zip(expected)
+                .fold(0u8, |diff, (left, right)| diff | (left ^ right))
+                == 0
+    }
+}
+
+/// True for requests that make Ollama generate an answer.
+pub fn is_answer_request(request: &Request) -> bool {
+    is_answer_uri(request.uri())
+}
+
+/// True for the answer stream and for searches (plain or scoped to one
+/// source) with `answer=true`.
+pub fn is_answer_uri(uri: &Uri) -> bool {
+    let path = uri.path();
+    if path == "/api/answer/stream" {
+        return true;
+    }
+    (path == "/api/search" || path.starts_with("/api/search/source/"))
+        && Query::<AnswerFlag>::try_from_uri(uri)
+            .is_ok_and(|Query(flag)| flag.answer.unwrap_or(false))
+}
+
+/// Middleware answering 401 to protected requests without the right
+/// `Authorization: Bearer <token>` header.
+pub async fn require_api_token(
+    State(auth): State<Arc<ApiAuth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if auth.protects(&request) && !auth.accepts(request.headers()) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(serde_json::json!({ "error": "missing or invalid API token" })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(require_for_search: bool) -> Router {
+        let auth = Arc::new(ApiAuth::new("secret".to_string(), require_for_search));
+        Router::new()
+            .route("/api/search", get(|| async { "ok" }))
+            .route("/api/search/source/:name", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(auth, require_api_token))
+    }
+
+    async fn status(uri: &str, token: Option<&str>) -> StatusCode {
+        let mut request = Request::get(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        app(false)
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn scoped_search_answers_need_a_token() {
+        let uri = "/api/search/source/docs?q=kernel&answer=true";
+        assert_eq!(status(uri, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(uri, Some("wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(uri, Some("secret")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn searches_without_answers_stay_open() {
+        assert_eq!(
+            status("/api/search/source/docs?q=kernel", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("/api/search/source/docs?q=kernel&answer=false", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("/api/search?q=kernel&answer=true", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+use crate::server::SearchPipeline;
+
+/// What to run for `bench`.
+#[derive(Debug, Clone)]
+pub struct BenchOptions<'a> {
+    pub index: Option<&'a str>,
+    /// Hits fetched per query; relevance metrics are measured at this cutoff.
+    pub limit: usize,
+    /// Times each query is run; every run counts towards the latencies.
+    pub runs: usize,
+}
+
+/// One line of a judgments file: how relevant `doc_id` is to `query`
+/// (`grade`, default 1; 0 marks a judged non-relevant document).
+#[derive(Debug, Deserialize)]
+struct Judgment {
+    query: String,
+    doc_id: String,
+    #[serde(default = "default_grade")]
+    grade: u32,
+}
+
+fn default_grade() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub queries: usize,
+    pub runs: usize,
+    pub latency_p50: Duration,
+    pub latency_p90: Duration,
+    pub latency_p99: Duration,
+    pub latency_max: Duration,
+    pub mean_total_hits: f64,
+    /// Queries without a single hit, in file order.
+    pub zero_hit_queries: Vec<String>,
+    /// Present when a judgments file was given.
+    pub relevance: Option<RelevanceReport>,
+}
+
+/// Relevance at the `limit` cutoff, averaged over the judged queries.
+#[derive(Debug, Clone)]
+pub struct RelevanceReport {
+    pub judged_queries: usize,
+    pub cutoff: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub mrr: f64,
+    pub ndcg: f64,
+}
+
+/// Reads one query per line from `queries_path` (blank lines and `#`
+/// comments skipped) and runs them in order through the search pipeline,
+/// timing each run.
+pub async fn run_bench(
+    config: &AppConfig,
+    queries_path: &Path,
+    judgments_path: Option<&Path>,
+    options: &BenchOptions<'_>,
+) -> Result<BenchReport> {
+    if options.limit == 0 {
+        bail!("the bench limit must be at least 1");
+    }
+    let queries = read_queries(queries_path)?;
+    if queries.is_empty() {
+        bail!("no queries in {}", queries_path.display());
+    }
+    let judgments = judgments_path.map(read_judgments).transpose()?;
+
+    let pipeline = SearchPipeline::new(config).await?;
+    let runs = options.runs.max(1);
+    let mut latencies = Vec::with_capacity(queries.len() * runs);
+    let mut results = Vec::with_capacity(queries.len());
+    for query in &queries {
+        let mut result = (0, Vec::new());
+        for _ in 0..runs {
+            let started = Instant::now();
+            result = pipeline
+                .search(query, options.index, options.limit)
+                .await
+                .with_context(|| format!("search for `{query}` failed"))?;
+            latencies.push(started.elapsed());
+        }
+        results.push(result);
+    }
+    latencies.sort();
+
+    let relevance =
+        judgments.map(|judgments| relevance_report(&queries, &results, &judgments, options.limit));
+    Ok(BenchReport {
+        queries: queries.len(),
+        runs,
+        latency_p50: percentile(&latencies, 50),
+        latency_p90: percentile(&latencies, 90),
+        latency_p99: percentile(&latencies, 99),
+        latency_max: latencies.last().copied().unwrap_or_default(),
+        mean_total_hits: results.iter().map(|(total, _)| *total as f64).sum::<f64>()
+            / queries.len() as f64,
+        zero_hit_queries: queries
+            .iter()
+            .zip(&results)
+            .filter(|(_, (total, _))| *total == 0)
+            .map(|(query, _)| query.clone())
+            .collect(),
+        relevance,
+    })
+}
+
+fn read_queries(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read queries from {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Grades by query, then doc id, from a JSON Lines judgments file.
+fn read_judgments(path: &Path) -> Result<HashMap<String, HashMap<String, u32>>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read judgments from {}", path.display()))?;
+    let mut judgments: HashMap<String, HashMap<String, u32>> = HashMap::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let judgment: Judgment = serde_json::from_str(line).with_context(|| {
+            format!("invalid judgment on line {} of {}", idx + 1, path.display())
+        })?;
+        judgments
+            .entry(judgment.query.trim().to_string())
+            .or_default()
+            .insert(judgment.doc_id, judgment.grade);
+    }
+    Ok(judgments)
+}
+
+/// Nearest-rank percentile of sorted `values`.
+fn percentile(values: &[Duration], percent: usize) -> Duration {
+    if values.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (values.len() * percent).div_ceil(100).max(1);
+    values[rank - 1]
+}
+
+/// Precision, recall, reciprocal rank and nDCG of each judged query's hits,
+/// averaged. Queries without judgments (or without a relevant document) are
+/// left out.
+fn relevance_report(
+    queries: &[String],
+    results: &[(usize, Vec<String>)],
+    judgments: &HashMap<String, HashMap<String, u32>>,
+    cutoff: usize,
+) -> RelevanceReport {
+    let mut judged = 0;
+    let (mut precision, mut recall, mut mrr, mut ndcg) = (0.0, 0.0, 0.0, 0.0);
+    let mut seen = HashSet::new();
+    for (query, (_, doc_ids)) in queries.iter().zip(results) {
+        let Some(grades) = judgments.get(query) else {
+            continue;
+        };
+        let relevant = grades.values().filter(|grade| **grade > 0).count();
+        if relevant == 0 || !seen.insert(query) {
+            continue;
+        }
+        judged += 1;
+
+        let hits: Vec<u32> = doc_ids
+            .iter()
+            .take(cutoff)
+            .map(|doc_id| grades.get(doc_id).copied().unwrap_or(0))
+            .collect();
+        let found = hits.iter().filter(|grade| **grade > 0).count();
+        precision += found as f64 / cutoff as f64;
+        recall += found as f64 / relevant as f64;
+        if let Some(rank) = hits.iter().position(|grade| *grade > 0) {
+            mrr += 1.0 / (rank + 1) as f64;
+        }
+
+        let mut ideal: Vec<u32> = grades.values().copied().collect();
+        ideal.sort_unstable_by(|a, b| b.cmp(a));
+        let ideal_dcg = dcg(&ideal[..ideal.len().min(cutoff)]);
+        ndcg += dcg(&hits) / ideal_dcg;
+    }
+
+    let average = |sum: f64| {
+        if judged == 0 {
+            0.0
+        } else {
+            sum / judged as f64
+        }
+    };
+    RelevanceReport {
+        judged_queries: judged,
+        cutoff,
+        precision: average(precision),
+        recall: average(recall),
+        mrr: average(mrr),
+        ndcg: average(ndcg),
+    }
+}
+
+/// Discounted cumulative gain with exponential gain, `2^grade - 1`.
+fn dcg(grades: &[u32]) -> f64 {
+    grades
+        .iter()
+        .enumerate()
+        .map(|(rank, grade)| (2f64.powi(*grade as i32) - 1.0) / (rank as f64 + 2.0).log2())
+        .sum()
+}
@@ -0,0 +1,114 @@
+use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+
+/// Splits Chinese, Japanese and Korean text, which has no spaces between
+/// words, into overlapping character bigrams: `東京都` becomes `東京`,
+/// `京都`, so a query matches any run of two or more characters. A lone
+/// CJK character is kept as a unigram; other text is split into words of
+/// letters and digits like tantivy's default tokenizer.
+#[derive(Clone, Default)]
+pub struct CjkBigramTokenizer {
+    tokens: Vec<Token>,
+}
+
+pub struct CjkBigramTokenStream<'a> {
+    tokens: &'a mut [Token],
+    next: usize,
+}
+
+impl Tokenizer for CjkBigramTokenizer {
+    type TokenStream<'a> = CjkBigramTokenStream<'a>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CjkBigramTokenStream<'a> {
+        self.tokens.clear();
+
+        for (start, run, cjk) in runs(text) {
+            if !cjk {
+                let position = self.tokens.len();
+                self.tokens.push(token(start, run, position));
+                continue;
+            }
+
+            let chars: Vec<(usize, char)> = run.char_indices().collect();
+            if chars.len() == 1 {
+                let position = self.tokens.len();
+                self.tokens.push(token(start, run, position));
+                continue;
+            }
+            for pair in chars.windows(2) {
+                let (offset, _) = pair[0];
+                let (second, ch) = pair[1];
+                let end = second + ch.len_utf8();
+                let position = self.tokens.len();
+                self.tokens
+                    .push(token(start + offset, &run[offset..end], position));
+            }
+        }
+
+        CjkBigramTokenStream {
+            tokens: &mut self.tokens,
+            next: 0,
+        }
+    }
+}
+
+impl TokenStream for CjkBigramTokenStream<'_> {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+fn token(start: usize, text: &str, position: usize) -> Token {
+    Token {
+        offset_from: start,
+        offset_to: start + text.len(),
+        position,
+        text: text.to_string(),
+        position_length: 1,
+    }
+}
+
+/// Han ideographs, kana and Hangul.
+fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{1100}'..='\u{11FF}'
+            | '\u{3040}'..='\u{30FF}'
+            | '\u{3130}'..='\u{318F}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF66}'..='\u{FF9F}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Runs of CJK characters and words of other letters and digits, with
+/// their byte offsets and whether they are CJK; everything else separates
+/// them.
+fn runs(text: &str) -> impl Iterator<Item = (usize, &str, bool)> {
+    let mut rest = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, first) = rest.find(|(_, ch)| ch.is_alphanumeric())?;
+        let cjk = is_cjk(first);
+        let mut end = text.len();
+        while let Some(&(index, ch)) = rest.peek() {
+            if !ch.is_alphanumeric() || is_cjk(ch) != cjk {
+                end = index;
+                break;
+            }
+            rest.next();
+        }
+        Some((start, &text[start..end], cjk))
+    })
+}
@@ -0,0 +1,126 @@
+use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+
+/// Splits source code into identifiers (runs of letters, digits and `_`).
+/// Each identifier is emitted whole, followed by its camelCase/snake_case
+/// parts at the same position, so `parse_http_response` and `parseHttp`
+/// match both as a whole and by `http`.
+#[derive(Clone, Default)]
+pub struct CodeTokenizer {
+    tokens: Vec<Token>,
+}
+
+pub struct CodeTokenStream<'a> {
+    tokens: &'a mut [Token],
+    next: usize,
+}
+
+impl Tokenizer for CodeTokenizer {
+    type TokenStream<'a> = CodeTokenStream<'a>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CodeTokenStream<'a> {
+        self.tokens.clear();
+
+        for (position, (start, identifier)) in identifiers(text).enumerate() {
+            self.tokens.push(token(start, identifier, position));
+            let parts = identifier_parts(identifier);
+            if parts.len() > 1 {
+                for (offset, part) in parts {
+                    self.tokens.push(token(start + offset, part, position));
+                }
+            }
+        }
+
+        CodeTokenStream {
+            tokens: &mut self.tokens,
+            next: 0,
+        }
+    }
+}
+
+impl TokenStream for CodeTokenStream<'_> {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+fn token(start: usize, text: &str, position: usize) -> Token {
+    Token {
+        offset_from: start,
+        offset_to: start + text.len(),
+        position,
+        text: text.to_string(),
+        position_length: 1,
+    }
+}
+
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Identifiers of `text` with their byte offsets; lone underscores are
+/// dropped.
+fn identifiers(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut rest = text.char_indices().peekable();
+    std::iter::from_fn(move || loop {
+        let (start, _) = rest.find(|(_, ch)| is_identifier_char(*ch))?;
+        let mut end = text.len();
+        while let Some(&(index, ch)) = rest.peek() {
+            if !is_identifier_char(ch) {
+                end = index;
+                break;
+            }
+            rest.next();
+        }
+        let identifier = &text[start..end];
+        if identifier.chars().any(|ch| ch != '_') {
+            return Some((start, identifier));
+        }
+    })
+}
+
+/// Splits an identifier at underscores and case changes: `HTTPResponse`
+/// becomes `HTTP`, `Response`; digits stay with the letters before them.
+fn identifier_parts(identifier: &str) -> Vec<(usize, &str)> {
+    let chars: Vec<(usize, char)> = identifier.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+
+    for (i, &(offset, ch)) in chars.iter().enumerate() {
+        if ch == '_' {
+            if let Some(part_start) = start.take() {
+                parts.push((part_start, &identifier[part_start..offset]));
+            }
+            continue;
+        }
+
+        let Some(part_start) = start else {
+            start = Some(offset);
+            continue;
+        };
+
+        let prev = chars[i - 1].1;
+        let next = chars.get(i + 1).map(|&(_, next)| next);
+        let boundary = ch.is_uppercase()
+            && (prev.is_lowercase()
+                || prev.is_numeric()
+                || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+        if boundary {
+            parts.push((part_start, &identifier[part_start..offset]));
+            start = Some(offset);
+        }
+    }
+
+    if let Some(part_start) = start {
+        parts.push((part_start, &identifier[part_start..]));
+    }
+    parts
+}
@@ -1,9 +1,14 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
 
+/// Name of the index built from the top-level `index_dir` and `sources`.
+pub const DEFAULT_INDEX: &str = "default";
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     #[serde(default = "default_index_dir")]
@@ -15,21 +20,231 @@ pub struct AppConfig {
     #[serde(default)]
     pub cors_allowed_origins: Vec<String>,
 
+    /// Request headers browsers may send; empty allows any.
+    #[serde(default)]
+    pub cors_allowed_headers: Vec<String>,
+
+    #[serde(default)]
+    pub cors_allow_credentials: bool,
+
+    /// Origins allowed on the answer routes instead of
+    /// `cors_allowed_origins`; empty allows none.
+    #[serde(default)]
+    pub answer_allowed_origins: Option<Vec<String>>,
+
     #[serde(default = "default_result_limit")]
     pub default_result_limit: usize,
 
     #[serde(default = "default_max_result_limit")]
     pub max_result_limit: usize,
 
+    /// Default `min_score` of searches: local hits scoring below it after
+    /// reranking are dropped (0 keeps every hit).
+    #[serde(default)]
+    pub min_score: f32,
+
+    /// Default `min_score_ratio` of searches: hits scoring below this
+    /// fraction of the top hit's score are dropped (0 keeps every hit).
+    #[serde(default)]
+    pub min_score_ratio: f32,
+
+    /// Default `rerank` of searches: whether title, coverage and per-source
+    /// boosts and penalties are added to the index scores of hits.
+    #[serde(default = "default_rerank")]
+    pub rerank: bool,
+
+    /// Source names (`kiwix` for every Kiwix collection not listed as
+    /// `kiwix:<id>`) whose hits go first among hits of equal score, in
+    /// order; unlisted sources come after them.
+    #[serde(default)]
+    pub source_priority: Vec<String>,
+
     #[serde(default = "default_max_indexed_chars")]
     pub max_indexed_chars: usize,
 
+    /// Filesystem files larger than this are skipped instead of read.
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+
+    /// Filesystem extensions read as text even when their content sniffs as
+    /// binary (e.g. BOM-less UTF-16 exports).
+    #[serde(default)]
+    pub always_text_extensions: Vec<String>,
+
+    /// Highlighted fragments joined into each local hit's snippet.
+    #[serde(default = "default_snippet_fragments")]
+    pub snippet_fragments: usize,
+
+    /// Length of each highlighted snippet fragment (formerly
+    /// `max_snippet_chars`).
+    #[serde(
+        default = "default_snippet_fragment_chars",
+        alias = "max_snippet_chars"
+    )]
+    pub snippet_fragment_chars: usize,
+
+    /// Length of hit previews, cut from the stored body at query time
+    /// (0 uses the 280-character preview stored at index time).
+    #[serde(default)]
+    pub preview_chars: usize,
+
     #[serde(default = "default_writer_memory_bytes")]
     pub writer_memory_bytes: usize,
 
+    /// Size cap of the index directory in bytes; indexing evicts the
+    /// longest-unchanged documents to stay under it (0 means no cap).
+    #[serde(default)]
+    pub max_index_bytes: u64,
+
+    /// Compression of stored fields in newly created indexes.
+    #[serde(default)]
+    pub stored_compression: StoredCompression,
+
+    /// zstd level (1 to 22) for `stored_compression = "zstd"`; zstd's own
+    /// default (3) when unset.
+    #[serde(default)]
+    pub stored_compression_level: Option<i32>,
+
+    /// Checksum every index file when the server opens an index, so a
+    /// damaged index fails at startup rather than mid-query.
+    #[serde(default = "default_verify_index_checksums")]
+    pub verify_index_checksums: bool,
+
+    /// Detected languages less certain than this (0 to 1) are tagged `und`.
+    #[serde(default = "default_lang_min_confidence")]
+    pub lang_min_confidence: f64,
+
+    #[serde(default = "default_ingest_threads")]
+    pub ingest_threads: usize,
+
+    /// Abort indexing on the first unreadable file or invalid record instead
+    /// of logging a warning and skipping it. Also set by `index --strict`.
+    #[serde(default)]
+    pub strict_ingest: bool,
+
+    #[serde(default = "default_watch_debounce_ms")]
+    pub watch_debounce_ms: u64,
+
+    #[serde(default = "default_query_timeout_ms")]
+    pub query_timeout_ms: u64,
+
+    #[serde(default)]
+    pub metrics_enabled: bool,
+
+    /// JSON Lines file each `/api/search` request is appended to; unset
+    /// (the default) logs no queries.
+    #[serde(default)]
+    pub query_log: Option<PathBuf>,
+
+    /// Log line format on stderr; `--log-json` forces `json`.
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    /// Serve a Swagger UI for the OpenAPI spec at `/api/docs`.
+    #[serde(default)]
+    pub api_docs_enabled: bool,
+
+    /// When set, answer requests and non-GET routes need
+    /// `Authorization: Bearer <api_token>`.
+    #[serde(default)]
+    pub api_token: Option<String>,
+
+    /// Also require the token for plain search endpoints.
+    #[serde(default)]
+    pub require_auth_for_search: bool,
+
+    /// Sustained requests per client IP per minute on `/api/` routes
+    /// (0 disables rate limiting).
+    #[serde(default)]
+    pub rate_limit_requests_per_minute: u32,
+
+    /// Requests a client may make in a burst before being limited.
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+
+    /// How many requests an Ollama answer counts as.
+    #[serde(default = "default_rate_limit_answer_cost")]
+    pub rate_limit_answer_cost: u32,
+
+    /// Take the client IP from `X-Forwarded-For` (set by a reverse proxy).
+    #[serde(default)]
+    pub trusted_proxy: bool,
+
+    #[serde(default)]
+    pub cache_enabled: bool,
+
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+
+    #[serde(default = "default_cache_max_entries")]
+    pub cache_max_entries: usize,
+
+    /// `Cache-Control` max-age of `/api/search` and `/api/sources`
+    /// responses; 0 makes browsers and proxies revalidate each time.
+    #[serde(default = "default_http_cache_max_age_secs")]
+    pub http_cache_max_age_secs: u64,
+
+    /// Offer a did-you-mean suggestion when a search has fewer hits than
+    /// this (0 disables it).
+    #[serde(default = "default_did_you_mean_below_hits")]
+    pub did_you_mean_below_hits: usize,
+
+    /// Longest pattern accepted by `regex=true` searches.
+    #[serde(default = "default_regex_max_pattern_chars")]
+    pub regex_max_pattern_chars: usize,
+
+    /// Above this many documents, `regex=true` patterns must start with a
+    /// literal so the term dictionary scan stays bounded.
+    #[serde(default = "default_regex_prefix_required_above_docs")]
+    pub regex_prefix_required_above_docs: u64,
+
+    /// Largest edit distance of `fuzzy=true` alternatives (1 or 2); short
+    /// terms only get one edit.
+    #[serde(default = "default_fuzzy_max_distance")]
+    pub fuzzy_max_distance: u8,
+
+    /// Terms shorter than this are not fuzzed by `fuzzy=true`.
+    #[serde(default = "default_fuzzy_min_term_chars")]
+    pub fuzzy_min_term_chars: usize,
+
+    #[serde(default)]
+    pub field_boosts: FieldBoosts,
+
+    /// How HTML pages, posts, chapters and mails become indexed text.
+    #[serde(default)]
+    pub html: HtmlConfig,
+
+    #[serde(default = "default_bm25_k1")]
+    pub bm25_k1: f32,
+
+    #[serde(default = "default_bm25_b")]
+    pub bm25_b: f32,
+
+    /// Default stemming language for sources that don't set their own.
+    #[serde(default)]
+    pub language: TextLanguage,
+
+    /// Words dropped from `title`/`body` when indexing and querying. A file
+    /// path is read once at load, so after `from_file` this is always a list.
+    #[serde(default)]
+    pub stop_words: StopWords,
+
+    /// Groups of interchangeable words searched for each other at query
+    /// time. A file path is read once at load, like `stop_words`.
+    #[serde(default)]
+    pub synonyms: Synonyms,
+
+    /// Boost of synonym expansions relative to the word typed (1.0).
+    #[serde(default = "default_synonym_boost")]
+    pub synonym_boost: f32,
+
     #[serde(default)]
     pub sources: Vec<SourceConfig>,
 
+    /// Additional named indexes, each with its own directory and sources.
+    #[serde(default)]
+    pub indexes: BTreeMap<String, IndexConfig>,
+
     #[serde(default)]
     pub kiwix: Option<KiwixConfig>,
 
@@ -38,12 +253,52 @@ pub struct AppConfig {
 }
 
 impl AppConfig {
+    /// Loads the TOML config at `path` with `BUNKER_*` environment
+    /// variables layered on top (see `env_overrides`). Without any such
+    /// variable the file must exist; with them it may be missing.
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
-        let raw = fs::read_to_string(path)
-            .with_context(|| format!("failed to read config at {}", path.display()))?;
-        let mut cfg: AppConfig = toml::from_str(&raw)
+        let overrides = env_overrides(std::env::vars())?;
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !overrides.is_empty() => {
+                String::new()
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read config at {}", path.display()))
+            }
+        };
+        let mut table: toml::Table = toml::from_str(&raw)
             .with_context(|| format!("failed to parse TOML config at {}", path.display()))?;
+        let includes = include_paths(&mut table, path)?;
+        let mut cfg: AppConfig = if overrides.is_empty() && includes.is_empty() {
+            toml::from_str(&raw)
+                .with_context(|| format!("failed to parse TOML config at {}", path.display()))?
+        } else {
+            for include in &includes {
+                let raw = fs::read_to_string(include).with_context(|| {
+                    format!("failed to read included config at {}", include.display())
+                })?;
+                let included: toml::Table = toml::from_str(&raw).with_context(|| {
+                    format!("failed to parse TOML config at {}", include.display())
+                })?;
+                if included.contains_key(INCLUDE_KEY) {
+                    bail!(
+                        "{} has its own `{INCLUDE_KEY}`; only the main config can include files",
+                        include.display()
+                    );
+                }
+                merge_config_tables(&mut table, included);
+            }
+            apply_env_overrides(&mut table, overrides)?;
+            toml::Value::Table(table).try_into().with_context(|| {
+                format!(
+                    "invalid config after applying includes and BUNKER_* environment variables to {}",
+                    path.display()
+                )
+            })?
+        };
 
         if cfg.default_result_limit == 0 {
             cfg.default_result_limit = default_result_limit();
@@ -51,12 +306,103 @@ impl AppConfig {
         if cfg.max_result_limit == 0 {
             cfg.max_result_limit = default_max_result_limit();
         }
+        if !cfg.min_score.is_finite() || cfg.min_score < 0.0 {
+            cfg.min_score = 0.0;
+        }
+        if !(0.0..=1.0).contains(&cfg.min_score_ratio) {
+            cfg.min_score_ratio = 0.0;
+        }
+        if cfg
+            .stored_compression_level
+            .is_some_and(|level| !(1..=22).contains(&level))
+        {
+            cfg.stored_compression_level = None;
+        }
         if cfg.max_indexed_chars == 0 {
             cfg.max_indexed_chars = default_max_indexed_chars();
         }
+        if cfg.max_file_bytes == 0 {
+            cfg.max_file_bytes = default_max_file_bytes();
+        }
+        cfg.always_text_extensions = cfg
+            .always_text_extensions
+            .iter()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .collect();
+        if cfg.snippet_fragments == 0 {
+            cfg.snippet_fragments = default_snippet_fragments();
+        }
+        if cfg.snippet_fragment_chars == 0 {
+            cfg.snippet_fragment_chars = default_snippet_fragment_chars();
+        }
+        if !(0.0..=1.0).contains(&cfg.lang_min_confidence) {
+            cfg.lang_min_confidence = default_lang_min_confidence();
+        }
         if cfg.writer_memory_bytes < 50_000_000 {
             cfg.writer_memory_bytes = default_writer_memory_bytes();
         }
+        if cfg.ingest_threads == 0 {
+            cfg.ingest_threads = default_ingest_threads();
+        }
+        if cfg.watch_debounce_ms == 0 {
+            cfg.watch_debounce_ms = default_watch_debounce_ms();
+        }
+        if cfg.query_timeout_ms == 0 {
+            cfg.query_timeout_ms = default_query_timeout_ms();
+        }
+        if cfg.cache_ttl_secs == 0 {
+            cfg.cache_ttl_secs = default_cache_ttl_secs();
+        }
+        if cfg.regex_max_pattern_chars == 0 {
+            cfg.regex_max_pattern_chars = default_regex_max_pattern_chars();
+        }
+        if cfg.fuzzy_max_distance == 0 {
+            cfg.fuzzy_max_distance = default_fuzzy_max_distance();
+        }
+        cfg.fuzzy_max_distance = cfg.fuzzy_max_distance.min(2);
+        if cfg.rate_limit_burst == 0 {
+            cfg.rate_limit_burst = default_rate_limit_burst();
+        }
+        if cfg.rate_limit_answer_cost == 0 {
+            cfg.rate_limit_answer_cost = default_rate_limit_answer_cost();
+        }
+        if cfg.cache_max_entries == 0 {
+            cfg.cache_max_entries = default_cache_max_entries();
+        }
+        if cfg.html.wrap_width == 0 {
+            cfg.html.wrap_width = default_html_wrap_width();
+        }
+        if !(cfg.field_boosts.title.is_finite() && cfg.field_boosts.title > 0.0) {
+            cfg.field_boosts.title = default_field_boost();
+        }
+        if !(cfg.field_boosts.body.is_finite() && cfg.field_boosts.body > 0.0) {
+            cfg.field_boosts.body = default_field_boost();
+        }
+        if !(cfg.bm25_k1.is_finite() && cfg.bm25_k1 >= 0.0) {
+            cfg.bm25_k1 = default_bm25_k1();
+        }
+        if !cfg.bm25_b.is_finite() {
+            cfg.bm25_b = default_bm25_b();
+        }
+        cfg.bm25_b = cfg.bm25_b.clamp(0.0, 1.0);
+        cfg.stop_words = StopWords::List(cfg.stop_words.load()?);
+        cfg.synonyms = Synonyms::Groups(cfg.synonyms.load()?);
+        if !(cfg.synonym_boost.is_finite() && cfg.synonym_boost > 0.0) {
+            cfg.synonym_boost = default_synonym_boost();
+        }
+        for source in cfg.sources.iter_mut().chain(
+            cfg.indexes
+                .values_mut()
+                .flat_map(|index| &mut index.sources),
+        ) {
+            let ranking = source.ranking_mut();
+            if !(ranking.weight.is_finite() && ranking.weight > 0.0) {
+                ranking.weight = default_source_weight();
+            }
+            ranking.recency_half_life_days = ranking
+                .recency_half_life_days
+                .filter(|days| days.is_finite() && *days > 0.0);
+        }
         if let Some(kiwix) = cfg.kiwix.as_mut() {
             if kiwix.max_hits_per_collection == 0 {
                 kiwix.max_hits_per_collection = default_kiwix_max_hits_per_collection();
@@ -64,6 +410,12 @@ impl AppConfig {
             if kiwix.timeout_secs == 0 {
                 kiwix.timeout_secs = default_kiwix_timeout_secs();
             }
+            if kiwix.max_concurrent_requests == 0 {
+                kiwix.max_concurrent_requests = default_kiwix_max_concurrent_requests();
+            }
+            if kiwix.fetch_snippets_timeout_ms == 0 {
+                kiwix.fetch_snippets_timeout_ms = default_kiwix_fetch_snippets_timeout_ms();
+            }
         }
         if let Some(ollama) = cfg.ollama.as_mut() {
             if ollama.timeout_secs == 0 {
@@ -75,10 +427,336 @@ impl AppConfig {
             if ollama.max_context_chars == 0 {
                 ollama.max_context_chars = default_ollama_max_context_chars();
             }
+            if let Some(template) = &ollama.answer_prompt_template {
+                for placeholder in ["{query}", "{context}"] {
+                    if !template.contains(placeholder) {
+                        bail!("ollama.answer_prompt_template must contain {placeholder}");
+                    }
+                }
+            }
+        }
+
+        cfg.api_token = cfg
+            .api_token
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+        if cfg.require_auth_for_search && cfg.api_token.is_none() {
+            bail!("require_auth_for_search needs api_token to be set");
+        }
+
+        if cfg.indexes.contains_key(DEFAULT_INDEX) {
+            bail!(
+                "index name `{DEFAULT_INDEX}` is reserved for the top-level index_dir and sources"
+            );
+        }
+        let mut index_dirs = BTreeSet::new();
+        index_dirs.insert(cfg.index_dir.clone());
+        for (name, index) in &cfg.indexes {
+            if !index_dirs.insert(index.index_dir.clone()) {
+                bail!(
+                    "index `{name}` reuses index_dir {}; every index needs its own directory",
+                    index.index_dir.display()
+                );
+            }
         }
 
         Ok(cfg)
     }
+
+    /// Every index as a config of its own: the default index (top-level
+    /// `index_dir` and `sources`) first, then the named ones. The default
+    /// index is left out when it has no sources but named indexes exist.
+    pub fn index_configs(&self) -> Vec<(String, AppConfig)> {
+        let mut configs = Vec::with_capacity(self.indexes.len() + 1);
+        if !self.sources.is_empty() || self.indexes.is_empty() {
+            let mut default = self.clone();
+            default.indexes.clear();
+            configs.push((DEFAULT_INDEX.to_string(), default));
+        }
+        for (name, index) in &self.indexes {
+            let mut named = self.clone();
+            named.index_dir = index.index_dir.clone();
+            named.sources = index.sources.clone();
+            named.indexes.clear();
+            configs.push((name.clone(), named));
+        }
+        configs
+    }
+
+    /// The stemming language applied to documents of the named source.
+    pub fn source_language(&self, source_name: &str) -> TextLanguage {
+        self.sources
+            .iter()
+            .find(|source| source.name() == source_name)
+            .and_then(SourceConfig::language)
+            .unwrap_or(self.language)
+    }
+
+    /// Whether the named source is indexed with the code tokenizer.
+    pub fn source_is_code(&self, source_name: &str) -> bool {
+        self.sources
+            .iter()
+            .any(|source| source.name() == source_name && source.is_code())
+    }
+
+    /// Whether any source needs the code-analyzed title/body fields.
+    pub fn has_code_sources(&self) -> bool {
+        self.sources.iter().any(SourceConfig::is_code)
+    }
+
+    /// Every stemming language in use; each gets its own analyzed fields.
+    pub fn stemmed_languages(&self) -> BTreeSet<TextLanguage> {
+        self.sources
+            .iter()
+            .map(|source| source.language().unwrap_or(self.language))
+            .filter(|language| *language != TextLanguage::None)
+            .collect()
+    }
+}
+
+/// Stop words given inline or as a file with one word per line (blank lines
+/// and `#` comments are skipped).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum StopWords {
+    List(Vec<String>),
+    File(PathBuf),
+}
+
+impl Default for StopWords {
+    fn default() -> Self {
+        StopWords::List(Vec::new())
+    }
+}
+
+impl StopWords {
+    /// The lowercased, deduplicated words; empty when none are configured.
+    pub fn words(&self) -> &[String] {
+        match self {
+            StopWords::List(words) => words,
+            StopWords::File(_) => &[],
+        }
+    }
+
+    fn load(&self) -> Result<Vec<String>> {
+        let raw = match self {
+            StopWords::List(words) => words.clone(),
+            StopWords::File(path) => fs::read_to_string(path)
+                .with_context(|| format!("failed to read stop_words file {}", path.display()))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+        };
+
+        let words: BTreeSet<String> = raw
+            .iter()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Ok(words.into_iter().collect())
+    }
+}
+
+/// Synonym groups given inline or as a file with one comma-separated group
+/// per line (blank lines and `#` comments are skipped).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Synonyms {
+    Groups(Vec<Vec<String>>),
+    File(PathBuf),
+}
+
+impl Default for Synonyms {
+    fn default() -> Self {
+        Synonyms::Groups(Vec::new())
+    }
+}
+
+impl Synonyms {
+    /// The lowercased groups of two or more distinct entries.
+    pub fn groups(&self) -> &[Vec<String>] {
+        match self {
+            Synonyms::Groups(groups) => groups,
+            Synonyms::File(_) => &[],
+        }
+    }
+
+    fn load(&self) -> Result<Vec<Vec<String>>> {
+        let raw = match self {
+            Synonyms::Groups(groups) => groups.clone(),
+            Synonyms::File(path) => fs::read_to_string(path)
+                .with_context(|| format!("failed to read synonyms file {}", path.display()))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.split(',').map(str::to_string).collect())
+                .collect(),
+        };
+
+        Ok(raw
+            .iter()
+            .map(|group| {
+                let entries: BTreeSet<String> = group
+                    .iter()
+                    .map(|entry| entry.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .map(|entry| entry.to_lowercase())
+                    .filter(|entry| !entry.is_empty())
+                    .collect();
+                entries.into_iter().collect::<Vec<_>>()
+            })
+            .filter(|group| group.len() > 1)
+            .collect())
+    }
+}
+
+/// Language used to stem and stop-word filter a source's text, in addition
+/// to the plain (unstemmed) index every document gets. `Cjk` instead splits
+/// Chinese, Japanese and Korean text into character bigrams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextLanguage {
+    #[default]
+    None,
+    Arabic,
+    Cjk,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl TextLanguage {
+    pub fn name(self) -> &'static str {
+        match self {
+            TextLanguage::None => "none",
+            TextLanguage::Arabic => "arabic",
+            TextLanguage::Cjk => "cjk",
+            TextLanguage::Danish => "danish",
+            TextLanguage::Dutch => "dutch",
+            TextLanguage::English => "english",
+            TextLanguage::Finnish => "finnish",
+            TextLanguage::French => "french",
+            TextLanguage::German => "german",
+            TextLanguage::Greek => "greek",
+            TextLanguage::Hungarian => "hungarian",
+            TextLanguage::Italian => "italian",
+            TextLanguage::Norwegian => "norwegian",
+            TextLanguage::Portuguese => "portuguese",
+            TextLanguage::Romanian => "romanian",
+            TextLanguage::Russian => "russian",
+            TextLanguage::Spanish => "spanish",
+            TextLanguage::Swedish => "swedish",
+            TextLanguage::Tamil => "tamil",
+            TextLanguage::Turkish => "turkish",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexConfig {
+    pub index_dir: PathBuf,
+
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct FieldBoosts {
+    #[serde(default = "default_field_boost")]
+    pub title: f32,
+
+    #[serde(default = "default_field_boost")]
+    pub body: f32,
+}
+
+impl Default for FieldBoosts {
+    fn default() -> Self {
+        Self {
+            title: default_field_boost(),
+            body: default_field_boost(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct HtmlConfig {
+    /// Column at which converted text is wrapped.
+    #[serde(default = "default_html_wrap_width")]
+    pub wrap_width: usize,
+
+    #[serde(default)]
+    pub links: HtmlLinks,
+}
+
+impl Default for HtmlConfig {
+    fn default() -> Self {
+        Self {
+            wrap_width: default_html_wrap_width(),
+            links: HtmlLinks::default(),
+        }
+    }
+}
+
+/// Codec of the document store (stored titles, bodies, previews, ...).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoredCompression {
+    /// Tantivy's default: fast, moderate ratio.
+    #[default]
+    Lz4,
+    /// Smaller stores at more CPU per indexed and fetched document.
+    Zstd,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human-readable line per event.
+    #[default]
+    Compact,
+    /// One JSON object per event, with the event's fields as keys.
+    Json,
+}
+
+/// Where filesystem documents get their titles. HTML always uses `<title>`,
+/// and every rule falls back to the file name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleFrom {
+    /// The first heading of Markdown files.
+    #[default]
+    Auto,
+    /// As `auto`, plus the first non-empty line of other text files.
+    FirstLine,
+    /// Always the de-slugified file name.
+    Filename,
+}
+
+/// What HTML-to-text conversion does with links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HtmlLinks {
+    /// `[text][1]` markers in the text and a `[1]: url` list at the end.
+    #[default]
+    Keep,
+    /// Only the link text, without the markdown-style `*`/`#`/`>` markup.
+    Strip,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -88,14 +766,37 @@ pub enum SourceConfig {
         name: String,
         path: PathBuf,
         #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+        #[serde(default)]
         extensions: Vec<String>,
         #[serde(default)]
         follow_symlinks: bool,
+        /// Gitignore-style globs of files and directories to skip.
+        #[serde(default)]
+        ignore: Vec<String>,
+        /// Honor `.gitignore`/`.ignore` files and skip `.git` directories.
+        #[serde(default)]
+        respect_gitignore: bool,
+        /// Gitignore-style globs of files to count as skipped rather than
+        /// index.
+        #[serde(default)]
+        exclude: Vec<String>,
+        /// Also index identifiers split at camelCase/snake_case boundaries.
+        #[serde(default)]
+        code: bool,
+        #[serde(default)]
+        title_from: TitleFrom,
     },
     Jsonl {
         name: String,
         path: PathBuf,
         #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+        #[serde(default)]
         id_field: Option<String>,
         #[serde(default)]
         title_field: Option<String>,
@@ -103,11 +804,267 @@ pub enum SourceConfig {
         body_field: Option<String>,
         #[serde(default)]
         url_field: Option<String>,
+        #[serde(default)]
+        date_field: Option<String>,
+        #[serde(default)]
+        tags_field: Option<String>,
+        #[serde(default)]
+        append_only: bool,
+        #[serde(default)]
+        exclude: Vec<FieldExclude>,
     },
     StackExchangeXml {
         name: String,
         path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+    },
+    Pdf {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
     },
+    Epub {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+    },
+    Mbox {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+    },
+    Office {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+    },
+    Subtitles {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+        /// Emit one document per cue instead of one per file.
+        #[serde(default)]
+        per_cue: bool,
+    },
+    HttpJsonl {
+        name: String,
+        url: String,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+        #[serde(default)]
+        id_field: Option<String>,
+        #[serde(default)]
+        title_field: Option<String>,
+        #[serde(default)]
+        body_field: Option<String>,
+        #[serde(default)]
+        url_field: Option<String>,
+        #[serde(default)]
+        date_field: Option<String>,
+        #[serde(default)]
+        tags_field: Option<String>,
+        #[serde(default)]
+        exclude: Vec<FieldExclude>,
+    },
+    Csv {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+        #[serde(default)]
+        id_column: Option<String>,
+        #[serde(default)]
+        title_column: Option<String>,
+        #[serde(default)]
+        body_columns: Vec<String>,
+        /// Field separator; defaults to tab for `.tsv` files, comma otherwise.
+        #[serde(default)]
+        delimiter: Option<char>,
+    },
+    Sqlite {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+        /// `SELECT` whose rows become documents.
+        query: String,
+        #[serde(default)]
+        id_column: Option<String>,
+        #[serde(default)]
+        title_column: Option<String>,
+        #[serde(default)]
+        body_column: Option<String>,
+    },
+    Feed {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<TextLanguage>,
+        #[serde(flatten)]
+        ranking: SourceRanking,
+    },
+}
+
+/// A JSONL record to skip: one whose `field` holds exactly `value`
+/// (numbers and booleans are compared as written).
+#[derive(Debug, Clone, Deserialize)]
+pub struct FieldExclude {
+    pub field: String,
+    pub value: String,
+}
+
+/// How a source's hits are weighed against other sources when results are
+/// merged; shared by every source type.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceRanking {
+    /// Multiplies the score of this source's hits before reranking.
+    #[serde(default = "default_source_weight")]
+    pub weight: f32,
+    /// Most hits of this source on one result page (0 = no cap).
+    #[serde(default)]
+    pub max_results: usize,
+    /// Corpus-specific reranking rules; unset means
+    /// [`RerankRule::defaults_for`] the source name.
+    #[serde(default)]
+    pub rerank_rules: Option<Vec<RerankRule>>,
+    /// Age in days at which a dated hit's reranked score is halved; unset
+    /// leaves scores independent of age.
+    #[serde(default)]
+    pub recency_half_life_days: Option<f32>,
+}
+
+impl Default for SourceRanking {
+    fn default() -> Self {
+        Self {
+            weight: default_source_weight(),
+            max_results: 0,
+            rerank_rules: None,
+            recency_half_life_days: None,
+        }
+    }
+}
+
+/// Named reranking rules a source can opt into, on top of the title and
+/// coverage boosts every hit gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerankRule {
+    /// Project Gutenberg books: extra title-coverage boosts, whole-book pages
+    /// over chapter splits and other editions.
+    Gutenberg,
+    /// Cover pages rank below the page they belong to, unless the query asks
+    /// for a cover.
+    Cover,
+}
+
+impl RerankRule {
+    /// Rules of a source that doesn't configure `rerank_rules`: `cover`, plus
+    /// `gutenberg` when the name contains "gutenberg".
+    pub fn defaults_for(source: &str) -> Vec<Self> {
+        let mut rules = vec![RerankRule::Cover];
+        if source.to_lowercase().contains("gutenberg") {
+            rules.push(RerankRule::Gutenberg);
+        }
+        rules
+    }
+}
+
+impl SourceConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            SourceConfig::Filesystem { name, .. }
+            | SourceConfig::Jsonl { name, .. }
+            | SourceConfig::StackExchangeXml { name, .. }
+            | SourceConfig::Pdf { name, .. }
+            | SourceConfig::Epub { name, .. }
+            | SourceConfig::Mbox { name, .. }
+            | SourceConfig::Office { name, .. }
+            | SourceConfig::Subtitles { name, .. }
+            | SourceConfig::HttpJsonl { name, .. }
+            | SourceConfig::Csv { name, .. }
+            | SourceConfig::Sqlite { name, .. }
+            | SourceConfig::Feed { name, .. } => name,
+        }
+    }
+
+    pub fn ranking(&self) -> &SourceRanking {
+        match self {
+            SourceConfig::Filesystem { ranking, .. }
+            | SourceConfig::Jsonl { ranking, .. }
+            | SourceConfig::StackExchangeXml { ranking, .. }
+            | SourceConfig::Pdf { ranking, .. }
+            | SourceConfig::Epub { ranking, .. }
+            | SourceConfig::Mbox { ranking, .. }
+            | SourceConfig::Office { ranking, .. }
+            | SourceConfig::Subtitles { ranking, .. }
+            | SourceConfig::HttpJsonl { ranking, .. }
+            | SourceConfig::Csv { ranking, .. }
+            | SourceConfig::Sqlite { ranking, .. }
+            | SourceConfig::Feed { ranking, .. } => ranking,
+        }
+    }
+
+    fn ranking_mut(&mut self) -> &mut SourceRanking {
+        match self {
+            SourceConfig::Filesystem { ranking, .. }
+            | SourceConfig::Jsonl { ranking, .. }
+            | SourceConfig::StackExchangeXml { ranking, .. }
+            | SourceConfig::Pdf { ranking, .. }
+            | SourceConfig::Epub { ranking, .. }
+            | SourceConfig::Mbox { ranking, .. }
+            | SourceConfig::Office { ranking, .. }
+            | SourceConfig::Subtitles { ranking, .. }
+            | SourceConfig::HttpJsonl { ranking, .. }
+            | SourceConfig::Csv { ranking, .. }
+            | SourceConfig::Sqlite { ranking, .. }
+            | SourceConfig::Feed { ranking, .. } => ranking,
+        }
+    }
+
+    pub fn is_code(&self) -> bool {
+        matches!(self, SourceConfig::Filesystem { code: true, .. })
+    }
+
+    pub fn language(&self) -> Option<TextLanguage> {
+        match self {
+            SourceConfig::Filesystem { language, .. }
+            | SourceConfig::Jsonl { language, .. }
+            | SourceConfig::StackExchangeXml { language, .. }
+            | SourceConfig::Pdf { language, .. }
+            | SourceConfig::Epub { language, .. }
+            | SourceConfig::Mbox { language, .. }
+            | SourceConfig::Office { language, .. }
+            | SourceConfig::Subtitles { language, .. }
+            | SourceConfig::HttpJsonl { language, .. }
+            | SourceConfig::Csv { language, .. }
+            | SourceConfig::Sqlite { language, .. }
+            | SourceConfig::Feed { language, .. } => *language,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -128,6 +1085,35 @@ pub struct KiwixConfig {
 
     #[serde(default = "default_kiwix_timeout_secs")]
     pub timeout_secs: u64,
+
+    /// Collections queried at the same time for one search.
+    #[serde(default = "default_kiwix_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+
+    /// Extra attempts after a connect error or 5xx response.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Wait before the first retry; doubled for each one after.
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+
+    /// Reranking rules by collection id, as a source's `rerank_rules`.
+    #[serde(default)]
+    pub rerank_rules: BTreeMap<String, Vec<RerankRule>>,
+
+    /// Fetch the articles of top hits that Kiwix returned without a snippet
+    /// and cut a snippet around the query words from their text.
+    #[serde(default)]
+    pub fetch_snippets: bool,
+
+    /// How many of the top Kiwix hits are considered for `fetch_snippets`.
+    #[serde(default = "default_kiwix_fetch_snippets_max_hits")]
+    pub fetch_snippets_max_hits: usize,
+
+    /// Articles not fetched within this keep the `From <collection>` preview.
+    #[serde(default = "default_kiwix_fetch_snippets_timeout_ms")]
+    pub fetch_snippets_timeout_ms: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -135,6 +1121,9 @@ pub struct OllamaConfig {
     pub base_url: String,
     pub model: String,
 
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+
     #[serde(default = "default_ollama_timeout_secs")]
     pub timeout_secs: u64,
 
@@ -143,6 +1132,19 @@ pub struct OllamaConfig {
 
     #[serde(default = "default_ollama_max_context_chars")]
     pub max_context_chars: usize,
+
+    /// Replaces the built-in answer prompt. `{query}` and `{context}` are
+    /// substituted with the question and the search snippets.
+    #[serde(default)]
+    pub answer_prompt_template: Option<String>,
+
+    /// Extra attempts after a connect error or 5xx response.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Wait before the first retry; doubled for each one after.
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
 }
 
 fn default_index_dir() -> PathBuf {
@@ -165,10 +1167,113 @@ fn default_max_indexed_chars() -> usize {
     200_000
 }
 
+fn default_max_file_bytes() -> u64 {
+    100_000_000
+}
+
+fn default_html_wrap_width() -> usize {
+    120
+}
+
+fn default_synonym_boost() -> f32 {
+    0.5
+}
+
+fn default_snippet_fragments() -> usize {
+    1
+}
+
+fn default_snippet_fragment_chars() -> usize {
+    280
+}
+
+fn default_lang_min_confidence() -> f64 {
+    0.5
+}
+
+fn default_verify_index_checksums() -> bool {
+    true
+}
+
+fn default_rerank() -> bool {
+    true
+}
+
 fn default_writer_memory_bytes() -> usize {
     200_000_000
 }
 
+fn default_ingest_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1)
+}
+
+fn default_watch_debounce_ms() -> u64 {
+    500
+}
+
+fn default_query_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_http_cache_max_age_secs() -> u64 {
+    60
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_cache_max_entries() -> usize {
+    1_000
+}
+
+fn default_rate_limit_burst() -> u32 {
+    20
+}
+
+fn default_rate_limit_answer_cost() -> u32 {
+    1
+}
+
+fn default_did_you_mean_below_hits() -> usize {
+    1
+}
+
+fn default_regex_max_pattern_chars() -> usize {
+    100
+}
+
+fn default_fuzzy_max_distance() -> u8 {
+    2
+}
+
+fn default_fuzzy_min_term_chars() -> usize {
+    4
+}
+
+fn default_regex_prefix_required_above_docs() -> u64 {
+    100_000
+}
+
+fn default_field_boost() -> f32 {
+    1.0
+}
+
+fn default_source_weight() -> f32 {
+    1.0
+}
+
+// Tantivy's built-in BM25 constants.
+pub fn default_bm25_k1() -> f32 {
+    1.2
+}
+
+pub fn default_bm25_b() -> f32 {
+    0.75
+}
+
 fn default_kiwix_auto_discover() -> bool {
     true
 }
@@ -181,6 +1286,26 @@ fn default_kiwix_timeout_secs() -> u64 {
     10
 }
 
+fn default_kiwix_max_concurrent_requests() -> usize {
+    8
+}
+
+fn default_kiwix_fetch_snippets_max_hits() -> usize {
+    3
+}
+
+fn default_kiwix_fetch_snippets_timeout_ms() -> u64 {
+    1500
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    250
+}
+
 fn default_ollama_timeout_secs() -> u64 {
     20
 }
@@ -192,3 +1317,242 @@ fn default_ollama_max_context_hits() -> usize {
 fn default_ollama_max_context_chars() -> usize {
     4_000
 }
+
+/// Top-level key listing further config files to merge into the main one.
+const INCLUDE_KEY: &str = "include";
+
+/// Removes the `include` list from `table` and expands it into file paths,
+/// in order. Relative patterns are resolved against the directory of
+/// `config_path`; glob matches come in alphabetical order, and a pattern
+/// without wildcards must name an existing file.
+fn include_paths(table: &mut toml::Table, config_path: &Path) -> Result<Vec<PathBuf>> {
+    let Some(value) = table.remove(INCLUDE_KEY) else {
+        return Ok(Vec::new());
+    };
+    let patterns: Vec<String> = value
+        .try_into()
+        .with_context(|| format!("`{INCLUDE_KEY}` must be a list of paths"))?;
+    let base_dir = config_path.parent().unwrap_or(Path::new(""));
+
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let full_pattern = if Path::new(&pattern).is_absolute() {
+            pattern.clone()
+        } else {
+            // The directory is matched literally, even if it contains `*`.
+            let escaped_dir = glob::Pattern::escape(&base_dir.to_string_lossy());
+            Path::new(&escaped_dir)
+                .join(&pattern)
+                .to_string_lossy()
+                .into_owned()
+        };
+        let matches = glob::glob(&full_pattern)
+            .with_context(|| format!("invalid `{INCLUDE_KEY}` pattern `{pattern}`"))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("failed to expand `{INCLUDE_KEY}` pattern `{pattern}`"))?;
+        if matches.is_empty() && glob::Pattern::escape(&pattern) == pattern {
+            bail!("included config {full_pattern} does not exist");
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+/// Merges an included config into `base`: tables are merged key by key,
+/// `sources` lists are appended to, and other values replace earlier ones.
+fn merge_config_tables(base: &mut toml::Table, included: toml::Table) {
+    for (key, value) in included {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_config_tables(base_table, table);
+            }
+            (Some(toml::Value::Array(base_list)), toml::Value::Array(list)) if key == "sources" => {
+                base_list.extend(list);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Prefix of environment variables that override config keys.
+const ENV_PREFIX: &str = "BUNKER_";
+
+/// Config overrides from `BUNKER_*` variables, as key paths and values.
+/// The name after the prefix is lowercased and split at `__` into nested
+/// keys, so `BUNKER_INDEX_DIR` sets `index_dir` and
+/// `BUNKER_OLLAMA__BASE_URL` sets `base_url` in `[ollama]`. Values are kept
+/// raw until `apply_env_overrides` sets them.
+fn env_overrides(
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<(Vec<String>, String)>> {
+    let mut overrides = Vec::new();
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let keys: Vec<String> = key.to_lowercase().split("__").map(str::to_string).collect();
+        if keys.iter().any(String::is_empty) {
+            bail!("environment variable {name} does not name a config key");
+        }
+        overrides.push((keys, raw));
+    }
+    // Sorted so shallow keys are set before the nested ones they contain.
+    overrides.sort_by(|(left, _), (right, _)| left.cmp(right));
+    Ok(overrides)
+}
+
+/// Sets `overrides` on `table` in order. Values are read as TOML (`8787`,
+/// `true`, `["a", "b"]`) and fall back to a plain string; a value that reads
+/// as TOML but only fits its key as a string, like a numeric
+/// `BUNKER_API_TOKEN`, is set as the string.
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    overrides: Vec<(Vec<String>, String)>,
+) -> Result<()> {
+    for (keys, raw) in overrides {
+        let value = env_value(&raw);
+        if value.is_str() || fits_config(table, &keys, value.clone())? {
+            set_config_value(table, &keys, value)?;
+        } else if fits_config(table, &keys, toml::Value::String(raw.clone()))? {
+            set_config_value(table, &keys, toml::Value::String(raw))?;
+        } else {
+            // Neither fits yet; later overrides may complete the config.
+            set_config_value(table, &keys, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the config in `table` still loads with `value` set at `keys`.
+fn fits_config(table: &toml::Table, keys: &[String], value: toml::Value) -> Result<bool> {
+    let mut candidate = table.clone();
+    set_config_value(&mut candidate, keys, value)?;
+    Ok(toml::Value::Table(candidate)
+        .try_into::<AppConfig>()
+        .is_ok())
+}
+
+/// `raw` read as a TOML value, or as a plain string when it is not one.
+fn env_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Sets `value` at the nested key path `keys`, creating missing tables.
+fn set_config_value(table: &mut toml::Table, keys: &[String], value: toml::Value) -> Result<()> {
+    let (last, parents) = keys.split_last().context("empty config key")?;
+    let mut current = table;
+    for key in parents {
+        let entry = current
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        current = entry
+            .as_table_mut()
+            .with_context(|| format!("cannot set `{}`: `{key}` is not a table", keys.join(".")))?;
+    }
+    current.insert(last.clone(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from_env(vars: &[(&str, &str)]) -> AppConfig {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        let mut table = toml::Table::new();
+        apply_env_overrides(&mut table, env_overrides(vars).unwrap()).unwrap();
+        toml::Value::Table(table).try_into().unwrap()
+    }
+
+    #[test]
+    fn numeric_env_values_stay_strings_for_string_keys() {
+        let config = config_from_env(&[
+            ("BUNKER_API_TOKEN", "12345"),
+            ("BUNKER_DEFAULT_RESULT_LIMIT", "50"),
+            ("BUNKER_OLLAMA__BASE_URL", "http://127.0.0.1:11434"),
+            ("BUNKER_OLLAMA__MODEL", "7"),
+        ]);
+        assert_eq!(config.api_token.as_deref(), Some("12345"));
+        assert_eq!(config.default_result_limit, 50);
+        assert_eq!(
+            config.ollama.map(|ollama| ollama.model).as_deref(),
+            Some("7")
+        );
+    }
+
+    #[test]
+    fn env_values_are_read_as_toml() {
+        let config = config_from_env(&[
+            ("BUNKER_CACHE_ENABLED", "false"),
+            (
+                "BUNKER_CORS_ALLOWED_ORIGINS",
+                r#"["https://a.example", "https://b.example"]"#,
+            ),
+        ]);
+        assert!(!config.cache_enabled);
+        assert_eq!(
+            config.cors_allowed_origins,
+            ["https://a.example", "https://b.example"]
+        );
+    }
+
+    #[test]
+    fn included_configs_are_merged_into_the_main_one() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("conf.d")).unwrap();
+        fs::write(
+            dir.path().join("main.toml"),
+            r#"
+include = ["conf.d/*.toml"]
+index_dir = "index"
+
+[[sources]]
+type = "filesystem"
+name = "notes"
+path = "notes"
+
+[ollama]
+base_url = "http://127.0.0.1:11434"
+model = "main-model"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("conf.d/extra.toml"),
+            r#"
+[[sources]]
+type = "filesystem"
+name = "docs"
+path = "docs"
+
+[ollama]
+model = "included-model"
+"#,
+        )
+        .unwrap();
+
+        let config = AppConfig::from_file(dir.path().join("main.toml")).unwrap();
+        let names: Vec<&str> = config.sources.iter().map(SourceConfig::name).collect();
+        assert_eq!(names, ["notes", "docs"]);
+        let ollama = config.ollama.unwrap();
+        assert_eq!(ollama.base_url, "http://127.0.0.1:11434");
+        assert_eq!(ollama.model, "included-model");
+
+        fs::write(
+            dir.path().join("conf.d/nested.toml"),
+            r#"include = ["more.toml"]"#,
+        )
+        .unwrap();
+        let err = AppConfig::from_file(dir.path().join("main.toml")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("only the main config can include files"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use blake3::Hasher;
+use serde::{Deserialize, Serialize};
+
+use crate::config::AppConfig;
+use crate::ollama::OllamaClient;
+use crate::search::SearchEngine;
+
+const EMBEDDINGS_FILE: &str = "embeddings.json";
+const MAX_EMBEDDING_INPUT_CHARS: usize = 2_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddingStats {
+    pub embedded: u64,
+    pub unchanged: u64,
+    pub removed: u64,
+}
+
+/// Per-document embedding vectors, kept in a sidecar file next to the
+/// Tantivy index and keyed by `doc_id`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EmbeddingStore {
+    model: String,
+    docs: BTreeMap<String, StoredEmbedding>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredEmbedding {
+    hash: String,
+    vector: Vec<f32>,
+}
+
+impl EmbeddingStore {
+    pub fn load(index_dir: &Path) -> Result<Self> {
+        let path = store_path(index_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read embeddings at {}", path.display()))?;
+        let store: EmbeddingStore = serde_json::from_str(&data)
+            .with_context(|| format!("failed to parse embeddings at {}", path.display()))?;
+        Ok(store)
+    }
+
+    fn save(&self, index_dir: &Path) -> Result<()> {
+        let path = store_path(index_dir);
+        let data = serde_json::to_vec(self).context("failed to serialize embeddings")?;
+        fs::write(&path, data)
+            .with_context(|| format!("failed to write embeddings at {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns up to `limit` doc ids ordered by cosine similarity to `query`.
+    pub fn nearest(&self, query: &[f32], limit: usize) -> Vec<(String, f32)> {
+        let mut scored: Vec<(String, f32)> = self
+            .docs
+            .iter()
+            .filter_map(|(doc_id, entry)| {
+                cosine_similarity(query, &entry.vector).map(|score| (doc_id.clone(), score))
+            })
+            .collect();
+
+        scored.sort_by(|left, right| right.1.total_cmp(&left.1));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+/// Embeds new or changed indexed documents and drops vectors for documents
+/// that are no longer in the index. Returns `None` when no
+/// `ollama.embedding_model` is configured.
+pub async fn update_embeddings(
+    config: &AppConfig,
+    rebuild: bool,
+) -> Result<Option<EmbeddingStats>> {
+    let Some(ollama_config) = config.ollama.clone() else {
+        return Ok(None);
+    };
+    let client = OllamaClient::from_config(ollama_config)
+        .context("failed to initialize Ollama integration")?;
+    let Some(model) = client.embedding_model().map(str::to_string) else {
+        return Ok(None);
+    };
+
+    let engine = SearchEngine::open(config)?;
+    let docs = engine.stored_documents()?;
+
+    let mut store = if rebuild {
+        EmbeddingStore::default()
+    } else {
+        EmbeddingStore::load(&config.index_dir)?
+    };
+    if store.model != model {
+        store = EmbeddingStore {
+            model,
+            docs: BTreeMap::new(),
+        };
+    }
+
+    let mut embedded = 0u64;
+    let mut unchanged = 0u64;
+    let mut seen = HashSet::with_capacity(docs.len());
+
+    for doc in docs {
+        let input = embedding_input(&doc.title, &doc.body);
+        let hash = content_hash(&input);
+        seen.insert(doc.doc_id.clone());
+
+        if store
+            .docs
+            .get(&doc.doc_id)
+            .is_some_and(|entry| entry.hash == hash)
+        {
+            unchanged += 1;
+            continue;
+        }
+
+        let vector = match client.embed(&input).await {
+            Ok(vector) => vector,
+            Err(err) => {
+                // Keep what was embedded so far; the rest is retried next run.
+                store.save(&config.index_dir)?;
+                return Err(err.context(format!("failed to embed document {}", doc.doc_id)));
+            }
+        };
+        store
+            .docs
+            .insert(doc.doc_id, StoredEmbedding { hash, vector });
+        embedded += 1;
+    }
+
+    let before = store.docs.len();
+    store.docs.retain(|doc_id, _| seen.contains(doc_id));
+    let removed = (before - store.docs.len()) as u64;
+
+    store.save(&config.index_dir)?;
+
+    Ok(Some(EmbeddingStats {
+        embedded,
+        unchanged,
+        removed,
+    }))
+}
+
+fn store_path(index_dir: &Path) -> PathBuf {
+    index_dir.join(EMBEDDINGS_FILE)
+}
+
+fn embedding_input(title: &str, body: &str) -> String {
+    let body: String = body.chars().take(MAX_EMBEDDING_INPUT_CHARS).collect();
+    format!("{title}\n\n{body}")
+}
+
+fn content_hash(input: &str) -> String {
+    let mut hasher = Hasher::new();
+    hasher.update(input.as_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+fn cosine_similarity(left: &[f32], right: &[f32]) -> Option<f32> {
+    if left.len() != right.len() || left.is_empty() {
+        return None;
+    }
+
+    let mut dot = 0.0f32;
+    let mut left_norm = 0.0f32;
+    let mut right_norm = 0.0f32;
+    for (a, b) in left.iter().zip(right) {
+        dot += a * b;
+        left_norm += a * a;
+        right_norm += b * b;
+    }
+
+    if left_norm == 0.0 || right_norm == 0.0 {
+        return None;
+    }
+
+    Some(dot / (left_norm.sqrt() * right_norm.sqrt()))
+}
@@ -1,151 +1,975 @@
-use std::collections::{BTreeMap, HashSet};
-use std::fs;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use tantivy::{TantivyDocument, Term};
+use anyhow::{bail, Context, Result};
+use fs4::FileExt;
+use serde::Serialize;
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
+use tantivy::indexer::NoMergePolicy;
+use tantivy::query::TermQuery;
+use tantivy::schema::{IndexRecordOption, Value};
+use tantivy::{DocAddress, Index, IndexWriter, TantivyDocument, Term};
 
-use crate::config::AppConfig;
-use crate::ingest;
-use crate::search;
+use crate::config::{AppConfig, TitleFrom};
+use crate::ingest::{self, FilesystemWalker, JsonlCheckpoints, RawDocument};
+use crate::manifest::{self, Manifest};
+use crate::progress::Progress;
+use crate::search::{self, IndexFields, SchemaStatus};
 
-const MANIFEST_FILE: &str = "manifest.json";
+/// Present while an indexing run writes to the index; one left behind means
+/// the last run was interrupted before it finished.
+const RUN_MARKER_FILE: &str = "index_run.incomplete";
+/// Exclusively locked by the process writing to the index, so a second
+/// indexer fails up front instead of on tantivy's writer lock.
+const WRITE_LOCK_FILE: &str = "index.lock";
+/// Body characters language detection looks at.
+const LANG_DETECT_CHARS: usize = 1000;
+/// Language tag of documents whose language couldn't be told.
+const UNDETERMINED_LANG: &str = "und";
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct IndexStats {
     pub scanned: u64,
     pub indexed: u64,
     pub skipped: u64,
     pub removed: u64,
+    pub failed: u64,
+    /// Deleted to keep the index under `max_index_bytes`.
+    pub evicted: u64,
+    pub elapsed: Duration,
+    /// Per-source breakdown of a full pass, in config order.
+    pub sources: Vec<SourceIndexStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceIndexStats {
+    pub name: String,
+    pub elapsed: Duration,
+    pub scanned: u64,
+    pub indexed: u64,
+    /// Skipped by the source plus read but unchanged since the last pass.
+    pub skipped: u64,
+    pub failed: u64,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct Manifest {
-    version: u8,
-    docs: BTreeMap<String, String>,
+pub fn index_sources(config: &AppConfig, rebuild: bool, progress: &Progress) -> Result<IndexStats> {
+    run_index(config, rebuild, None, progress)
 }
 
-pub fn index_sources(config: &AppConfig, rebuild: bool) -> Result<IndexStats> {
+/// Indexes only `source_name`, diffing it against its own documents in the
+/// index; other sources' documents and manifest entries are left as they
+/// are. With `rebuild`, every document of the source is rewritten (and its
+/// JSONL checkpoint and HTTP validators reset) whether or not it changed.
+pub fn index_source(
+    config: &AppConfig,
+    source_name: &str,
+    rebuild: bool,
+    progress: &Progress,
+) -> Result<IndexStats> {
+    run_index(config, rebuild, Some(source_name), progress)
+}
+
+fn run_index(
+    config: &AppConfig,
+    rebuild: bool,
+    only_source: Option<&str>,
+    progress: &Progress,
+) -> Result<IndexStats> {
+    let started = Instant::now();
+    let scoped_config;
+    let ingest_config = match only_source {
+        Some(source_name) => {
+            let sources: Vec<_> = config
+                .sources
+                .iter()
+                .filter(|source| source.name() == source_name)
+                .cloned()
+                .collect();
+            if sources.is_empty() {
+                bail!("no source named `{source_name}` in the config");
+            }
+            scoped_config = AppConfig {
+                sources,
+                ..config.clone()
+            };
+            &scoped_config
+        }
+        None => config,
+    };
     if config.sources.is_empty() {
         tracing::warn!("config has no sources; nothing to index");
     }
 
-    let manifest_path = manifest_path(&config.index_dir);
-    let old_manifest = if rebuild {
-        Manifest::default()
+    let _lock = lock_index(&config.index_dir)?;
+    if last_run_interrupted(&config.index_dir) {
+        tracing::warn!(
+            index_dir = %config.index_dir.display(),
+            "the previous indexing run did not finish"
+        );
+    }
+
+    // A full rebuild recreates the index, so it may start over a damaged one.
+    let full_rebuild = rebuild && only_source.is_none();
+    let status = match search::schema_status(config) {
+        Err(err) if full_rebuild => {
+            tracing::warn!(error = %format!("{err:#}"), "discarding unreadable index");
+            search::remove_index_files(&config.index_dir)?;
+            SchemaStatus::Missing
+        }
+        status => status?,
+    };
+    if let (Some(source_name), SchemaStatus::Outdated { additive, .. }) = (only_source, &status) {
+        bail!(
+            "the index schema changed; run a full `bunker-search index{}` before indexing `{source_name}` on its own",
+            if *additive { "" } else { " --rebuild" }
+        );
+    }
+    let full_rebuild = match status {
+        SchemaStatus::Outdated { additive, .. } if full_rebuild || additive => {
+            if !full_rebuild {
+                tracing::warn!("index schema gained new fields; rebuilding index in place");
+            }
+            search::remove_index_files(&config.index_dir)?;
+            true
+        }
+        _ => full_rebuild,
+    };
+    let rebuild = rebuild || full_rebuild;
+    if full_rebuild && search::stored_compression_changed(config)? {
+        search::remove_index_files(&config.index_dir)?;
+    }
+
+    if !full_rebuild && search::stop_words_changed(config)? {
+        bail!(
+            "stop_words changed since the index at {} was built; run `bunker-search index --rebuild` to apply them",
+            config.index_dir.display()
+        );
+    }
+
+    let manifest = Manifest::open(&config.index_dir)?;
+    if full_rebuild {
+        manifest.clear()?;
+    }
+
+    let mut checkpoints = if full_rebuild {
+        JsonlCheckpoints::default()
     } else {
-        load_manifest(&manifest_path)?
+        JsonlCheckpoints::load(&config.index_dir)?
     };
 
-    let index_handle = search::open_or_create_index(&config.index_dir)?;
+    let index_handle = search::open_or_create_index(config)?;
     let fields = index_handle.fields;
+    // The documents the manifest diff covers: the named source's, or all.
+    let scoped_doc_ids = match only_source {
+        Some(source_name) => {
+            let mut doc_ids = source_doc_ids(&index_handle.index, &fields, source_name)?;
+            doc_ids.extend(manifest.evicted_doc_ids(source_name)?);
+            Some(doc_ids)
+        }
+        None => None,
+    };
 
     let mut writer = index_handle
         .index
         .writer(config.writer_memory_bytes)
         .context("failed to create tantivy index writer")?;
+    begin_run(&config.index_dir)?;
 
-    if rebuild {
+    if full_rebuild {
         writer
             .delete_all_documents()
             .context("failed to clear index for rebuild")?;
     }
 
-    let mut new_docs = BTreeMap::new();
-    let mut seen_doc_ids = HashSet::new();
+    let mut http_validators = manifest.http_validators()?;
+    if let (Some(source_name), true) = (only_source, rebuild) {
+        writer.delete_term(Term::from_field_text(fields.source, source_name));
+        checkpoints.forget(source_name);
+        http_validators.forget(source_name);
+    }
+
+    let now = unix_now();
 
     let mut indexed_count = 0u64;
     let mut unchanged_count = 0u64;
+    // (indexed, unchanged) per source name.
+    let mut source_counts: HashMap<String, (u64, u64)> = HashMap::new();
 
-    let ingest_stats = ingest::ingest_sources(config, |doc| {
-        if let Some(old_fp) = old_manifest.docs.get(&doc.doc_id) {
-            if !rebuild && old_fp == &doc.fingerprint {
+    let ingest_stats = ingest::ingest_sources(
+        ingest_config,
+        &mut checkpoints,
+        &mut http_validators,
+        progress,
+        |doc| {
+            manifest.mark_seen(&doc.doc_id)?;
+            if !rebuild && manifest.fingerprint(&doc.doc_id)?.as_ref() == Some(&doc.fingerprint) {
                 unchanged_count += 1;
-                seen_doc_ids.insert(doc.doc_id.clone());
-                new_docs.insert(doc.doc_id, old_fp.clone());
+                source_counts.entry(doc.source).or_default().1 += 1;
+                progress.unchanged();
                 return Ok(());
             }
-        }
 
-        let doc_id = doc.doc_id.clone();
-        seen_doc_ids.insert(doc_id.clone());
+            let source = doc.source.clone();
+            let (doc_id, fingerprint) = write_document(&writer, &fields, config, doc)?;
+            manifest.record_indexed(&doc_id, &fingerprint, now)?;
+            indexed_count += 1;
+            source_counts.entry(source).or_default().0 += 1;
+            progress.indexed();
 
-        writer.delete_term(Term::from_field_text(fields.doc_id, &doc_id));
+            Ok(())
+        },
+    )?;
+    progress.finish();
 
-        let mut indexed_doc = TantivyDocument::default();
-        indexed_doc.add_text(fields.doc_id, doc_id.clone());
-        indexed_doc.add_text(fields.source, doc.source);
-        indexed_doc.add_text(fields.title, doc.title);
-        indexed_doc.add_text(fields.body, doc.body);
-        indexed_doc.add_text(fields.preview, doc.preview);
-        indexed_doc.add_text(fields.location, doc.location);
-        if let Some(url) = doc.url {
-            indexed_doc.add_text(fields.url, url);
-        }
-
-        writer
-            .add_document(indexed_doc)
-            .context("failed to add document to index")?;
-
-        new_docs.insert(doc_id, doc.fingerprint);
-        indexed_count += 1;
-
-        Ok(())
-    })?;
+    for prefix in &ingest_stats.retained_prefixes {
+        unchanged_count += manifest.mark_seen_with_prefix(prefix)?;
+    }
 
+    // Documents the run didn't read are gone from their source. Those of
+    // the sources a `--source` run didn't read are kept as they are.
     let mut removed_count = 0u64;
-    if !rebuild {
-        for old_doc_id in old_manifest.docs.keys() {
-            if !seen_doc_ids.contains(old_doc_id) {
-                writer.delete_term(Term::from_field_text(fields.doc_id, old_doc_id));
-                removed_count += 1;
+    if !full_rebuild {
+        match &scoped_doc_ids {
+            Some(doc_ids) => {
+                for doc_id in doc_ids {
+                    if !manifest.is_seen(doc_id)? {
+                        writer.delete_term(Term::from_field_text(fields.doc_id, doc_id));
+                        manifest.remove(doc_id)?;
+                        removed_count += 1;
+                    }
+                }
+            }
+            None => {
+                removed_count = manifest.remove_unseen(|doc_id| {
+                    writer.delete_term(Term::from_field_text(fields.doc_id, doc_id));
+                })?;
             }
         }
     }
 
     if rebuild || indexed_count > 0 || removed_count > 0 {
-        writer.commit().context("failed to commit index changes")?;
+        search::commit_index(&mut writer, config)?;
+    }
+
+    manifest.set_http_validators(&http_validators)?;
+    let evicted_count =
+        enforce_index_budget(config, &index_handle.index, writer, &fields, &manifest)?;
+    manifest.commit()?;
+    checkpoints.save(&config.index_dir)?;
+    finish_run(&config.index_dir)?;
+
+    let sources = ingest_stats
+        .sources
+        .into_iter()
+        .map(|source| {
+            let (indexed, unchanged) = source_counts.get(&source.name).copied().unwrap_or_default();
+            SourceIndexStats {
+                name: source.name,
+                elapsed: source.elapsed,
+                scanned: source.scanned,
+                indexed,
+                skipped: source.skipped + unchanged,
+                failed: source.failed,
+            }
+        })
+        .collect();
+
+    Ok(IndexStats {
+        scanned: ingest_stats.scanned,
+        indexed: indexed_count,
+        skipped: ingest_stats.skipped + unchanged_count,
+        removed: removed_count,
+        failed: ingest_stats.failed,
+        evicted: evicted_count,
+        elapsed: started.elapsed(),
+        sources,
+    })
+}
+
+/// Reads every source like `index_sources` and reports what a real run
+/// would index, skip and remove, without opening the index writer or
+/// writing the manifest, checkpoints or HTTP validators. Each document that
+/// would be (re)indexed is logged at debug level.
+pub fn dry_run(config: &AppConfig, rebuild: bool, progress: &Progress) -> Result<IndexStats> {
+    let started = Instant::now();
+    if config.sources.is_empty() {
+        tracing::warn!("config has no sources; nothing to index");
     }
 
-    let new_manifest = Manifest {
-        version: 1,
-        docs: new_docs,
+    let manifest = if rebuild {
+        Manifest::in_memory()?
+    } else {
+        Manifest::open_read_only(&config.index_dir)?
+    };
+    let mut checkpoints = if rebuild {
+        JsonlCheckpoints::default()
+    } else {
+        JsonlCheckpoints::load(&config.index_dir)?
     };
-    save_manifest(&manifest_path, &new_manifest)?;
+
+    let mut indexed_count = 0u64;
+    let mut unchanged_count = 0u64;
+    // (indexed, unchanged) per source name.
+    let mut source_counts: HashMap<String, (u64, u64)> = HashMap::new();
+
+    let mut http_validators = manifest.http_validators()?;
+    let ingest_stats = ingest::ingest_sources(
+        config,
+        &mut checkpoints,
+        &mut http_validators,
+        progress,
+        |doc| {
+            manifest.mark_seen(&doc.doc_id)?;
+            if manifest.fingerprint(&doc.doc_id)?.as_ref() == Some(&doc.fingerprint) {
+                unchanged_count += 1;
+                source_counts.entry(doc.source).or_default().1 += 1;
+                progress.unchanged();
+            } else {
+                tracing::debug!(
+                    doc_id = %doc.doc_id,
+                    title = %doc.title,
+                    "would index document"
+                );
+                indexed_count += 1;
+                source_counts.entry(doc.source).or_default().0 += 1;
+                progress.indexed();
+            }
+            Ok(())
+        },
+    )?;
+    progress.finish();
+
+    for prefix in &ingest_stats.retained_prefixes {
+        unchanged_count += manifest.mark_seen_with_prefix(prefix)?;
+    }
+    let removed_count = manifest.count_unseen()?;
+
+    let sources = ingest_stats
+        .sources
+        .into_iter()
+        .map(|source| {
+            let (indexed, unchanged) = source_counts.get(&source.name).copied().unwrap_or_default();
+            SourceIndexStats {
+                name: source.name,
+                elapsed: source.elapsed,
+                scanned: source.scanned,
+                indexed,
+                skipped: source.skipped + unchanged,
+                failed: source.failed,
+            }
+        })
+        .collect();
 
     Ok(IndexStats {
         scanned: ingest_stats.scanned,
         indexed: indexed_count,
         skipped: ingest_stats.skipped + unchanged_count,
         removed: removed_count,
+        failed: ingest_stats.failed,
+        evicted: 0,
+        elapsed: started.elapsed(),
+        sources,
     })
 }
 
-fn manifest_path(index_dir: &Path) -> PathBuf {
-    index_dir.join(MANIFEST_FILE)
+/// Re-indexes the given paths of one filesystem source, applying the same
+/// manifest diff as a full pass: changed files are replaced and vanished
+/// files (or directories) are deleted. Used by `index --watch`.
+pub fn apply_filesystem_changes(
+    config: &AppConfig,
+    source_name: &str,
+    walker: &FilesystemWalker,
+    extensions: &[String],
+    title_from: TitleFrom,
+    paths: &BTreeSet<PathBuf>,
+) -> Result<IndexStats> {
+    let _lock = lock_index(&config.index_dir)?;
+    let manifest = Manifest::open(&config.index_dir)?;
+
+    let index_handle = search::open_or_create_index(config)?;
+    let fields = index_handle.fields;
+
+    let mut writer = index_handle
+        .index
+        .writer(config.writer_memory_bytes)
+        .context("failed to create tantivy index writer")?;
+
+    let started = Instant::now();
+    let mut stats = IndexStats {
+        scanned: 0,
+        indexed: 0,
+        skipped: 0,
+        removed: 0,
+        failed: 0,
+        evicted: 0,
+        elapsed: Duration::ZERO,
+        sources: Vec::new(),
+    };
+
+    let root = walker.root();
+    for path in paths {
+        // A created or moved-in directory only produces one event, so walk it.
+        let files: Vec<PathBuf> = if path.is_dir() {
+            if walker.is_ignored(path, true) {
+                continue;
+            }
+            walker.files(path).collect()
+        } else {
+            vec![path.clone()]
+        };
+
+        for file in files {
+            let doc_id = ingest::filesystem_doc_id(source_name, root, &file);
+            // Files that became ignored are dropped like deleted ones.
+            let ignored = walker.is_ignored(&file, false);
+            if ignored && !manifest.contains(&doc_id)? {
+                continue;
+            }
+            stats.scanned += 1;
+
+            let doc = if ignored || walker.is_excluded(&file) {
+                None
+            } else {
+                ingest::read_filesystem_path(
+                    config,
+                    source_name,
+                    root,
+                    extensions,
+                    title_from,
+                    &file,
+                )
+            };
+            match doc {
+                Some(doc) => {
+                    if manifest.fingerprint(&doc.doc_id)?.as_ref() == Some(&doc.fingerprint) {
+                        stats.skipped += 1;
+                        continue;
+                    }
+                    let (doc_id, fingerprint) = write_document(&writer, &fields, config, doc)?;
+                    manifest.record_indexed(&doc_id, &fingerprint, unix_now())?;
+                    stats.indexed += 1;
+                }
+                None => {
+                    if manifest.remove(&doc_id)? {
+                        writer.delete_term(Term::from_field_text(fields.doc_id, &doc_id));
+                        stats.removed += 1;
+                    } else {
+                        stats.skipped += 1;
+                    }
+                }
+            }
+        }
+
+        // A removed directory leaves manifest entries underneath it.
+        if !path.exists() {
+            let prefix = format!("{}/", ingest::filesystem_doc_id(source_name, root, path));
+            for doc_id in manifest.doc_ids_with_prefix(&prefix)? {
+                manifest.remove(&doc_id)?;
+                writer.delete_term(Term::from_field_text(fields.doc_id, &doc_id));
+                stats.removed += 1;
+            }
+        }
+    }
+
+    if stats.indexed > 0 || stats.removed > 0 {
+        begin_run(&config.index_dir)?;
+        search::commit_index(&mut writer, config)?;
+        stats.evicted =
+            enforce_index_budget(config, &index_handle.index, writer, &fields, &manifest)?;
+        manifest.commit()?;
+        finish_run(&config.index_dir)?;
+    }
+
+    stats.elapsed = started.elapsed();
+    Ok(stats)
 }
 
-fn load_manifest(path: &Path) -> Result<Manifest> {
-    if !path.exists() {
-        return Ok(Manifest::default());
+/// Deletes every document of `source_name` and forgets them in the manifest
+/// (plus the source's JSONL checkpoint and HTTP validators), so removing a
+/// source does not need a rebuild. Returns the number of documents removed.
+pub fn drop_source(config: &AppConfig, source_name: &str) -> Result<u64> {
+    let _lock = lock_index(&config.index_dir)?;
+    let manifest = Manifest::open(&config.index_dir)?;
+    let mut checkpoints = JsonlCheckpoints::load(&config.index_dir)?;
+
+    let index_handle = search::open_or_create_index(config)?;
+    let fields = index_handle.fields;
+    let source_term = Term::from_field_text(fields.source, source_name);
+    let doc_ids = source_doc_ids(&index_handle.index, &fields, source_name)?;
+
+    let mut writer: IndexWriter = index_handle
+        .index
+        .writer(config.writer_memory_bytes)
+        .context("failed to create tantivy index writer")?;
+    begin_run(&config.index_dir)?;
+    writer.delete_term(source_term);
+    search::commit_index(&mut writer, config)?;
+
+    for doc_id in &doc_ids {
+        manifest.remove(doc_id)?;
+    }
+    manifest.remove_evicted(source_name)?;
+    let mut http_validators = manifest.http_validators()?;
+    http_validators.forget(source_name);
+    manifest.set_http_validators(&http_validators)?;
+    manifest.commit()?;
+    checkpoints.forget(source_name);
+    checkpoints.save(&config.index_dir)?;
+    finish_run(&config.index_dir)?;
+
+    Ok(doc_ids.len() as u64)
+}
+
+/// Evicts the documents written longest ago (those from before timestamps
+/// were kept first) until the index directory fits `max_index_bytes`,
+/// merging segments so the deleted documents free their space. Returns the
+/// number of documents evicted.
+fn enforce_index_budget(
+    config: &AppConfig,
+    index: &Index,
+    writer: IndexWriter,
+    fields: &IndexFields,
+    manifest: &Manifest,
+) -> Result<u64> {
+    let budget = config.max_index_bytes;
+    if budget == 0 || search::index_size_bytes(&config.index_dir)? <= budget {
+        return Ok(0);
+    }
+
+    // Background merges started by the last commit would claim segments the
+    // merges below need.
+    writer
+        .wait_merging_threads()
+        .context("failed to finish merging index segments")?;
+    let mut writer: IndexWriter = index
+        .writer(config.writer_memory_bytes)
+        .context("failed to create tantivy index writer")?;
+    writer.set_merge_policy(Box::new(NoMergePolicy));
+    let reader = index.reader().context("failed to create tantivy reader")?;
+
+    let mut evicted = 0u64;
+    loop {
+        writer
+            .garbage_collect_files()
+            .wait()
+            .context("failed to remove unused index files")?;
+        let size = search::index_size_bytes(&config.index_dir)?;
+        reader.reload().context("failed to refresh index reader")?;
+        let searcher = reader.searcher();
+        let live_docs = searcher.num_docs();
+        if size <= budget || live_docs == 0 {
+            if size > budget {
+                tracing::warn!(
+                    size_bytes = size,
+                    max_index_bytes = budget,
+                    "index is over max_index_bytes with no documents left to evict"
+                );
+            }
+            break;
+        }
+
+        // Evict roughly the excess, judged by the average document size.
+        let average_doc_bytes = (size / live_docs).max(1);
+        let wanted = (size - budget).div_ceil(average_doc_bytes).min(live_docs) as usize;
+        let mut chosen = Vec::with_capacity(wanted);
+        manifest.oldest_first(|doc_id| {
+            if chosen.len() == wanted {
+                return Ok(false);
+            }
+            let query = TermQuery::new(
+                Term::from_field_text(fields.doc_id, doc_id),
+                IndexRecordOption::Basic,
+            );
+            let Some((_, doc_address)) = searcher
+                .search(&query, &TopDocs::with_limit(1))
+                .context("failed to look up document to evict")?
+                .into_iter()
+                .next()
+            else {
+                return Ok(true);
+            };
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_address)
+                .context("failed to read indexed document")?;
+            let source = doc
+                .get_first(fields.source)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string();
+            chosen.push((doc_id.to_string(), source));
+            Ok(true)
+        })?;
+        if chosen.is_empty() {
+            break;
+        }
+
+        for (doc_id, source) in chosen {
+            writer.delete_term(Term::from_field_text(fields.doc_id, &doc_id));
+            manifest.record_evicted(&doc_id, &source)?;
+            evicted += 1;
+        }
+        search::commit_index(&mut writer, config)?;
+        let segment_ids = index
+            .searchable_segment_ids()
+            .context("failed to list index segments")?;
+        if !segment_ids.is_empty() {
+            writer
+                .merge(&segment_ids)
+                .wait()
+                .context("failed to merge index segments")?;
+        }
     }
 
-    let data = fs::read_to_string(path)
-        .with_context(|| format!("failed to read manifest at {}", path.display()))?;
-    let manifest: Manifest = serde_json::from_str(&data)
-        .with_context(|| format!("failed to parse manifest at {}", path.display()))?;
-    Ok(manifest)
+    if evicted > 0 {
+        tracing::info!(
+            evicted,
+            max_index_bytes = budget,
+            "evicted the oldest documents to stay under max_index_bytes"
+        );
+    }
+    Ok(evicted)
 }
 
-fn save_manifest(path: &Path, manifest: &Manifest) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("failed to create manifest dir {}", parent.display()))?;
+/// Ids of the indexed documents of `source_name`.
+fn source_doc_ids(index: &Index, fields: &IndexFields, source_name: &str) -> Result<Vec<String>> {
+    let searcher = index
+        .reader()
+        .context("failed to create tantivy reader")?
+        .searcher();
+    let doc_addresses = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_field_text(fields.source, source_name),
+                IndexRecordOption::Basic,
+            ),
+            &DocSetCollector,
+        )
+        .context("failed to look up source documents")?;
+
+    let mut doc_ids = Vec::with_capacity(doc_addresses.len());
+    for doc_address in doc_addresses {
+        let doc = searcher
+            .doc::<TantivyDocument>(doc_address)
+            .context("failed to read indexed document")?;
+        if let Some(doc_id) = doc
+            .get_first(fields.doc_id)
+            .and_then(|value| value.as_str())
+        {
+            doc_ids.push(doc_id.to_string());
+        }
     }
+    Ok(doc_ids)
+}
+
+/// Segment counts around an `index --optimize` run.
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizeStats {
+    pub segments_before: usize,
+    pub segments_after: usize,
+}
+
+/// Merges the index's segments down to at most `max_segments`, dropping
+/// deleted documents on the way. Neighbouring segments are merged in equal
+/// groups. A running server keeps answering from the old segments until its
+/// reader reloads.
+pub fn optimize_index(config: &AppConfig, max_segments: usize) -> Result<OptimizeStats> {
+    let _lock = lock_index(&config.index_dir)?;
+    let index_handle = search::open_or_create_index(config)?;
+    let segment_ids = index_handle
+        .index
+        .searchable_segment_ids()
+        .context("failed to list index segments")?;
+    let segments_before = segment_ids.len();
+
+    if segments_before > max_segments.max(1) {
+        let mut writer: IndexWriter = index_handle
+            .index
+            .writer(config.writer_memory_bytes)
+            .context("failed to create tantivy index writer")?;
+        let group_size = segments_before.div_ceil(max_segments.max(1));
+        for group in segment_ids.chunks(group_size) {
+            if group.len() > 1 {
+                writer
+                    .merge(group)
+                    .wait()
+                    .context("failed to merge index segments")?;
+            }
+        }
+        writer
+            .garbage_collect_files()
+            .wait()
+            .context("failed to remove merged segment files")?;
+        writer
+            .wait_merging_threads()
+            .context("failed to finish merging index segments")?;
+    }
+
+    let segments_after = index_handle
+        .index
+        .searchable_segment_ids()
+        .context("failed to list index segments")?
+        .len();
+    Ok(OptimizeStats {
+        segments_before,
+        segments_after,
+    })
+}
+
+/// Outcome of an `index --vacuum` run.
+#[derive(Debug, Clone, Copy)]
+pub struct VacuumStats {
+    /// Manifest entries of documents missing from the index.
+    pub removed: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Forgets the manifest entries of documents that are no longer in the
+/// index (so they are indexed again by the next run instead of being taken
+/// as unchanged), then compacts the manifest file. Evicted documents keep
+/// their entries.
+pub fn vacuum_manifest(config: &AppConfig) -> Result<VacuumStats> {
+    let _lock = lock_index(&config.index_dir)?;
+    if search::schema_status(config)? == SchemaStatus::Missing {
+        bail!("no index at {}", config.index_dir.display());
+    }
+    let manifest = Manifest::open(&config.index_dir)?;
+    let manifest_bytes =
+        || fs::metadata(manifest::manifest_path(&config.index_dir)).map_or(0, |meta| meta.len());
+    let bytes_before = manifest_bytes();
+
+    let index_handle = search::open_or_create_index(config)?;
+    let fields = index_handle.fields;
+    let searcher = index_handle
+        .index
+        .reader()
+        .context("failed to create tantivy reader")?
+        .searcher();
+    let removed = manifest.retain_indexed(|doc_id| {
+        let query = TermQuery::new(
+            Term::from_field_text(fields.doc_id, doc_id),
+            IndexRecordOption::Basic,
+        );
+        let count = searcher
+            .search(&query, &Count)
+            .context("failed to look up indexed document")?;
+        Ok(count > 0)
+    })?;
+    manifest.vacuum()?;
+
+    Ok(VacuumStats {
+        removed,
+        bytes_before,
+        bytes_after: manifest_bytes(),
+    })
+}
+
+/// One line of `index --export`.
+#[derive(Debug, Serialize)]
+struct ExportedDocument<'a> {
+    doc_id: &'a str,
+    source: &'a str,
+    title: &'a str,
+    body: &'a str,
+    preview: &'a str,
+    location: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<&'a str>,
+}
+
+/// Writes every stored document to `path` as JSON Lines, one segment at a
+/// time so memory stays flat. The body is the indexed (possibly truncated)
+/// text. Returns the number of documents written.
+pub fn export_documents(config: &AppConfig, path: &Path) -> Result<u64> {
+    if search::schema_status(config)? == SchemaStatus::Missing {
+        bail!("no index at {}", config.index_dir.display());
+    }
+
+    let index_handle = search::open_or_create_index(config)?;
+    let fields = index_handle.fields;
+    let searcher = index_handle
+        .index
+        .reader()
+        .context("failed to create tantivy reader")?
+        .searcher();
+
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    let text = |doc: &TantivyDocument, field| {
+        doc.get_first(field)
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+            .unwrap_or_default()
+    };
+
+    let mut exported = 0u64;
+    for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in segment_reader.doc_ids_alive() {
+            let doc = searcher
+                .doc::<TantivyDocument>(DocAddress::new(segment_ord as u32, doc_id))
+                .context("failed to read indexed document")?;
+            let url = text(&doc, fields.url);
+            let line = ExportedDocument {
+                doc_id: &text(&doc, fields.doc_id),
+                source: &text(&doc, fields.source),
+                title: &text(&doc, fields.title),
+                body: &text(&doc, fields.body),
+                preview: &text(&doc, fields.preview),
+                location: &text(&doc, fields.location),
+                url: Some(url.as_str()).filter(|url| !url.is_empty()),
+                date: doc.get_first(fields.date).and_then(|value| value.as_i64()),
+                tags: doc
+                    .get_all(fields.tags)
+                    .filter_map(|value| value.as_str())
+                    .collect(),
+            };
+            serde_json::to_writer(&mut out, &line).context("failed to serialize document")?;
+            out.write_all(b"\n")
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            exported += 1;
+        }
+    }
+
+    out.flush()
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(exported)
+}
+
+/// Replaces any existing copy of `doc` in the index and returns its doc id
+/// and fingerprint for the manifest.
+fn write_document(
+    writer: &IndexWriter,
+    fields: &IndexFields,
+    config: &AppConfig,
+    doc: RawDocument,
+) -> Result<(String, String)> {
+    writer.delete_term(Term::from_field_text(fields.doc_id, &doc.doc_id));
+    let lang = detect_language(&doc.body, config.lang_min_confidence);
+
+    let mut indexed_doc = TantivyDocument::default();
+    indexed_doc.add_text(fields.doc_id, doc.doc_id.clone());
+    if let Some(stemmed) = fields.stemmed_for(config.source_language(&doc.source)) {
+        indexed_doc.add_text(stemmed.title, &doc.title);
+        indexed_doc.add_text(stemmed.body, &doc.body);
+    }
+    if let Some(code) = fields.code.filter(|_| config.source_is_code(&doc.source)) {
+        indexed_doc.add_text(code.title, &doc.title);
+        indexed_doc.add_text(code.body, &doc.body);
+    }
+    indexed_doc.add_text(fields.source, doc.source);
+    indexed_doc.add_text(fields.title_sort, doc.title.to_lowercase());
+    indexed_doc.add_text(fields.title, doc.title);
+    indexed_doc.add_text(fields.body, doc.body);
+    indexed_doc.add_text(fields.preview, doc.preview);
+    indexed_doc.add_text(fields.location_terms, &doc.location);
+    indexed_doc.add_text(fields.location, doc.location);
+    if let Some(url) = doc.url {
+        indexed_doc.add_text(fields.url, url);
+    }
+    if let Some(date) = doc.date {
+        indexed_doc.add_i64(fields.date, date);
+    }
+    indexed_doc.add_text(fields.lang, lang);
+    for tag in &doc.tags {
+        indexed_doc.add_text(fields.tags, tag);
+    }
+
+    writer
+        .add_document(indexed_doc)
+        .context("failed to add document to index")?;
+
+    Ok((doc.doc_id, doc.fingerprint))
+}
+
+/// ISO 639-3 code of the language `text` is written in, judged from its
+/// first `LANG_DETECT_CHARS` characters; `und` below `min_confidence`.
+fn detect_language(text: &str, min_confidence: f64) -> &'static str {
+    let prefix = text
+        .char_indices()
+        .nth(LANG_DETECT_CHARS)
+        .map_or(text, |(end, _)| &text[..end]);
+    whatlang::detect(prefix)
+        .filter(|info| info.confidence() >= min_confidence)
+        .map_or(UNDETERMINED_LANG, |info| info.lang().code())
+}
+
+/// Exclusive hold on an index directory's write lock. Dropping it, or the
+/// process exiting in any way, releases the lock.
+#[derive(Debug)]
+pub struct IndexLock {
+    _file: File,
+}
+
+/// Takes the write lock of `index_dir`, failing right away when another
+/// process (an `index` run, `--watch`, `--drop-source`, `--optimize` or
+/// `--vacuum`) holds it.
+pub fn lock_index(index_dir: &Path) -> Result<IndexLock> {
+    fs::create_dir_all(index_dir)
+        .with_context(|| format!("failed to create index dir {}", index_dir.display()))?;
+    let path = index_dir.join(WRITE_LOCK_FILE);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        let holder = fs::read_to_string(&path)
+            .ok()
+            .map(|pid| pid.trim().to_string())
+            .filter(|pid| !pid.is_empty())
+            .map(|pid| format!(" (pid {pid})"))
+            .unwrap_or_default();
+        bail!(
+            "another bunker-search process{holder} is already writing to the index at {}; wait for it to finish",
+            index_dir.display()
+        );
+    }
+    // The pid is informational only; the OS lock is what excludes.
+    file.set_len(0)
+        .and_then(|_| write!(file, "{}", std::process::id()))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(IndexLock { _file: file })
+}
+
+/// True while some process holds the write lock of `index_dir`.
+pub fn indexing_in_progress(index_dir: &Path) -> bool {
+    let Ok(file) = File::open(index_dir.join(WRITE_LOCK_FILE)) else {
+        return false;
+    };
+    FileExt::try_lock_shared(&file).is_err()
+}
+
+/// True when an indexing run started on `index_dir` and never finished, so
+/// the index may lack its last changes.
+pub fn last_run_interrupted(index_dir: &Path) -> bool {
+    index_dir.join(RUN_MARKER_FILE).exists()
+}
+
+fn begin_run(index_dir: &Path) -> Result<()> {
+    let path = index_dir.join(RUN_MARKER_FILE);
+    fs::write(&path, b"").with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Removes the run marker once the commit, manifest and checkpoints are
+/// all on disk.
+fn finish_run(index_dir: &Path) -> Result<()> {
+    let path = index_dir.join(RUN_MARKER_FILE);
+    fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))
+}
 
-    let data = serde_json::to_vec(manifest).context("failed to serialize manifest")?;
-    fs::write(path, data)
-        .with_context(|| format!("failed to write manifest at {}", path.display()))?;
-    Ok(())
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
 }
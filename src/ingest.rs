@@ -1,23 +1,55 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::time::UNIX_EPOCH;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use blake3::Hasher;
 use content_inspector::{inspect, ContentType};
+use flate2::read::MultiGzDecoder;
+use html2text::render::text_renderer::TrivialDecorator;
+use ignore::gitignore::GitignoreBuilder;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{Match, WalkBuilder};
 use once_cell::sync::Lazy;
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
+use rayon::iter::{ParallelBridge, ParallelIterator};
 use regex::Regex;
+use reqwest::header;
+use reqwest::StatusCode;
+use rusqlite::types::ValueRef;
+use rusqlite::OpenFlags;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use walkdir::WalkDir;
 
-use crate::config::{AppConfig, SourceConfig};
+use crate::config::{AppConfig, FieldExclude, HtmlLinks, SourceConfig, TitleFrom};
+use crate::mail::{self, Mail};
+use crate::progress::Progress;
+use crate::search::parse_timestamp;
 
 static HTML_TITLE_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid html title regex"));
+static HTML_TAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<[^>]*>").expect("valid html tag regex"));
+static SUBTITLE_MARKUP_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<[^>]*>|\{\\[^}]*\}").expect("valid subtitle markup regex"));
+
+const JSONL_CHECKPOINTS_FILE: &str = "jsonl_checkpoints.json";
+const JSONL_TAIL_HASH_BYTES: u64 = 4096;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Filesystem files are read in chunks of this size, and the first chunk
+/// alone decides whether a file is binary.
+const FILE_READ_CHUNK_BYTES: u64 = 64 * 1024;
+/// First-line titles are cut here, as a text file's first line may be a
+/// whole paragraph.
+const MAX_LINE_TITLE_CHARS: usize = 200;
 
 static DEFAULT_TEXT_EXTENSIONS: &[&str] = &[
     "txt", "md", "markdown", "rst", "org", "tex", "html", "htm", "xhtml", "xml", "json", "jsonl",
@@ -33,35 +65,90 @@ pub struct RawDocument {
     pub preview: String,
     pub location: String,
     pub url: Option<String>,
+    /// Unix timestamp (seconds) used for date-range filtering, if known.
+    pub date: Option<i64>,
     pub fingerprint: String,
+    /// Lowercased topic tags, such as Markdown front matter `tags`.
+    pub tags: Vec<String>,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone)]
 pub struct IngestStats {
     pub scanned: u64,
     pub emitted: u64,
     pub skipped: u64,
+    /// Remote sources that could not be fetched (their documents are kept).
+    pub failed: u64,
+    /// Doc id prefixes whose previously indexed documents were not re-read
+    /// (append-only resume) and must be kept as-is.
+    pub retained_prefixes: Vec<String>,
+    /// Per-source breakdown, in config order.
+    pub sources: Vec<SourceStats>,
 }
 
-pub fn ingest_sources<F>(config: &AppConfig, mut on_doc: F) -> Result<IngestStats>
+/// Counters of one source in an ingest pass. `elapsed` includes the time
+/// spent handing its documents to the indexer.
+#[derive(Debug, Clone)]
+pub struct SourceStats {
+    pub name: String,
+    pub elapsed: Duration,
+    pub scanned: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+pub fn ingest_sources<F>(
+    config: &AppConfig,
+    checkpoints: &mut JsonlCheckpoints,
+    http_validators: &mut HttpValidators,
+    progress: &Progress,
+    mut on_doc: F,
+) -> Result<IngestStats>
 where
     F: FnMut(RawDocument) -> Result<()>,
 {
     let mut total = IngestStats::default();
 
+    http_validators.sources.retain(|name, _| {
+        config
+            .sources
+            .iter()
+            .any(|source| matches!(source, SourceConfig::HttpJsonl { .. }) && source.name() == name)
+    });
+
     for source in &config.sources {
+        let expected = if progress.wants_totals() {
+            source_total(source)
+        } else {
+            None
+        };
+        progress.start_source(source.name(), expected);
+        let started = Instant::now();
+
         let source_stats = match source {
             SourceConfig::Filesystem {
                 name,
                 path,
                 extensions,
                 follow_symlinks,
+                ignore,
+                respect_gitignore,
+                exclude,
+                title_from,
+                ..
             } => ingest_filesystem(
                 config,
                 name,
-                path,
+                &FilesystemWalker::new(
+                    path,
+                    *follow_symlinks,
+                    ignore,
+                    *respect_gitignore,
+                    exclude,
+                )?,
                 extensions,
-                *follow_symlinks,
+                *title_from,
+                progress,
                 &mut on_doc,
             )?,
             SourceConfig::Jsonl {
@@ -71,35 +158,194 @@ where
                 title_field,
                 body_field,
                 url_field,
+                date_field,
+                tags_field,
+                append_only,
+                exclude,
+                ..
             } => ingest_jsonl(
                 config,
                 name,
                 path,
-                id_field.as_deref(),
-                title_field.as_deref(),
-                body_field.as_deref(),
-                url_field.as_deref(),
+                &JsonlFields::new(
+                    id_field.as_deref(),
+                    title_field.as_deref(),
+                    body_field.as_deref(),
+                    url_field.as_deref(),
+                    date_field.as_deref(),
+                    tags_field.as_deref(),
+                    exclude,
+                ),
+                append_only.then_some(&mut *checkpoints),
+                progress,
+                &mut on_doc,
+            )?,
+            SourceConfig::StackExchangeXml { name, path, .. } => {
+                ingest_stackexchange_xml(config, name, path, progress, &mut on_doc)?
+            }
+            SourceConfig::Pdf { name, path, .. } => {
+                ingest_pdf(config, name, path, progress, &mut on_doc)?
+            }
+            SourceConfig::Epub { name, path, .. } => {
+                ingest_epub(config, name, path, progress, &mut on_doc)?
+            }
+            SourceConfig::Mbox { name, path, .. } => {
+                ingest_mbox(config, name, path, progress, &mut on_doc)?
+            }
+            SourceConfig::Office { name, path, .. } => {
+                ingest_office(config, name, path, progress, &mut on_doc)?
+            }
+            SourceConfig::Subtitles {
+                name,
+                path,
+                per_cue,
+                ..
+            } => ingest_subtitles(config, name, path, *per_cue, progress, &mut on_doc)?,
+            SourceConfig::HttpJsonl {
+                name,
+                url,
+                id_field,
+                title_field,
+                body_field,
+                url_field,
+                date_field,
+                tags_field,
+                exclude,
+                ..
+            } => ingest_http_jsonl(
+                config,
+                name,
+                url,
+                &JsonlFields::new(
+                    id_field.as_deref(),
+                    title_field.as_deref(),
+                    body_field.as_deref(),
+                    url_field.as_deref(),
+                    date_field.as_deref(),
+                    tags_field.as_deref(),
+                    exclude,
+                ),
+                http_validators,
+                progress,
+                &mut on_doc,
+            )?,
+            SourceConfig::Csv {
+                name,
+                path,
+                id_column,
+                title_column,
+                body_columns,
+                delimiter,
+                ..
+            } => ingest_csv(
+                config,
+                name,
+                path,
+                &CsvColumns {
+                    id: id_column.as_deref(),
+                    title: title_column.as_deref(),
+                    body: body_columns,
+                },
+                *delimiter,
+                progress,
+                &mut on_doc,
+            )?,
+            SourceConfig::Sqlite {
+                name,
+                path,
+                query,
+                id_column,
+                title_column,
+                body_column,
+                ..
+            } => ingest_sqlite(
+                config,
+                name,
+                path,
+                query,
+                &SqliteColumns {
+                    id: id_column.as_deref(),
+                    title: title_column.as_deref(),
+                    body: body_column.as_deref(),
+                },
+                progress,
                 &mut on_doc,
             )?,
-            SourceConfig::StackExchangeXml { name, path } => {
-                ingest_stackexchange_xml(config, name, path, &mut on_doc)?
+            SourceConfig::Feed { name, path, .. } => {
+                ingest_feeds(config, name, path, progress, &mut on_doc)?
             }
         };
+        progress.finish_source(&source_stats);
+        total.sources.push(SourceStats {
+            name: source.name().to_string(),
+            elapsed: started.elapsed(),
+            scanned: source_stats.scanned,
+            skipped: source_stats.skipped,
+            failed: source_stats.failed,
+        });
 
         total.scanned += source_stats.scanned;
         total.emitted += source_stats.emitted;
         total.skipped += source_stats.skipped;
+        total.failed += source_stats.failed;
+        total
+            .retained_prefixes
+            .extend(source_stats.retained_prefixes);
     }
 
     Ok(total)
 }
 
+/// Number of items a source will scan, where that is cheap to know up front:
+/// files under a filesystem root, or lines of a JSONL file read in full.
+fn source_total(source: &SourceConfig) -> Option<u64> {
+    match source {
+        SourceConfig::Filesystem {
+            path,
+            follow_symlinks,
+            ignore,
+            respect_gitignore,
+            exclude,
+            ..
+        } => Some(
+            FilesystemWalker::new(path, *follow_symlinks, ignore, *respect_gitignore, exclude)
+                .ok()?
+                .files(path)
+                .count() as u64,
+        ),
+        SourceConfig::Jsonl {
+            path,
+            append_only: false,
+            ..
+        } => {
+            let mut file = File::open(path).ok()?;
+            let gzip = is_gzip(path, &mut file).ok()?;
+            let mut reader = buffered_source(file, gzip);
+            let mut lines = 0u64;
+            let mut ends_with_newline = true;
+            loop {
+                let buf = reader.fill_buf().ok()?;
+                if buf.is_empty() {
+                    break;
+                }
+                lines += buf.iter().filter(|byte| **byte == b'\n').count() as u64;
+                ends_with_newline = buf.last() == Some(&b'\n');
+                let len = buf.len();
+                reader.consume(len);
+            }
+            Some(lines + u64::from(!ends_with_newline))
+        }
+        _ => None,
+    }
+}
+
 fn ingest_filesystem<F>(
     config: &AppConfig,
     source_name: &str,
-    root: &Path,
+    walker: &FilesystemWalker,
     extensions: &[String],
-    follow_symlinks: bool,
+    title_from: TitleFrom,
+    progress: &Progress,
     on_doc: &mut F,
 ) -> Result<IngestStats>
 where
@@ -107,110 +353,2654 @@ where
 {
     let mut stats = IngestStats::default();
 
-    let whitelist: Vec<String> = if extensions.is_empty() {
+    let whitelist = extension_whitelist(extensions);
+
+    let threads = config.ingest_threads.max(1);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .context("failed to build ingest thread pool")?;
+
+    // Files are read and converted on the pool; outcomes come back over a
+    // bounded channel so `on_doc` (and the index writer) stays on this thread.
+    // They arrive in whatever order the workers finish, not walk order, so
+    // nothing downstream may depend on the order of a source's documents.
+    let (sender, receiver) = mpsc::sync_channel::<FileOutcome>(threads * 4);
+
+    let root = walker.root();
+    thread::scope(|scope| -> Result<()> {
+        let whitelist = &whitelist;
+        scope.spawn(move || {
+            pool.install(|| {
+                let _ =
+                    walker
+                        .entries(root)
+                        .par_bridge()
+                        .try_for_each_with(sender, |sender, entry| {
+                            let outcome = match entry {
+                                Err(err) => {
+                                    tracing::warn!(%err, "walkdir entry error");
+                                    FileOutcome::Unreadable(err.into())
+                                }
+                                Ok(path) if walker.is_excluded(&path) => FileOutcome::Skipped,
+                                Ok(path) => read_filesystem_file(
+                                    config,
+                                    source_name,
+                                    root,
+                                    &path,
+                                    whitelist,
+                                    title_from,
+                                ),
+                            };
+                            sender.send(outcome).map_err(|_| ())
+                        });
+            });
+        });
+
+        for outcome in receiver {
+            stats.scanned += 1;
+            progress.observe(&stats);
+            match outcome {
+                FileOutcome::Skipped => stats.skipped += 1,
+                FileOutcome::Unreadable(err) => {
+                    if config.strict_ingest {
+                        return Err(err);
+                    }
+                    stats.skipped += 1;
+                }
+                FileOutcome::Emitted(doc) => {
+                    on_doc(*doc)?;
+                    stats.emitted += 1;
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(stats)
+}
+
+/// Walks a filesystem source, skipping its `ignore` globs and, with
+/// `respect_gitignore`, whatever `.gitignore`/`.ignore` files exclude plus
+/// `.git` directories. Skipped entries are never read or counted.
+pub struct FilesystemWalker {
+    root: PathBuf,
+    follow_symlinks: bool,
+    overrides: Override,
+    respect_gitignore: bool,
+    /// `exclude` globs, as a whitelist of negations like `overrides`.
+    exclude: Override,
+}
+
+impl FilesystemWalker {
+    pub fn new(
+        root: &Path,
+        follow_symlinks: bool,
+        ignore: &[String],
+        respect_gitignore: bool,
+        exclude: &[String],
+    ) -> Result<Self> {
+        // Override globs are a whitelist; `!` turns each into an exclusion.
+        let mut overrides = OverrideBuilder::new(root);
+        for pattern in ignore {
+            overrides
+                .add(&format!("!{pattern}"))
+                .with_context(|| format!("invalid ignore pattern `{pattern}`"))?;
+        }
+        let mut excludes = OverrideBuilder::new(root);
+        for pattern in exclude {
+            excludes
+                .add(&format!("!{pattern}"))
+                .with_context(|| format!("invalid exclude pattern `{pattern}`"))?;
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            follow_symlinks,
+            overrides: overrides.build().context("invalid ignore patterns")?,
+            respect_gitignore,
+            exclude: excludes.build().context("invalid exclude patterns")?,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Files under `dir` (the root or a directory below it) that are not
+    /// ignored. Entries that cannot be walked are logged and left out.
+    pub fn files(&self, dir: &Path) -> impl Iterator<Item = PathBuf> {
+        self.entries(dir).filter_map(|entry| match entry {
+            Ok(path) => Some(path),
+            Err(err) => {
+                tracing::warn!(%err, "walkdir entry error");
+                None
+            }
+        })
+    }
+
+    /// Like `files`, but also yields the errors of entries that cannot be
+    /// walked.
+    fn entries(&self, dir: &Path) -> impl Iterator<Item = Result<PathBuf, ignore::Error>> {
+        let mut builder = WalkBuilder::new(dir);
+        builder
+            .standard_filters(false)
+            .follow_links(self.follow_symlinks)
+            .overrides(self.overrides.clone());
+        if self.respect_gitignore {
+            builder
+                .git_ignore(true)
+                .git_exclude(true)
+                .ignore(true)
+                .parents(true)
+                .require_git(false)
+                .filter_entry(|entry| entry.file_name() != ".git");
+        }
+
+        builder
+            .build()
+            .filter(|entry| {
+                entry.as_ref().map_or(true, |entry| {
+                    entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file())
+                })
+            })
+            .map(|entry| entry.map(|entry| entry.into_path()))
+    }
+
+    /// Whether a walked file matches an `exclude` glob, so it is counted as
+    /// skipped instead of read.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.matched(path, false).is_ignore()
+    }
+
+    /// Whether a single path, e.g. from a watch event, is skipped by the
+    /// walk because of itself or one of its parent directories.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+
+        let mut candidates = path
+            .ancestors()
+            .take_while(|candidate| *candidate != self.root)
+            .enumerate();
+        if candidates.any(|(depth, candidate)| {
+            self.overrides
+                .matched(candidate, depth > 0 || is_dir)
+                .is_ignore()
+                || (self.respect_gitignore && candidate.file_name() == Some(".git".as_ref()))
+        }) {
+            return true;
+        }
+        if !self.respect_gitignore {
+            return false;
+        }
+
+        // The closest ignore file with an opinion decides.
+        for dir in path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+        {
+            let mut builder = GitignoreBuilder::new(dir);
+            for name in [".gitignore", ".ignore"] {
+                let file = dir.join(name);
+                if file.is_file() {
+                    builder.add(file);
+                }
+            }
+            let Ok(gitignore) = builder.build() else {
+                continue;
+            };
+            match gitignore.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// Re-reads a single file of a filesystem source, as `ingest_filesystem`
+/// would. Returns `None` when the file is missing, filtered or empty.
+pub fn read_filesystem_path(
+    config: &AppConfig,
+    source_name: &str,
+    root: &Path,
+    extensions: &[String],
+    title_from: TitleFrom,
+    path: &Path,
+) -> Option<RawDocument> {
+    if !path.is_file() {
+        return None;
+    }
+
+    match read_filesystem_file(
+        config,
+        source_name,
+        root,
+        path,
+        &extension_whitelist(extensions),
+        title_from,
+    ) {
+        FileOutcome::Emitted(doc) => Some(*doc),
+        FileOutcome::Skipped | FileOutcome::Unreadable(_) => None,
+    }
+}
+
+/// The doc id a filesystem source assigns to `path`, whether or not the
+/// file still exists.
+pub fn filesystem_doc_id(source_name: &str, root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let rel_str = rel.to_string_lossy().replace('\\', "/");
+    format!("fs:{source_name}:{rel_str}")
+}
+
+fn extension_whitelist(extensions: &[String]) -> Vec<String> {
+    if extensions.is_empty() {
         DEFAULT_TEXT_EXTENSIONS
             .iter()
             .map(|ext| (*ext).to_string())
             .collect()
     } else {
         extensions.iter().map(|ext| ext.to_lowercase()).collect()
+    }
+}
+
+/// Under `strict_ingest` (`index --strict`), turns a problem that is
+/// otherwise logged and skipped into an error that aborts the run.
+fn fail_if_strict(config: &AppConfig, problem: impl FnOnce() -> String) -> Result<()> {
+    if config.strict_ingest {
+        bail!(problem());
+    }
+    Ok(())
+}
+
+fn unreadable_file(path: &Path, err: impl Into<anyhow::Error>) -> anyhow::Error {
+    err.into()
+        .context(format!("unable to read {}", path.display()))
+}
+
+enum FileOutcome {
+    Skipped,
+    /// The file could not be read; fatal under `strict_ingest`.
+    Unreadable(anyhow::Error),
+    Emitted(Box<RawDocument>),
+}
+
+fn read_filesystem_file(
+    config: &AppConfig,
+    source_name: &str,
+    root: &Path,
+    path: &Path,
+    whitelist: &[String],
+    title_from: TitleFrom,
+) -> FileOutcome {
+    if !is_extension_allowed(path, whitelist) {
+        return FileOutcome::Skipped;
+    }
+
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > config.max_file_bytes => {
+            tracing::warn!(
+                path = %path.display(),
+                bytes = metadata.len(),
+                max_file_bytes = config.max_file_bytes,
+                "skipping file larger than max_file_bytes"
+            );
+            return FileOutcome::Skipped;
+        }
+        Ok(_) => {}
+        Err(err) => {
+            tracing::warn!(path = %path.display(), %err, "unable to read file");
+            return FileOutcome::Unreadable(unreadable_file(path, err));
+        }
+    }
+
+    // HTML is converted as a whole document; plain text only needs as much
+    // as will be indexed.
+    let ext = file_extension(path).unwrap_or_default();
+    let max_chars = (!is_html_ext(&ext)).then_some(config.max_indexed_chars);
+    let force_text = config.always_text_extensions.contains(&ext);
+    let bytes = match read_text_file(path, max_chars, force_text) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return FileOutcome::Skipped,
+        Err(err) => {
+            tracing::warn!(path = %path.display(), %err, "unable to read file");
+            return FileOutcome::Unreadable(unreadable_file(path, err));
+        }
     };
 
-    for entry in WalkDir::new(root)
-        .follow_links(follow_symlinks)
-        .into_iter()
-        .filter_map(|entry| match entry {
-            Ok(entry) => Some(entry),
-            Err(err) => {
-                tracing::warn!(%err, "walkdir entry error");
-                None
+    let mut raw_text = String::from_utf8_lossy(&bytes).into_owned();
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+    let mut front_matter = FrontMatter::default();
+    if matches!(ext.as_str(), "md" | "markdown") {
+        if let Some((parsed, body)) = split_front_matter(&raw_text) {
+            front_matter = parsed;
+            raw_text = body.to_string();
+        }
+    }
+
+    let (mut title, body_source) = if is_html_ext(&ext) {
+        let extracted_title = extract_html_title(&raw_text)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| path_to_title(rel));
+        let body = html_to_text(config, raw_text.as_bytes());
+        (extracted_title, body)
+    } else {
+        let title = match front_matter.title.take() {
+            Some(title) if title_from != TitleFrom::Filename => title,
+            _ => text_title(&raw_text, &ext, title_from).unwrap_or_else(|| path_to_title(rel)),
+        };
+        (title, raw_text)
+    };
+
+    title = normalize_whitespace(&title);
+    if title.is_empty() {
+        title = rel_str.clone();
+    }
+
+    let body = truncate_chars(
+        &normalize_whitespace(&body_source),
+        config.max_indexed_chars,
+    );
+    if body.is_empty() {
+        return FileOutcome::Skipped;
+    }
+
+    let fingerprint = fingerprint_for_file(path).unwrap_or_else(|_| "0:0".to_string());
+
+    FileOutcome::Emitted(Box::new(RawDocument {
+        doc_id: filesystem_doc_id(source_name, root, path),
+        source: source_name.to_string(),
+        title,
+        preview: preview_from_text(&body, 280),
+        body,
+        location: rel_str,
+        url: None,
+        date: front_matter.date.or_else(|| file_mtime(path)),
+        fingerprint,
+        tags: front_matter.tags,
+    }))
+}
+
+/// Metadata read from the YAML front matter of a Markdown file.
+#[derive(Debug, Default)]
+struct FrontMatter {
+    title: Option<String>,
+    date: Option<i64>,
+    tags: Vec<String>,
+}
+
+/// Splits a leading `---` fenced YAML block off `text`, returning its
+/// `title`, `date` and `tags` and the text after the closing `---` (or
+/// `...`). `None` when there is no such block or it isn't a YAML mapping,
+/// so the text is indexed unchanged.
+fn split_front_matter(text: &str) -> Option<(FrontMatter, &str)> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let (first_line, rest) = text.split_once('\n')?;
+    if first_line.trim_end() != "---" {
+        return None;
+    }
+
+    let mut yaml_len = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            let yaml: serde_yaml::Value = serde_yaml::from_str(&rest[..yaml_len]).ok()?;
+            let front_matter = match yaml {
+                serde_yaml::Value::Mapping(mapping) => FrontMatter {
+                    title: mapping
+                        .get("title")
+                        .and_then(serde_yaml::Value::as_str)
+                        .map(normalize_whitespace)
+                        .filter(|title| !title.is_empty()),
+                    date: mapping.get("date").and_then(|date| match date {
+                        serde_yaml::Value::Number(secs) => secs.as_i64(),
+                        serde_yaml::Value::String(date) => parse_timestamp(date),
+                        _ => None,
+                    }),
+                    tags: mapping
+                        .get("tags")
+                        .map(front_matter_tags)
+                        .unwrap_or_default(),
+                },
+                serde_yaml::Value::Null => FrontMatter::default(),
+                _ => return None,
+            };
+            return Some((front_matter, &rest[yaml_len + line.len()..]));
+        }
+        yaml_len += line.len();
+    }
+    None
+}
+
+/// Tags from a YAML list (`[a, b]` or one `- a` per line) or a
+/// comma-separated string.
+fn front_matter_tags(value: &serde_yaml::Value) -> Vec<String> {
+    normalize_tags(match value {
+        serde_yaml::Value::Sequence(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                serde_yaml::Value::String(tag) => Some(tag.clone()),
+                serde_yaml::Value::Number(number) => Some(number.to_string()),
+                _ => None,
+            })
+            .collect(),
+        serde_yaml::Value::String(tags) => tags.split(',').map(str::to_string).collect(),
+        _ => Vec::new(),
+    })
+}
+
+/// Tags from a JSON array of strings (or numbers) or a comma-separated
+/// string.
+fn json_tags(value: &Value) -> Vec<String> {
+    normalize_tags(match value {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                Value::String(tag) => Some(tag.clone()),
+                Value::Number(number) => Some(number.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Value::String(tags) => tags.split(',').map(str::to_string).collect(),
+        _ => Vec::new(),
+    })
+}
+
+/// Trims, lowercases and deduplicates tags, dropping empty ones.
+fn normalize_tags(raw: Vec<String>) -> Vec<String> {
+    let mut tags = Vec::new();
+    for tag in raw {
+        let tag = normalize_whitespace(&tag).to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Converts HTML to text wrapped at `[html] wrap_width`, keeping or stripping
+/// link targets as configured.
+fn html_to_text(config: &AppConfig, html: &[u8]) -> String {
+    match config.html.links {
+        HtmlLinks::Keep => html2text::from_read(html, config.html.wrap_width),
+        HtmlLinks::Strip => html2text::from_read_with_decorator(
+            html,
+            config.html.wrap_width,
+            TrivialDecorator::new(),
+        ),
+    }
+}
+
+/// Reads a text file chunk by chunk as UTF-8, or returns `None` when the
+/// first chunk looks binary (unless `force_text`). With `max_chars`, reading
+/// stops once the file has yielded that many characters of
+/// whitespace-collapsed text, so a huge log costs no more memory than the
+/// part of it that gets indexed. UTF-16 files are read whole and converted.
+fn read_text_file(
+    path: &Path,
+    max_chars: Option<usize>,
+    force_text: bool,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let mut bytes = Vec::new();
+    let mut chars = 0;
+    let mut last_was_space = false;
+
+    loop {
+        let start = bytes.len();
+        let read = (&mut file)
+            .take(FILE_READ_CHUNK_BYTES)
+            .read_to_end(&mut bytes)?;
+        if read == 0 {
+            return Ok(Some(bytes));
+        }
+        if start == 0 {
+            match sniff_text_encoding(&bytes, force_text) {
+                None => return Ok(None),
+                Some((TextEncoding::Utf8, bom_len)) => {
+                    bytes.drain(..bom_len);
+                }
+                Some((TextEncoding::Utf16 { little_endian }, bom_len)) => {
+                    file.read_to_end(&mut bytes)?;
+                    let text = decode_utf16(&bytes[bom_len..], little_endian);
+                    return Ok(Some(text.into_bytes()));
+                }
             }
-        })
-    {
-        if !entry.file_type().is_file() {
+        }
+
+        let Some(max_chars) = max_chars else {
             continue;
+        };
+        // Counts UTF-8 lead bytes, with a run of ASCII whitespace as one
+        // character, the way `normalize_whitespace` will collapse it.
+        for &byte in &bytes[start..] {
+            if byte.is_ascii_whitespace() {
+                chars += usize::from(!last_was_space);
+                last_was_space = true;
+            } else if byte & 0xc0 != 0x80 {
+                chars += 1;
+                last_was_space = false;
+            }
+        }
+        if chars > max_chars {
+            // Drop a character cut in half by the chunk boundary.
+            if let Err(err) = std::str::from_utf8(&bytes) {
+                if err.error_len().is_none() {
+                    bytes.truncate(err.valid_up_to());
+                }
+            }
+            return Ok(Some(bytes));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextEncoding {
+    Utf8,
+    Utf16 { little_endian: bool },
+}
+
+/// The encoding of a file from its first chunk, with the length of its
+/// byte-order mark; `None` for binary content. A byte-order mark wins over
+/// the content sniff, which flags UTF-16 as binary for its NUL bytes. With
+/// `force_text`, binary-looking content is guessed to be BOM-less UTF-16 when
+/// every other byte is NUL, and UTF-8 otherwise.
+fn sniff_text_encoding(head: &[u8], force_text: bool) -> Option<(TextEncoding, usize)> {
+    if head.starts_with(&[0xef, 0xbb, 0xbf]) {
+        return Some((TextEncoding::Utf8, 3));
+    }
+    if head.starts_with(&[0xff, 0xfe]) {
+        return Some((
+            TextEncoding::Utf16 {
+                little_endian: true,
+            },
+            2,
+        ));
+    }
+    if head.starts_with(&[0xfe, 0xff]) {
+        return Some((
+            TextEncoding::Utf16 {
+                little_endian: false,
+            },
+            2,
+        ));
+    }
+    if !matches!(inspect(head), ContentType::BINARY) {
+        return Some((TextEncoding::Utf8, 0));
+    }
+    if !force_text {
+        return None;
+    }
+
+    // Mostly-ASCII UTF-16 has a NUL as the high byte of nearly every unit.
+    let nuls_at = |parity: usize| {
+        head.iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|&&byte| byte == 0)
+            .count()
+    };
+    let (even, odd) = (nuls_at(0), nuls_at(1));
+    let units = head.len() / 2;
+    let encoding = if odd > units / 2 && even < units / 10 {
+        TextEncoding::Utf16 {
+            little_endian: true,
+        }
+    } else if even > units / 2 && odd < units / 10 {
+        TextEncoding::Utf16 {
+            little_endian: false,
         }
+    } else {
+        TextEncoding::Utf8
+    };
+    Some((encoding, 0))
+}
 
-        stats.scanned += 1;
+fn decode_utf16(bytes: &[u8], little_endian: bool) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| {
+        if little_endian {
+            u16::from_le_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_be_bytes([pair[0], pair[1]])
+        }
+    });
+    char::decode_utf16(units)
+        .map(|unit| unit.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
 
-        let path = entry.path();
-        if !is_extension_allowed(path, &whitelist) {
-            stats.skipped += 1;
-            continue;
+/// Field names of a JSONL source, with the conventional defaults applied.
+struct JsonlFields<'a> {
+    id: &'a str,
+    title: &'a str,
+    body: &'a str,
+    url: &'a str,
+    date: Option<&'a str>,
+    tags: Option<&'a str>,
+    exclude: &'a [FieldExclude],
+}
+
+impl<'a> JsonlFields<'a> {
+    fn new(
+        id: Option<&'a str>,
+        title: Option<&'a str>,
+        body: Option<&'a str>,
+        url: Option<&'a str>,
+        date: Option<&'a str>,
+        tags: Option<&'a str>,
+        exclude: &'a [FieldExclude],
+    ) -> Self {
+        Self {
+            id: id.unwrap_or("id"),
+            title: title.unwrap_or("title"),
+            body: body.unwrap_or("body"),
+            url: url.unwrap_or("url"),
+            date,
+            tags,
+            exclude,
+        }
+    }
+
+    /// Whether `record` matches one of the source's `exclude` rules.
+    fn excludes(&self, record: &Value) -> bool {
+        self.exclude.iter().any(|rule| {
+            value_to_string(record.get(&rule.field)).is_some_and(|value| value == rule.value)
+        })
+    }
+}
+
+fn ingest_jsonl<F>(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    fields: &JsonlFields<'_>,
+    checkpoints: Option<&mut JsonlCheckpoints>,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    let mut file = File::open(path)
+        .with_context(|| format!("failed to open JSONL source {}", path.display()))?;
+
+    let gzip = is_gzip(path, &mut file)?;
+    let checkpoints = match checkpoints {
+        Some(_) if gzip => {
+            tracing::info!(
+                source = source_name,
+                "append_only resume is not supported for gzip sources; reading the whole file"
+            );
+            None
+        }
+        other => other,
+    };
+
+    let resume = match checkpoints.as_deref() {
+        Some(checkpoints) => checkpoints.resume_point(source_name, path, &mut file)?,
+        None => None,
+    };
+    let start = match &resume {
+        Some(checkpoint) => {
+            file.seek(SeekFrom::Start(checkpoint.offset))
+                .with_context(|| format!("failed to seek in {}", path.display()))?;
+            stats
+                .retained_prefixes
+                .push(format!("jsonl:{source_name}:"));
+            (checkpoint.offset, checkpoint.lines as usize)
         }
+        None => (0u64, 0usize),
+    };
+
+    let mut reader = buffered_source(file, gzip);
+    let read = read_jsonl_lines(
+        config,
+        source_name,
+        &path.display().to_string(),
+        &mut reader,
+        fields,
+        start,
+        progress,
+        on_doc,
+        &mut stats,
+    )?;
+
+    if let Some(checkpoints) = checkpoints {
+        checkpoints.record(
+            source_name,
+            path,
+            read.complete_offset,
+            read.complete_lines as u64,
+        )?;
+    }
+
+    Ok(stats)
+}
+
+/// Where a pass over JSONL lines stopped.
+struct JsonlRead {
+    /// Offset and count of the last newline-terminated line, i.e. where a
+    /// later run can safely resume.
+    complete_offset: u64,
+    complete_lines: usize,
+    /// The input failed before its end.
+    interrupted: bool,
+}
+
+/// Emits one document per JSONL line of `reader`, which is positioned at
+/// byte `start.0` / line `start.1` of `location`.
+#[allow(clippy::too_many_arguments)]
+fn read_jsonl_lines<F>(
+    config: &AppConfig,
+    source_name: &str,
+    location: &str,
+    reader: &mut dyn BufRead,
+    fields: &JsonlFields<'_>,
+    start: (u64, usize),
+    progress: &Progress,
+    on_doc: &mut F,
+    stats: &mut IngestStats,
+) -> Result<JsonlRead>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let (mut offset, mut line_idx) = start;
+    let (mut complete_offset, mut complete_lines) = start;
+    let mut interrupted = false;
+
+    let mut raw_line = Vec::new();
+    loop {
+        raw_line.clear();
+        let read = match reader.read_until(b'\n', &mut raw_line) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) => {
+                tracing::warn!(location, line = line_idx + 1, %err, "failed to read JSONL line");
+                fail_if_strict(config, || {
+                    format!("failed to read line {} of {location}: {err}", line_idx + 1)
+                })?;
+                stats.skipped += 1;
+                interrupted = true;
+                break;
+            }
+        };
+
+        offset += read as u64;
+        line_idx += 1;
+        stats.scanned += 1;
+        progress.observe(stats);
+        if raw_line.last() == Some(&b'\n') {
+            complete_offset = offset;
+            complete_lines = line_idx;
+            raw_line.pop();
+            if raw_line.last() == Some(&b'\r') {
+                raw_line.pop();
+            }
+        }
+
+        let line = match std::str::from_utf8(&raw_line) {
+            Ok(line) => line,
+            Err(err) => {
+                tracing::warn!(location, line = line_idx, %err, "failed to read JSONL line");
+                fail_if_strict(config, || {
+                    format!("failed to read line {line_idx} of {location}: {err}")
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        if line.trim().is_empty() {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let parsed: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::warn!(location, line = line_idx, %err, "invalid JSONL object");
+                fail_if_strict(config, || {
+                    format!("invalid JSONL object on line {line_idx} of {location}: {err}")
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        if fields.excludes(&parsed) {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let id = value_to_string(parsed.get(fields.id)).unwrap_or_else(|| line_idx.to_string());
+        let mut title =
+            value_to_string(parsed.get(fields.title)).unwrap_or_else(|| format!("Document {id}"));
+        let body = value_to_string(parsed.get(fields.body)).unwrap_or_default();
+        let url = value_to_string(parsed.get(fields.url)).filter(|value| !value.trim().is_empty());
+
+        let body = truncate_chars(&normalize_whitespace(&body), config.max_indexed_chars);
+        if body.is_empty() {
+            stats.skipped += 1;
+            continue;
+        }
+
+        title = normalize_whitespace(&title);
+        if title.is_empty() {
+            title = format!("Document {id}");
+        }
+
+        let mut hasher = Hasher::new();
+        hasher.update(line.as_bytes());
+
+        let doc = RawDocument {
+            doc_id: format!("jsonl:{source_name}:{id}"),
+            source: source_name.to_string(),
+            title,
+            preview: preview_from_text(&body, 280),
+            body,
+            location: format!("{location}:{line_idx}"),
+            url,
+            date: fields
+                .date
+                .and_then(|field| parsed.get(field))
+                .and_then(value_to_timestamp),
+            fingerprint: hasher.finalize().to_hex().to_string(),
+            tags: fields
+                .tags
+                .and_then(|field| parsed.get(field))
+                .map(json_tags)
+                .unwrap_or_default(),
+        };
+
+        on_doc(doc)?;
+        stats.emitted += 1;
+    }
+
+    Ok(JsonlRead {
+        complete_offset,
+        complete_lines,
+        interrupted,
+    })
+}
+
+/// Conditional-GET validators of remote sources, keyed by source name.
+/// Kept in the manifest so an unchanged download is not re-read.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HttpValidators {
+    sources: BTreeMap<String, HttpValidator>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HttpValidator {
+    url: String,
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+}
+
+impl HttpValidators {
+    /// Drops the validators of a source whose documents were deleted, so
+    /// its next fetch is unconditional.
+    pub fn forget(&mut self, source_name: &str) {
+        self.sources.remove(source_name);
+    }
+}
+
+/// Streams a remote JSONL dump (optionally gzip-compressed) through the same
+/// line handling as local files. Fetch failures are logged and counted, and
+/// the source's previously indexed documents are kept.
+#[allow(clippy::too_many_arguments)]
+fn ingest_http_jsonl<F>(
+    config: &AppConfig,
+    source_name: &str,
+    url: &str,
+    fields: &JsonlFields<'_>,
+    validators: &mut HttpValidators,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+    let prefix = format!("jsonl:{source_name}:");
+
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .timeout(None)
+        .build()
+        .context("failed to build HTTP client")?;
+
+    let previous = validators
+        .sources
+        .get(source_name)
+        .filter(|previous| previous.url == url);
+    let mut request = client.get(url);
+    if let Some(previous) = previous {
+        if let Some(etag) = &previous.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = match request
+        .send()
+        .and_then(|response| response.error_for_status())
+    {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::warn!(source = source_name, url, %err, "failed to fetch remote source; keeping its indexed documents");
+            fail_if_strict(config, || format!("failed to fetch {url}: {err}"))?;
+            stats.failed += 1;
+            stats.retained_prefixes.push(prefix);
+            return Ok(stats);
+        }
+    };
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        tracing::info!(source = source_name, url, "remote source unchanged");
+        stats.retained_prefixes.push(prefix);
+        return Ok(stats);
+    }
+
+    let header_value = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let validator = HttpValidator {
+        url: url.to_string(),
+        etag: header_value(header::ETAG),
+        last_modified: header_value(header::LAST_MODIFIED),
+    };
+
+    let mut body = BufReader::new(response);
+    let gzip = body
+        .fill_buf()
+        .is_ok_and(|head| head.starts_with(&GZIP_MAGIC));
+    let mut reader: Box<dyn BufRead> = if gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(body)))
+    } else {
+        Box::new(body)
+    };
+
+    let read = read_jsonl_lines(
+        config,
+        source_name,
+        url,
+        &mut reader,
+        fields,
+        (0, 0),
+        progress,
+        on_doc,
+        &mut stats,
+    )?;
+
+    if read.interrupted {
+        // Lines past the break were never seen; keep their documents and
+        // fetch the whole file again next time.
+        stats.failed += 1;
+        stats.retained_prefixes.push(prefix);
+        validators.sources.remove(source_name);
+    } else {
+        validators
+            .sources
+            .insert(source_name.to_string(), validator);
+    }
+
+    Ok(stats)
+}
+
+/// Per-source resume points for append-only JSONL files, persisted next to
+/// the manifest so re-indexing can seek past lines it has already seen.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JsonlCheckpoints {
+    sources: BTreeMap<String, JsonlCheckpoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JsonlCheckpoint {
+    path: PathBuf,
+    len: u64,
+    modified: u64,
+    offset: u64,
+    lines: u64,
+    tail_hash: String,
+}
+
+impl JsonlCheckpoints {
+    /// Drops the checkpoint of a source whose documents were deleted.
+    pub fn forget(&mut self, source_name: &str) {
+        self.sources.remove(source_name);
+    }
+
+    pub fn load(index_dir: &Path) -> Result<Self> {
+        let path = index_dir.join(JSONL_CHECKPOINTS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read JSONL checkpoints at {}", path.display()))?;
+        let checkpoints: JsonlCheckpoints = serde_json::from_str(&data)
+            .with_context(|| format!("failed to parse JSONL checkpoints at {}", path.display()))?;
+        Ok(checkpoints)
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        let path = index_dir.join(JSONL_CHECKPOINTS_FILE);
+        let data = serde_json::to_vec(self).context("failed to serialize JSONL checkpoints")?;
+        fs::write(&path, data)
+            .with_context(|| format!("failed to write JSONL checkpoints at {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Returns the stored checkpoint when the file looks like it was only
+    /// appended to since: same path, not shorter, and the bytes just before
+    /// the checkpoint are unchanged. Anything else means a full rescan.
+    fn resume_point(
+        &self,
+        source_name: &str,
+        path: &Path,
+        file: &mut File,
+    ) -> Result<Option<JsonlCheckpoint>> {
+        let Some(checkpoint) = self.sources.get(source_name) else {
+            return Ok(None);
+        };
+        if checkpoint.path != path {
+            return Ok(None);
+        }
+
+        let (len, modified) = file_len_and_mtime(path)?;
+        if len < checkpoint.len || checkpoint.offset > len {
+            return Ok(None);
+        }
+        if len == checkpoint.len && modified == checkpoint.modified {
+            return Ok(Some(checkpoint.clone()));
+        }
+
+        if tail_hash(file, checkpoint.offset)? != checkpoint.tail_hash {
+            return Ok(None);
+        }
+
+        Ok(Some(checkpoint.clone()))
+    }
+
+    fn record(&mut self, source_name: &str, path: &Path, offset: u64, lines: u64) -> Result<()> {
+        let (len, modified) = file_len_and_mtime(path)?;
+        let mut file = File::open(path)
+            .with_context(|| format!("failed to open JSONL source {}", path.display()))?;
+        let tail_hash = tail_hash(&mut file, offset)?;
+
+        self.sources.insert(
+            source_name.to_string(),
+            JsonlCheckpoint {
+                path: path.to_path_buf(),
+                len,
+                modified,
+                offset,
+                lines,
+                tail_hash,
+            },
+        );
+        Ok(())
+    }
+}
+
+fn file_len_and_mtime(path: &Path) -> Result<(u64, u64)> {
+    let meta =
+        fs::metadata(path).with_context(|| format!("metadata failed for {}", path.display()))?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|value| value.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    Ok((meta.len(), modified))
+}
+
+/// Hashes the bytes immediately preceding `offset`, as a cheap check that
+/// the already-indexed part of the file has not been rewritten.
+fn tail_hash(file: &mut File, offset: u64) -> Result<String> {
+    let start = offset.saturating_sub(JSONL_TAIL_HASH_BYTES);
+    file.seek(SeekFrom::Start(start))
+        .context("failed to seek in JSONL source")?;
+
+    let mut tail = Vec::with_capacity((offset - start) as usize);
+    file.take(offset - start)
+        .read_to_end(&mut tail)
+        .context("failed to read JSONL source")?;
+
+    let mut hasher = Hasher::new();
+    hasher.update(&tail);
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// True when `path` ends in `.gz` or the file starts with the gzip magic
+/// number. Leaves the file positioned at the start.
+fn is_gzip(path: &Path, file: &mut File) -> Result<bool> {
+    if file_extension(path).as_deref() == Some("gz") {
+        return Ok(true);
+    }
+
+    let mut magic = [0u8; 2];
+    let read = file
+        .read(&mut magic)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    file.seek(SeekFrom::Start(0))
+        .with_context(|| format!("failed to seek in {}", path.display()))?;
+    Ok(read == magic.len() && magic == GZIP_MAGIC)
+}
+
+/// Buffers `file`, decompressing it on the fly when it is gzip. Multi-member
+/// archives (e.g. concatenated log rotations) are read to the end.
+fn buffered_source(file: File, gzip: bool) -> Box<dyn BufRead> {
+    if gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    }
+}
+
+fn ingest_stackexchange_xml<F>(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    let mut file = File::open(path).with_context(|| {
+        format!(
+            "failed to open Stack Exchange XML source {}",
+            path.display()
+        )
+    })?;
+    let gzip = is_gzip(path, &mut file)?;
+    let mut reader = Reader::from_reader(buffered_source(file, gzip));
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Empty(tag)) if tag.name().as_ref() == b"row" => {
+                process_stackexchange_row(
+                    config,
+                    source_name,
+                    path,
+                    &tag,
+                    progress,
+                    on_doc,
+                    &mut stats,
+                )?;
+            }
+            Ok(Event::Start(tag)) if tag.name().as_ref() == b"row" => {
+                process_stackexchange_row(
+                    config,
+                    source_name,
+                    path,
+                    &tag,
+                    progress,
+                    on_doc,
+                    &mut stats,
+                )?;
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(err) => {
+                return Err(anyhow::anyhow!(
+                    "error while parsing {} at byte {}: {err}",
+                    path.display(),
+                    reader.buffer_position()
+                ));
+            }
+        }
+
+        buf.clear();
+    }
+
+    Ok(stats)
+}
+
+fn process_stackexchange_row<F>(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    tag: &BytesStart<'_>,
+    progress: &Progress,
+    on_doc: &mut F,
+    stats: &mut IngestStats,
+) -> Result<()>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    stats.scanned += 1;
+    progress.observe(stats);
+
+    let mut id: Option<String> = None;
+    let mut title: Option<String> = None;
+    let mut body: Option<String> = None;
+    let mut last_activity: Option<String> = None;
+
+    for attr in tag.attributes().with_checks(false) {
+        let attr = match attr {
+            Ok(attr) => attr,
+            Err(err) => {
+                tracing::warn!(%err, "invalid XML attribute");
+                fail_if_strict(config, || {
+                    format!("invalid XML attribute in {}: {err}", path.display())
+                })?;
+                continue;
+            }
+        };
+
+        let value = attr
+            .unescape_value()
+            .map(|v| v.into_owned())
+            .unwrap_or_default();
+
+        match attr.key.as_ref() {
+            b"Id" => id = Some(value),
+            b"Title" => title = Some(value),
+            b"Body" => body = Some(value),
+            b"LastActivityDate" => last_activity = Some(value),
+            _ => {}
+        }
+    }
+
+    let id = match id {
+        Some(id) => id,
+        None => {
+            stats.skipped += 1;
+            return Ok(());
+        }
+    };
+
+    let body_raw = body.unwrap_or_default();
+    let body_plain = if body_raw.is_empty() {
+        String::new()
+    } else {
+        html_to_text(config, body_raw.as_bytes())
+    };
+    let body = truncate_chars(&normalize_whitespace(&body_plain), config.max_indexed_chars);
+
+    if body.is_empty() && title.as_deref().unwrap_or_default().trim().is_empty() {
+        stats.skipped += 1;
+        return Ok(());
+    }
+
+    let title = normalize_whitespace(&title.unwrap_or_else(|| infer_title_from_body(&body, &id)));
+    let title = if title.is_empty() {
+        format!("Post {id}")
+    } else {
+        title
+    };
+
+    let body = if body.is_empty() { title.clone() } else { body };
+
+    let doc = RawDocument {
+        doc_id: format!("stackexchange:{source_name}:{id}"),
+        source: source_name.to_string(),
+        title,
+        preview: preview_from_text(&body, 280),
+        body,
+        location: format!("{}#{}", path.display(), id),
+        url: None,
+        date: last_activity.as_deref().and_then(parse_timestamp),
+        fingerprint: format!("{}:{}", last_activity.unwrap_or_default(), body_raw.len()),
+        tags: Vec::new(),
+    };
+
+    on_doc(doc)?;
+    stats.emitted += 1;
+    Ok(())
+}
+
+fn ingest_pdf<F>(
+    config: &AppConfig,
+    source_name: &str,
+    root: &Path,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    for entry in WalkDir::new(root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!(%err, "walkdir entry error");
+                fail_if_strict(config, || {
+                    format!("unable to walk {}: {err}", root.display())
+                })?;
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        stats.scanned += 1;
+
+        progress.observe(&stats);
+
+        let path = entry.path();
+        if file_extension(path).as_deref() != Some("pdf") {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let pdf = match lopdf::Document::load(path) {
+            Ok(pdf) => pdf,
+            Err(lopdf::Error::Decryption(err)) => {
+                tracing::warn!(path = %path.display(), %err, "skipping encrypted PDF");
+                stats.skipped += 1;
+                continue;
+            }
+            Err(err) => {
+                tracing::warn!(path = %path.display(), %err, "unable to parse PDF");
+                fail_if_strict(config, || {
+                    format!("unable to parse {}: {err}", path.display())
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        if pdf.is_encrypted() {
+            tracing::warn!(path = %path.display(), "skipping encrypted PDF");
+            stats.skipped += 1;
+            continue;
+        }
+
+        let page_numbers: Vec<u32> = pdf.get_pages().keys().copied().collect();
+        let mut page_texts = BTreeMap::new();
+        for page in &page_numbers {
+            match pdf.extract_text(&[*page]) {
+                Ok(page_text) => {
+                    page_texts.insert(*page, page_text);
+                }
+                Err(err) => {
+                    tracing::debug!(path = %path.display(), page, %err, "unable to extract PDF page text");
+                }
+            }
+        }
+
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+        let mut title = normalize_whitespace(
+            &pdf_metadata_title(&pdf)
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| path_to_title(rel)),
+        );
+        if title.is_empty() {
+            title = rel_str.clone();
+        }
+
+        let fingerprint = fingerprint_for_file(path).unwrap_or_else(|_| "0:0".to_string());
+
+        // With an outline, each top-level entry becomes its own document.
+        let sections = pdf_outline_sections(&pdf, page_numbers.len() as u32);
+        if !sections.is_empty() {
+            let mut emitted = false;
+            for (index, section) in sections.iter().enumerate() {
+                let text = page_texts
+                    .range(section.first_page..=section.last_page)
+                    .map(|(_, text)| text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let body = truncate_chars(&normalize_whitespace(&text), config.max_indexed_chars);
+                if body.is_empty() {
+                    continue;
+                }
+                let pages = if section.first_page == section.last_page {
+                    format!("page {}", section.first_page)
+                } else {
+                    format!("pages {}-{}", section.first_page, section.last_page)
+                };
+                let doc = RawDocument {
+                    doc_id: format!("pdf:{source_name}:{rel_str}#{}", index + 1),
+                    source: source_name.to_string(),
+                    title: section.title.clone().unwrap_or_else(|| title.clone()),
+                    preview: preview_from_text(&body, 280),
+                    body,
+                    location: format!("{rel_str} ({pages})"),
+                    url: None,
+                    date: file_mtime(path),
+                    fingerprint: fingerprint.clone(),
+                    tags: Vec::new(),
+                };
+                on_doc(doc)?;
+                stats.emitted += 1;
+                emitted = true;
+            }
+            if !emitted {
+                stats.skipped += 1;
+            }
+            continue;
+        }
+
+        let text = page_texts.into_values().collect::<Vec<_>>().join("\n");
+        let body = truncate_chars(&normalize_whitespace(&text), config.max_indexed_chars);
+        if body.is_empty() {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let doc = RawDocument {
+            doc_id: format!("pdf:{source_name}:{rel_str}"),
+            source: source_name.to_string(),
+            title,
+            preview: preview_from_text(&body, 280),
+            body,
+            location: format!("{rel_str} ({} pages)", page_numbers.len()),
+            url: None,
+            date: file_mtime(path),
+            fingerprint,
+            tags: Vec::new(),
+        };
+
+        on_doc(doc)?;
+        stats.emitted += 1;
+    }
+
+    Ok(stats)
+}
+
+/// A run of PDF pages under one top-level outline entry.
+struct PdfSection {
+    /// `None` for the pages before the first entry, and for blank titles.
+    title: Option<String>,
+    first_page: u32,
+    last_page: u32,
+}
+
+/// The page ranges of a PDF's top-level outline (bookmark) entries, in page
+/// order: each runs up to the page before the next entry starts, the last to
+/// `page_count`, and pages before the first entry form an untitled section.
+/// Empty when the PDF has no usable outline.
+fn pdf_outline_sections(pdf: &lopdf::Document, page_count: u32) -> Vec<PdfSection> {
+    let Ok(toc) = pdf.get_toc() else {
+        return Vec::new();
+    };
+    let mut entries: Vec<(u32, String)> = toc
+        .toc
+        .into_iter()
+        .filter(|entry| entry.level == 1)
+        .filter_map(|entry| {
+            let page = u32::try_from(entry.page).ok()?;
+            (1..=page_count)
+                .contains(&page)
+                .then(|| (page, normalize_whitespace(&entry.title)))
+        })
+        .collect();
+    entries.sort_by_key(|(page, _)| *page);
+
+    let mut sections = Vec::with_capacity(entries.len() + 1);
+    if let Some((first_page, _)) = entries.first() {
+        if *first_page > 1 {
+            sections.push(PdfSection {
+                title: None,
+                first_page: 1,
+                last_page: first_page - 1,
+            });
+        }
+    }
+    for (index, (first_page, title)) in entries.iter().enumerate() {
+        let next_page = entries
+            .get(index + 1)
+            .map_or(page_count + 1, |(page, _)| *page);
+        sections.push(PdfSection {
+            title: Some(title.clone()).filter(|title| !title.is_empty()),
+            first_page: *first_page,
+            // Entries starting on the same page share it.
+            last_page: next_page.saturating_sub(1).max(*first_page),
+        });
+    }
+    sections
+}
+
+fn pdf_metadata_title(pdf: &lopdf::Document) -> Option<String> {
+    let info = pdf.trailer.get(b"Info").ok()?;
+    let (_, info) = pdf.dereference(info).ok()?;
+    let title = info.as_dict().ok()?.get(b"Title").ok()?;
+    let (_, title) = pdf.dereference(title).ok()?;
+    lopdf::decode_text_string(title).ok()
+}
+
+fn ingest_epub<F>(
+    config: &AppConfig,
+    source_name: &str,
+    root: &Path,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    for entry in WalkDir::new(root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!(%err, "walkdir entry error");
+                fail_if_strict(config, || {
+                    format!("unable to walk {}: {err}", root.display())
+                })?;
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        stats.scanned += 1;
+
+        progress.observe(&stats);
+
+        let path = entry.path();
+        if file_extension(path).as_deref() != Some("epub") {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let book = match read_epub(config, path) {
+            Ok(book) => book,
+            Err(err) => {
+                tracing::warn!(path = %path.display(), error = %err, "unable to read EPUB");
+                fail_if_strict(config, || {
+                    format!("unable to read {}: {err:#}", path.display())
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        let body = truncate_chars(&normalize_whitespace(&book.text), config.max_indexed_chars);
+        if body.is_empty() {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+        let mut title = normalize_whitespace(
+            &book
+                .title
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| path_to_title(rel)),
+        );
+        if let Some(author) = book.author.filter(|author| !author.trim().is_empty()) {
+            title = format!("{title} by {}", normalize_whitespace(&author));
+        }
+        if title.is_empty() {
+            title = rel_str.clone();
+        }
+
+        let fingerprint = fingerprint_for_file(path).unwrap_or_else(|_| "0:0".to_string());
+
+        let doc = RawDocument {
+            doc_id: format!("epub:{source_name}:{rel_str}"),
+            source: source_name.to_string(),
+            title,
+            preview: preview_from_text(&body, 280),
+            body,
+            location: rel_str,
+            url: None,
+            date: file_mtime(path),
+            fingerprint,
+            tags: Vec::new(),
+        };
+
+        on_doc(doc)?;
+        stats.emitted += 1;
+    }
+
+    Ok(stats)
+}
+
+struct EpubBook {
+    title: Option<String>,
+    author: Option<String>,
+    text: String,
+}
+
+#[derive(Default)]
+struct EpubPackage {
+    title: Option<String>,
+    author: Option<String>,
+    manifest: HashMap<String, String>,
+    spine: Vec<String>,
+}
+
+fn read_epub(config: &AppConfig, path: &Path) -> Result<EpubBook> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).context("invalid zip archive")?;
+
+    let container = read_zip_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path =
+        parse_epub_rootfile(&container).ok_or_else(|| anyhow!("container.xml has no rootfile"))?;
+    let opf = read_zip_entry(&mut archive, &opf_path)?;
+    let package = parse_epub_package(&opf)?;
+
+    let opf_dir = match opf_path.rfind('/') {
+        Some(idx) => &opf_path[..=idx],
+        None => "",
+    };
+
+    let mut text = String::new();
+    for idref in &package.spine {
+        let Some(href) = package.manifest.get(idref) else {
+            continue;
+        };
+        let href = href.split('#').next().unwrap_or_default();
+        let chapter_path = format!("{opf_dir}{href}");
+        match read_zip_entry(&mut archive, &chapter_path) {
+            Ok(chapter) => {
+                text.push_str(&html_to_text(config, chapter.as_bytes()));
+                text.push('\n');
+            }
+            Err(err) => {
+                tracing::debug!(path = %path.display(), chapter = %chapter_path, error = %err, "unable to read EPUB chapter");
+            }
+        }
+    }
+
+    Ok(EpubBook {
+        title: package.title,
+        author: package.author,
+        text,
+    })
+}
+
+fn read_zip_entry<R>(archive: &mut zip::ZipArchive<R>, name: &str) -> Result<String>
+where
+    R: Read + std::io::Seek,
+{
+    let mut entry = archive
+        .by_name(name)
+        .with_context(|| format!("missing zip entry {name}"))?;
+    let mut bytes = Vec::new();
+    entry
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read zip entry {name}"))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn parse_epub_rootfile(container_xml: &str) -> Option<String> {
+    let mut reader = Reader::from_str(container_xml);
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Empty(tag)) | Ok(Event::Start(tag))
+                if tag.local_name().as_ref() == b"rootfile" =>
+            {
+                return xml_attr(&tag, b"full-path");
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        buf.clear();
+    }
+}
+
+fn parse_epub_package(opf_xml: &str) -> Result<EpubPackage> {
+    let mut reader = Reader::from_str(opf_xml);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut package = EpubPackage::default();
+    let mut current_tag: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(tag)) => {
+                let name = tag.local_name().as_ref().to_vec();
+                if name == b"title" || name == b"creator" {
+                    current_tag = Some(name);
+                }
+            }
+            Ok(Event::Empty(tag)) => match tag.local_name().as_ref() {
+                b"item" => {
+                    if let (Some(id), Some(href)) = (xml_attr(&tag, b"id"), xml_attr(&tag, b"href"))
+                    {
+                        package.manifest.insert(id, href);
+                    }
+                }
+                b"itemref" => {
+                    if let Some(idref) = xml_attr(&tag, b"idref") {
+                        package.spine.push(idref);
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Text(text)) => {
+                let value = text
+                    .unescape()
+                    .map(|decoded| decoded.into_owned())
+                    .unwrap_or_default();
+                match current_tag.as_deref() {
+                    Some(b"title") if package.title.is_none() => package.title = Some(value),
+                    Some(b"creator") if package.author.is_none() => package.author = Some(value),
+                    _ => {}
+                }
+            }
+            Ok(Event::End(_)) => current_tag = None,
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(err) => return Err(anyhow!("failed parsing EPUB package document: {err}")),
+        }
+
+        buf.clear();
+    }
+
+    Ok(package)
+}
+
+fn xml_attr(tag: &BytesStart<'_>, key: &[u8]) -> Option<String> {
+    tag.attributes()
+        .with_checks(false)
+        .filter_map(|attr| attr.ok())
+        .find(|attr| attr.key.local_name().as_ref() == key)
+        .and_then(|attr| attr.unescape_value().ok().map(|value| value.into_owned()))
+}
+
+fn ingest_office<F>(
+    config: &AppConfig,
+    source_name: &str,
+    root: &Path,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    for entry in WalkDir::new(root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!(%err, "walkdir entry error");
+                fail_if_strict(config, || {
+                    format!("unable to walk {}: {err}", root.display())
+                })?;
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        stats.scanned += 1;
+
+        progress.observe(&stats);
+
+        let path = entry.path();
+        let layout = match file_extension(path).as_deref() {
+            Some("docx") => &DOCX_LAYOUT,
+            Some("odt") => &ODT_LAYOUT,
+            _ => {
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        let document = match read_office_document(path, layout) {
+            Ok(document) => document,
+            Err(err) => {
+                tracing::warn!(path = %path.display(), error = %err, "unable to read office document");
+                fail_if_strict(config, || {
+                    format!("unable to read {}: {err:#}", path.display())
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        let body = truncate_chars(
+            &normalize_whitespace(&document.text),
+            config.max_indexed_chars,
+        );
+        if body.is_empty() {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+        let mut title = normalize_whitespace(
+            &document
+                .title
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| path_to_title(rel)),
+        );
+        if title.is_empty() {
+            title = rel_str.clone();
+        }
+
+        let fingerprint = fingerprint_for_file(path).unwrap_or_else(|_| "0:0".to_string());
+
+        let doc = RawDocument {
+            doc_id: format!("office:{source_name}:{rel_str}"),
+            source: source_name.to_string(),
+            title,
+            preview: preview_from_text(&body, 280),
+            body,
+            location: rel_str,
+            url: None,
+            date: file_mtime(path),
+            fingerprint,
+            tags: Vec::new(),
+        };
+
+        on_doc(doc)?;
+        stats.emitted += 1;
+    }
+
+    Ok(stats)
+}
+
+/// Where an office format keeps its text and metadata inside the zip.
+struct OfficeLayout {
+    content: &'static str,
+    properties: &'static str,
+    /// Element whose text is document text (`w:t`); `None` takes all text
+    /// inside paragraphs.
+    text_element: Option<&'static [u8]>,
+}
+
+const DOCX_LAYOUT: OfficeLayout = OfficeLayout {
+    content: "word/document.xml",
+    properties: "docProps/core.xml",
+    text_element: Some(b"t"),
+};
+
+const ODT_LAYOUT: OfficeLayout = OfficeLayout {
+    content: "content.xml",
+    properties: "meta.xml",
+    text_element: None,
+};
+
+struct OfficeDocument {
+    title: Option<String>,
+    text: String,
+}
+
+fn read_office_document(path: &Path, layout: &OfficeLayout) -> Result<OfficeDocument> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).context("invalid zip archive")?;
+
+    let content = read_zip_entry(&mut archive, layout.content)?;
+    let text = parse_office_text(&content, layout.text_element)?;
+    // Metadata is optional; a missing or broken properties part only loses
+    // the title.
+    let title = read_zip_entry(&mut archive, layout.properties)
+        .ok()
+        .and_then(|properties| parse_office_title(&properties));
+
+    Ok(OfficeDocument { title, text })
+}
+
+/// Paragraph text of a WordprocessingML or OpenDocument body, one paragraph
+/// per line.
+fn parse_office_text(xml: &str, text_element: Option<&[u8]>) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut text = String::new();
+    let mut paragraph_depth = 0usize;
+    let mut in_text_element = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(tag)) => match tag.local_name().as_ref() {
+                b"p" | b"h" => paragraph_depth += 1,
+                name if Some(name) == text_element => in_text_element = true,
+                _ => {}
+            },
+            Ok(Event::Empty(tag)) if paragraph_depth > 0 => match tag.local_name().as_ref() {
+                b"tab" | b"s" => text.push(' '),
+                b"br" | b"line-break" => text.push('\n'),
+                _ => {}
+            },
+            Ok(Event::Text(value))
+                if paragraph_depth > 0 && (text_element.is_none() || in_text_element) =>
+            {
+                if let Ok(decoded) = value.unescape() {
+                    text.push_str(&decoded);
+                }
+            }
+            Ok(Event::End(tag)) => match tag.local_name().as_ref() {
+                b"p" | b"h" => {
+                    paragraph_depth = paragraph_depth.saturating_sub(1);
+                    text.push('\n');
+                }
+                name if Some(name) == text_element => in_text_element = false,
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(err) => return Err(anyhow!("failed parsing office document body: {err}")),
+        }
+
+        buf.clear();
+    }
+
+    Ok(text)
+}
+
+/// `dc:title` of `docProps/core.xml` (DOCX) or `meta.xml` (ODT).
+fn parse_office_title(xml: &str) -> Option<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut in_title = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(tag)) => in_title = tag.local_name().as_ref() == b"title",
+            Ok(Event::Text(value)) if in_title => {
+                return value.unescape().ok().map(|decoded| decoded.into_owned());
+            }
+            Ok(Event::End(_)) => in_title = false,
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        buf.clear();
+    }
+}
+
+fn ingest_subtitles<F>(
+    config: &AppConfig,
+    source_name: &str,
+    root: &Path,
+    per_cue: bool,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    for entry in WalkDir::new(root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!(%err, "walkdir entry error");
+                fail_if_strict(config, || {
+                    format!("unable to walk {}: {err}", root.display())
+                })?;
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        stats.scanned += 1;
+
+        progress.observe(&stats);
+
+        let path = entry.path();
+        if !matches!(file_extension(path).as_deref(), Some("srt" | "vtt")) {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let cues = match fs::read(path) {
+            Ok(bytes) => parse_subtitles(&String::from_utf8_lossy(&bytes)),
+            Err(err) => {
+                tracing::warn!(path = %path.display(), error = %err, "unable to read subtitles");
+                fail_if_strict(config, || {
+                    format!("unable to read {}: {err:#}", path.display())
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        if cues.is_empty() {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+        let mut talk = path_to_title(rel);
+        if talk.is_empty() {
+            talk = rel_str.clone();
+        }
+        let fingerprint = fingerprint_for_file(path).unwrap_or_else(|_| "0:0".to_string());
+        let date = file_mtime(path);
+
+        if per_cue {
+            for (index, cue) in cues.iter().enumerate() {
+                let body = truncate_chars(&cue.text, config.max_indexed_chars);
+                let seconds = cue.start_ms / 1000;
+                let doc = RawDocument {
+                    doc_id: format!("subtitles:{source_name}:{rel_str}#{}", index + 1),
+                    source: source_name.to_string(),
+                    title: format!("{talk} @ {}", format_cue_time(seconds)),
+                    preview: preview_from_text(&body, 280),
+                    body,
+                    location: format!("{rel_str}#t={seconds}"),
+                    url: None,
+                    date,
+                    fingerprint: fingerprint.clone(),
+                    tags: Vec::new(),
+                };
+                on_doc(doc)?;
+                stats.emitted += 1;
+            }
+            continue;
+        }
+
+        let text = cues
+            .iter()
+            .map(|cue| cue.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let body = truncate_chars(&text, config.max_indexed_chars);
+
+        let doc = RawDocument {
+            doc_id: format!("subtitles:{source_name}:{rel_str}"),
+            source: source_name.to_string(),
+            title: talk,
+            preview: preview_from_text(&body, 280),
+            body,
+            location: rel_str,
+            url: None,
+            date,
+            fingerprint,
+            tags: Vec::new(),
+        };
+
+        on_doc(doc)?;
+        stats.emitted += 1;
+    }
+
+    Ok(stats)
+}
+
+struct SubtitleCue {
+    start_ms: u64,
+    text: String,
+}
+
+/// Cues of an SRT or WebVTT file in file order. Cue numbers, identifiers,
+/// timing lines, `WEBVTT`/`NOTE`/`STYLE` blocks and inline markup are dropped.
+fn parse_subtitles(input: &str) -> Vec<SubtitleCue> {
+    let input = input.trim_start_matches('\u{feff}');
+    let mut cues = Vec::new();
+    let mut block: Vec<&str> = Vec::new();
+
+    for line in input.lines().chain(std::iter::once("")) {
+        let line = line.trim_end_matches('\r');
+        if !line.trim().is_empty() {
+            block.push(line);
+            continue;
+        }
+
+        if let Some(timing) = block.iter().position(|line| line.contains("-->")) {
+            let start = block[timing].split("-->").next().unwrap_or_default();
+            let text = block[timing + 1..]
+                .iter()
+                .map(|line| SUBTITLE_MARKUP_RE.replace_all(line, " "))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let text = normalize_whitespace(&text);
+            if let (Some(start_ms), false) = (parse_cue_time(start), text.is_empty()) {
+                cues.push(SubtitleCue { start_ms, text });
+            }
+        }
+        block.clear();
+    }
+
+    cues
+}
+
+/// Parses `HH:MM:SS,mmm` (SRT) or `[HH:]MM:SS.mmm` (WebVTT) into milliseconds.
+fn parse_cue_time(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes, seconds] => (hours.parse::<u64>().ok()?, *minutes, *seconds),
+        [minutes, seconds] => (0, *minutes, *seconds),
+        _ => return None,
+    };
+    let minutes = minutes.parse::<u64>().ok()?;
+    let (whole, fraction) = seconds.split_once([',', '.']).unwrap_or((seconds, "0"));
+    let millis = format!("{fraction:0<3}").get(..3)?.parse::<u64>().ok()?;
+    let seconds = whole.parse::<u64>().ok()?;
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+fn format_cue_time(seconds: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn ingest_mbox<F>(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    let mut file = File::open(path)
+        .with_context(|| format!("failed to open mbox source {}", path.display()))?;
+    let gzip = is_gzip(path, &mut file)?;
+    let mut reader = buffered_source(file, gzip);
+
+    let mut message = Vec::new();
+    let mut message_idx = 0usize;
+    let mut previous_blank = true;
+    let mut raw_line = Vec::new();
+    loop {
+        raw_line.clear();
+        let read = reader
+            .read_until(b'\n', &mut raw_line)
+            .with_context(|| format!("failed to read mbox source {}", path.display()))?;
+
+        // A `From ` line after a blank line (or at the start) opens the next
+        // message; everything before it belongs to the previous one.
+        if read == 0 || (previous_blank && raw_line.starts_with(b"From ")) {
+            if message_idx > 0 {
+                stats.scanned += 1;
+                progress.observe(&stats);
+                match mbox_message_document(config, source_name, path, message_idx, &message)? {
+                    Some(doc) => {
+                        on_doc(doc)?;
+                        stats.emitted += 1;
+                    }
+                    None => stats.skipped += 1,
+                }
+            }
+            if read == 0 {
+                break;
+            }
+
+            message.clear();
+            message_idx += 1;
+            previous_blank = false;
+            continue;
+        }
+
+        previous_blank = raw_line == b"\n" || raw_line == b"\r\n";
+
+        // mboxrd escapes body lines starting with `From ` as `>From `.
+        let unescaped = match raw_line.iter().position(|byte| *byte != b'>') {
+            Some(depth) if depth > 0 && raw_line[depth..].starts_with(b"From ") => &raw_line[1..],
+            _ => &raw_line[..],
+        };
+        message.extend_from_slice(unescaped);
+    }
+
+    Ok(stats)
+}
+
+fn mbox_message_document(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    message_idx: usize,
+    raw_message: &[u8],
+) -> Result<Option<RawDocument>> {
+    let mail = match Mail::parse(raw_message) {
+        Ok(mail) => mail,
+        Err(err) => {
+            tracing::warn!(path = %path.display(), message = message_idx, %err, "unable to parse mbox message");
+            fail_if_strict(config, || {
+                format!(
+                    "unable to parse message {message_idx} of {}: {err}",
+                    path.display()
+                )
+            })?;
+            return Ok(None);
+        }
+    };
+
+    let header = |name: &str| {
+        mail.header(name)
+            .map(|value| normalize_whitespace(&value))
+            .filter(|value| !value.is_empty())
+    };
+
+    let text = mail_plain_text(config, &mail).unwrap_or_default();
+    let from = header("From");
+    let body_text = match &from {
+        Some(from) => format!("From: {from}\n{text}"),
+        None => text,
+    };
+    let body = truncate_chars(&normalize_whitespace(&body_text), config.max_indexed_chars);
+    if body.is_empty() {
+        return Ok(None);
+    }
+
+    let title = header("Subject").unwrap_or_else(|| format!("Message {message_idx}"));
+    let anchor = header("Message-ID")
+        .map(|id| id.trim_matches(|ch| ch == '<' || ch == '>').to_string())
+        .unwrap_or_else(|| message_idx.to_string());
+
+    let mut hasher = Hasher::new();
+    hasher.update(raw_message);
+
+    Ok(Some(RawDocument {
+        doc_id: format!("mbox:{source_name}:{anchor}"),
+        source: source_name.to_string(),
+        title,
+        preview: preview_from_text(&body, 280),
+        body,
+        location: format!("{}#{}", path.display(), message_idx),
+        url: None,
+        date: header("Date").and_then(|date| mail::parse_date(&date)),
+        fingerprint: hasher.finalize().to_hex().to_string(),
+        tags: Vec::new(),
+    }))
+}
+
+/// Column mapping of a CSV source, each entry a header name or, for files
+/// without a header row, a zero-based column index.
+struct CsvColumns<'a> {
+    id: Option<&'a str>,
+    title: Option<&'a str>,
+    body: &'a [String],
+}
+
+/// Resolved column positions for the rows of one CSV file.
+struct CsvLayout {
+    id: Option<usize>,
+    title: Option<usize>,
+    /// Empty means every column other than id and title.
+    body: Vec<usize>,
+}
+
+fn ingest_csv<F>(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    columns: &CsvColumns<'_>,
+    delimiter: Option<char>,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    let delimiter = match delimiter {
+        Some(delimiter) => u8::try_from(delimiter)
+            .ok()
+            .filter(u8::is_ascii)
+            .ok_or_else(|| {
+                anyhow!("CSV delimiter {delimiter:?} must be a single ASCII character")
+            })?,
+        None if path_is_tsv(path) => b'\t',
+        None => b',',
+    };
+
+    let mut file = File::open(path)
+        .with_context(|| format!("failed to open CSV source {}", path.display()))?;
+    let gzip = is_gzip(path, &mut file)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(buffered_source(file, gzip));
+
+    let mut records = reader.byte_records();
+    let Some(first) = records.next() else {
+        return Ok(stats);
+    };
+    let first = first.with_context(|| format!("failed to read CSV source {}", path.display()))?;
+    let first: Vec<String> = first
+        .iter()
+        .map(|field| String::from_utf8_lossy(field).trim().to_string())
+        .collect();
+
+    let header = csv_is_header(&first, columns);
+    let layout = csv_layout(header.then_some(first.as_slice()), columns)
+        .with_context(|| format!("invalid column mapping for CSV source {}", path.display()))?;
+
+    let first_row = if header { None } else { Some(Ok(first)) };
+    let rows = first_row.into_iter().chain(records.map(|record| {
+        record.map(|record| {
+            record
+                .iter()
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+                .collect::<Vec<_>>()
+        })
+    }));
+
+    for (row_idx, row) in rows.enumerate() {
+        let row_idx = row_idx + 1;
+        stats.scanned += 1;
+        progress.observe(&stats);
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => {
+                tracing::warn!(path = %path.display(), row = row_idx, %err, "invalid CSV row");
+                fail_if_strict(config, || {
+                    format!("invalid CSV row {row_idx} in {}: {err}", path.display())
+                })?;
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        match csv_row_document(config, source_name, path, &layout, row_idx, &row) {
+            Some(doc) => {
+                on_doc(doc)?;
+                stats.emitted += 1;
+            }
+            None => stats.skipped += 1,
+        }
+    }
+
+    Ok(stats)
+}
+
+fn path_is_tsv(path: &Path) -> bool {
+    let path = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("gz") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    file_extension(&path).is_some_and(|ext| ext == "tsv")
+}
+
+/// Treats the first row as a header when it names a configured column, or,
+/// with no named columns configured, when it looks like labels: non-empty,
+/// non-numeric and distinct.
+fn csv_is_header(first: &[String], columns: &CsvColumns<'_>) -> bool {
+    let named: Vec<&str> = columns
+        .id
+        .into_iter()
+        .chain(columns.title)
+        .chain(columns.body.iter().map(String::as_str))
+        .filter(|column| column.parse::<usize>().is_err())
+        .collect();
+    if !named.is_empty() {
+        return first
+            .iter()
+            .any(|field| named.iter().any(|name| field.eq_ignore_ascii_case(name)));
+    }
+
+    let mut seen = HashSet::new();
+    first.iter().all(|field| {
+        !field.is_empty() && field.parse::<f64>().is_err() && seen.insert(field.to_lowercase())
+    })
+}
+
+fn csv_layout(header: Option<&[String]>, columns: &CsvColumns<'_>) -> Result<CsvLayout> {
+    let resolve = |column: &str| -> Result<usize> {
+        if let Some(position) = header.and_then(|header| {
+            header
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(column))
+        }) {
+            return Ok(position);
+        }
+        column
+            .parse::<usize>()
+            .map_err(|_| anyhow!("column `{column}` not found in the header row"))
+    };
+
+    // Without explicit mapping, pick up conventionally named columns.
+    let by_name = |name: &str| {
+        header.and_then(|header| {
+            header
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(name))
+        })
+    };
+
+    Ok(CsvLayout {
+        id: match columns.id {
+            Some(column) => Some(resolve(column)?),
+            None => by_name("id"),
+        },
+        title: match columns.title {
+            Some(column) => Some(resolve(column)?),
+            None => by_name("title"),
+        },
+        body: columns
+            .body
+            .iter()
+            .map(|column| resolve(column))
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn csv_row_document(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    layout: &CsvLayout,
+    row_idx: usize,
+    row: &[String],
+) -> Option<RawDocument> {
+    let field = |position: Option<usize>| {
+        position
+            .and_then(|position| row.get(position))
+            .map(|value| normalize_whitespace(value))
+            .filter(|value| !value.is_empty())
+    };
+
+    let body_text = if layout.body.is_empty() {
+        row.iter()
+            .enumerate()
+            .filter(|(position, _)| Some(*position) != layout.id && Some(*position) != layout.title)
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        layout
+            .body
+            .iter()
+            .filter_map(|position| row.get(*position))
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let body = truncate_chars(&normalize_whitespace(&body_text), config.max_indexed_chars);
+    if body.is_empty() {
+        return None;
+    }
+
+    let id = field(layout.id).unwrap_or_else(|| row_idx.to_string());
+    let title = field(layout.title).unwrap_or_else(|| format!("Row {id}"));
+
+    let mut hasher = Hasher::new();
+    for value in row {
+        hasher.update(value.as_bytes());
+        hasher.update(&[0x1f]);
+    }
+
+    Some(RawDocument {
+        doc_id: format!("csv:{source_name}:{id}"),
+        source: source_name.to_string(),
+        title,
+        preview: preview_from_text(&body, 280),
+        body,
+        location: format!("{}:{}", path.display(), row_idx),
+        url: None,
+        date: None,
+        fingerprint: hasher.finalize().to_hex().to_string(),
+        tags: Vec::new(),
+    })
+}
 
-        let bytes = match fs::read(path) {
-            Ok(bytes) => bytes,
-            Err(err) => {
-                tracing::warn!(path = %path.display(), %err, "unable to read file");
-                stats.skipped += 1;
-                continue;
-            }
-        };
+/// Result columns of a SQLite source's query that map to document fields;
+/// unset ones default to `id`, `title` and `body` like JSONL fields.
+struct SqliteColumns<'a> {
+    id: Option<&'a str>,
+    title: Option<&'a str>,
+    body: Option<&'a str>,
+}
 
-        if matches!(inspect(&bytes), ContentType::BINARY) {
-            stats.skipped += 1;
-            continue;
+/// Runs a SQLite source's query and emits one document per row. The
+/// database is opened read-only and immutable, so no lock is taken and an
+/// app that has it open is never blocked.
+fn ingest_sqlite<F>(
+    config: &AppConfig,
+    source_name: &str,
+    path: &Path,
+    query: &str,
+    columns: &SqliteColumns<'_>,
+    progress: &Progress,
+    on_doc: &mut F,
+) -> Result<IngestStats>
+where
+    F: FnMut(RawDocument) -> Result<()>,
+{
+    let mut stats = IngestStats::default();
+
+    if !path.is_file() {
+        bail!("SQLite source {} does not exist", path.display());
+    }
+    let connection = rusqlite::Connection::open_with_flags(
+        sqlite_immutable_uri(path),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("failed to open SQLite source {}", path.display()))?;
+    let mut statement = connection
+        .prepare(query)
+        .with_context(|| format!("invalid query for SQLite source {}", path.display()))?;
+
+    let column = |configured: Option<&str>, default: &str| -> Result<Option<usize>> {
+        match configured {
+            Some(name) => statement.column_index(name).map(Some).map_err(|_| {
+                anyhow!(
+                    "column `{name}` is not returned by the query of SQLite source {}",
+                    path.display()
+                )
+            }),
+            None => Ok(statement.column_index(default).ok()),
         }
+    };
+    let id_column = column(columns.id, "id")?;
+    let title_column = column(columns.title, "title")?;
+    let Some(body_column) = column(columns.body, "body")? else {
+        bail!(
+            "the query of SQLite source {} returns no `body` column; set body_column",
+            path.display()
+        );
+    };
 
-        let raw_text = String::from_utf8_lossy(&bytes).into_owned();
-        let ext = file_extension(path).unwrap_or_default();
-        let rel = path.strip_prefix(root).unwrap_or(path);
-        let rel_str = rel.to_string_lossy().replace('\\', "/");
+    let mut rows = statement
+        .query([])
+        .with_context(|| format!("failed to query SQLite source {}", path.display()))?;
+    let mut row_idx = 0usize;
+    while let Some(row) = rows
+        .next()
+        .with_context(|| format!("failed to read SQLite source {}", path.display()))?
+    {
+        row_idx += 1;
+        stats.scanned += 1;
+        progress.observe(&stats);
 
-        let (mut title, body_source) = if is_html_ext(&ext) {
-            let extracted_title = extract_html_title(&raw_text)
-                .filter(|title| !title.is_empty())
-                .unwrap_or_else(|| path_to_title(rel));
-            let body = html2text::from_read(raw_text.as_bytes(), 120);
-            (extracted_title, body)
-        } else {
-            let title = path_to_title(rel);
-            (title, raw_text)
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| row.get_ref(column).ok())
+                .and_then(sqlite_value_to_string)
         };
+        let id = field(id_column).unwrap_or_else(|| row_idx.to_string());
+        let title = field(title_column).unwrap_or_default();
+        let body = field(Some(body_column)).unwrap_or_default();
 
-        title = normalize_whitespace(&title);
-        if title.is_empty() {
-            title = rel_str.clone();
+        let mut hasher = Hasher::new();
+        for value in [&id, &title, &body] {
+            hasher.update(value.as_bytes());
+            hasher.update(&[0x1f]);
         }
 
-        let body = truncate_chars(
-            &normalize_whitespace(&body_source),
-            config.max_indexed_chars,
-        );
+        let body = truncate_chars(&normalize_whitespace(&body), config.max_indexed_chars);
         if body.is_empty() {
             stats.skipped += 1;
             continue;
         }
+        let mut title = normalize_whitespace(&title);
+        if title.is_empty() {
+            title = format!("Row {id}");
+        }
 
-        let fingerprint = fingerprint_for_file(path).unwrap_or_else(|_| "0:0".to_string());
-
-        let doc = RawDocument {
-            doc_id: format!("fs:{source_name}:{rel_str}"),
+        on_doc(RawDocument {
+            doc_id: format!("sqlite:{source_name}:{id}"),
             source: source_name.to_string(),
             title,
             preview: preview_from_text(&body, 280),
             body,
-            location: rel_str,
+            location: format!("{}:{id}", path.display()),
             url: None,
-            fingerprint,
-        };
-
-        on_doc(doc)?;
+            date: None,
+            fingerprint: hasher.finalize().to_hex().to_string(),
+            tags: Vec::new(),
+        })?;
         stats.emitted += 1;
     }
 
     Ok(stats)
 }
 
-fn ingest_jsonl<F>(
+/// A `file:` URI opening `path` read-only with `immutable=1`.
+fn sqlite_immutable_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let escaped = path
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    format!("file:{escaped}?mode=ro&immutable=1")
+}
+
+fn sqlite_value_to_string(value: ValueRef<'_>) -> Option<String> {
+    match value {
+        ValueRef::Integer(value) => Some(value.to_string()),
+        ValueRef::Real(value) => Some(value.to_string()),
+        ValueRef::Text(text) => Some(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Null | ValueRef::Blob(_) => None,
+    }
+}
+
+fn ingest_feeds<F>(
     config: &AppConfig,
     source_name: &str,
-    path: &Path,
-    id_field: Option<&str>,
-    title_field: Option<&str>,
-    body_field: Option<&str>,
-    url_field: Option<&str>,
+    root: &Path,
+    progress: &Progress,
     on_doc: &mut F,
 ) -> Result<IngestStats>
 where
@@ -218,212 +3008,299 @@ where
 {
     let mut stats = IngestStats::default();
 
-    let file = File::open(path)
-        .with_context(|| format!("failed to open JSONL source {}", path.display()))?;
-    let reader = BufReader::new(file);
-
-    let id_field = id_field.unwrap_or("id");
-    let title_field = title_field.unwrap_or("title");
-    let body_field = body_field.unwrap_or("body");
-    let url_field = url_field.unwrap_or("url");
-
-    for (line_idx, line) in reader.lines().enumerate() {
-        stats.scanned += 1;
-
-        let line = match line {
-            Ok(line) => line,
+    for entry in WalkDir::new(root) {
+        let entry = match entry {
+            Ok(entry) => entry,
             Err(err) => {
-                tracing::warn!(path = %path.display(), line = line_idx + 1, %err, "failed to read JSONL line");
-                stats.skipped += 1;
+                tracing::warn!(%err, "walkdir entry error");
+                fail_if_strict(config, || {
+                    format!("unable to walk {}: {err}", root.display())
+                })?;
                 continue;
             }
         };
+        if !entry.file_type().is_file() {
+            continue;
+        }
 
-        if line.trim().is_empty() {
+        let path = entry.path();
+        if !matches!(
+            file_extension(path).as_deref(),
+            Some("xml" | "rss" | "atom")
+        ) {
+            stats.scanned += 1;
             stats.skipped += 1;
+            progress.observe(&stats);
             continue;
         }
 
-        let parsed: Value = match serde_json::from_str(&line) {
-            Ok(value) => value,
+        let entries = match read_feed(path) {
+            Ok(entries) => entries,
             Err(err) => {
-                tracing::warn!(path = %path.display(), line = line_idx + 1, %err, "invalid JSONL object");
+                tracing::warn!(path = %path.display(), error = %err, "unable to read feed");
+                fail_if_strict(config, || {
+                    format!("unable to read {}: {err:#}", path.display())
+                })?;
+                stats.scanned += 1;
                 stats.skipped += 1;
+                progress.observe(&stats);
                 continue;
             }
         };
 
-        let id =
-            value_to_string(parsed.get(id_field)).unwrap_or_else(|| (line_idx + 1).to_string());
-        let mut title =
-            value_to_string(parsed.get(title_field)).unwrap_or_else(|| format!("Document {id}"));
-        let body = value_to_string(parsed.get(body_field)).unwrap_or_default();
-        let url = value_to_string(parsed.get(url_field)).filter(|value| !value.trim().is_empty());
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let rel_str = match rel.to_string_lossy().replace('\\', "/") {
+            rel_str if rel_str.is_empty() => path_to_title(path),
+            rel_str => rel_str,
+        };
 
-        let body = truncate_chars(&normalize_whitespace(&body), config.max_indexed_chars);
-        if body.is_empty() {
-            stats.skipped += 1;
-            continue;
-        }
+        for (entry_idx, feed_entry) in entries.into_iter().enumerate() {
+            stats.scanned += 1;
+            progress.observe(&stats);
+
+            let mut hasher = Hasher::new();
+            for value in [
+                &feed_entry.title,
+                &feed_entry.summary,
+                &feed_entry.content,
+                feed_entry.link.as_deref().unwrap_or_default(),
+            ] {
+                hasher.update(value.as_bytes());
+                hasher.update(&[0x1f]);
+            }
+            hasher.update(&feed_entry.date().unwrap_or_default().to_le_bytes());
+
+            // Full content when the feed carries it, the summary otherwise.
+            let html = if feed_entry.content.trim().is_empty() {
+                &feed_entry.summary
+            } else {
+                &feed_entry.content
+            };
+            let body = truncate_chars(
+                &normalize_whitespace(&html_to_text(config, html.as_bytes())),
+                config.max_indexed_chars,
+            );
+            if body.is_empty() {
+                stats.skipped += 1;
+                continue;
+            }
 
-        title = normalize_whitespace(&title);
-        if title.is_empty() {
-            title = format!("Document {id}");
+            // The guid (RSS) or id (Atom) keeps entries stable across
+            // re-fetches; the link is the next best thing.
+            let id = feed_entry
+                .id
+                .clone()
+                .or_else(|| feed_entry.link.clone())
+                .unwrap_or_else(|| format!("{rel_str}#{}", entry_idx + 1));
+            // Atom titles may be `type="html"`.
+            let mut title = normalize_whitespace(&HTML_TAG_RE.replace_all(&feed_entry.title, ""));
+            if title.is_empty() {
+                title = infer_title_from_body(&body, &id);
+            }
+
+            on_doc(RawDocument {
+                doc_id: format!("feed:{source_name}:{id}"),
+                source: source_name.to_string(),
+                title,
+                preview: preview_from_text(&body, 280),
+                body,
+                location: rel_str.clone(),
+                url: feed_entry.link.clone(),
+                date: feed_entry.date(),
+                fingerprint: hasher.finalize().to_hex().to_string(),
+                tags: Vec::new(),
+            })?;
+            stats.emitted += 1;
         }
+    }
 
-        let mut hasher = Hasher::new();
-        hasher.update(line.as_bytes());
+    Ok(stats)
+}
 
-        let location = format!("{}:{}", path.display(), line_idx + 1);
-        let doc = RawDocument {
-            doc_id: format!("jsonl:{source_name}:{id}"),
-            source: source_name.to_string(),
-            title,
-            preview: preview_from_text(&body, 280),
-            body,
-            location,
-            url,
-            fingerprint: hasher.finalize().to_hex().to_string(),
-        };
+#[derive(Debug, Default)]
+struct FeedEntry {
+    id: Option<String>,
+    title: String,
+    link: Option<String>,
+    summary: String,
+    content: String,
+    published: Option<i64>,
+    updated: Option<i64>,
+}
 
-        on_doc(doc)?;
-        stats.emitted += 1;
+impl FeedEntry {
+    fn date(&self) -> Option<i64> {
+        self.published.or(self.updated)
     }
-
-    Ok(stats)
 }
 
-fn ingest_stackexchange_xml<F>(
-    config: &AppConfig,
-    source_name: &str,
-    path: &Path,
-    on_doc: &mut F,
-) -> Result<IngestStats>
-where
-    F: FnMut(RawDocument) -> Result<()>,
-{
-    let mut stats = IngestStats::default();
+#[derive(Clone, Copy, PartialEq)]
+enum FeedField {
+    Id,
+    Title,
+    Link,
+    Summary,
+    Content,
+    Published,
+    Updated,
+}
 
-    let file = File::open(path).with_context(|| {
-        format!(
-            "failed to open Stack Exchange XML source {}",
-            path.display()
-        )
-    })?;
+/// Entries of an RSS 2.0 (`<item>`) or Atom (`<entry>`) feed. Only direct
+/// children of an entry are read, so an Atom `<source>` or `<author>` does
+/// not leak its `<title>`/`<id>` into the entry.
+fn read_feed(path: &Path) -> Result<Vec<FeedEntry>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
     let mut reader = Reader::from_reader(BufReader::new(file));
-    reader.config_mut().trim_text(true);
 
     let mut buf = Vec::new();
+    let mut entries = Vec::new();
+    let mut entry: Option<FeedEntry> = None;
+    // Depth below the current entry element, and the field being read.
+    let mut depth = 0usize;
+    let mut field: Option<FeedField> = None;
+    let mut text = String::new();
+
     loop {
         match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(tag)) if tag.name().as_ref() == b"row" => {
-                process_stackexchange_row(config, source_name, path, &tag, on_doc, &mut stats)?;
+            Ok(Event::Start(tag)) => {
+                let Some(current) = entry.as_mut() else {
+                    if matches!(tag.local_name().as_ref(), b"item" | b"entry") {
+                        entry = Some(FeedEntry::default());
+                        depth = 0;
+                    }
+                    buf.clear();
+                    continue;
+                };
+                depth += 1;
+                if depth == 1 {
+                    field = feed_field(&tag);
+                    if field == Some(FeedField::Link) {
+                        // Atom links carry the URL in `href`.
+                        if let Some(href) = feed_link_href(&tag) {
+                            current.link.get_or_insert(href);
+                        }
+                    }
+                    text.clear();
+                } else if field.is_some() {
+                    // Markup inside an Atom `type="xhtml"` body.
+                    text.push(' ');
+                }
             }
-            Ok(Event::Start(tag)) if tag.name().as_ref() == b"row" => {
-                process_stackexchange_row(config, source_name, path, &tag, on_doc, &mut stats)?;
+            Ok(Event::Empty(tag)) => {
+                if let Some(current) = entry.as_mut() {
+                    if depth == 0 && tag.local_name().as_ref() == b"link" {
+                        if let Some(href) = feed_link_href(&tag) {
+                            current.link.get_or_insert(href);
+                        }
+                    }
+                }
+            }
+            Ok(Event::Text(value)) if field.is_some() => match value.unescape() {
+                Ok(decoded) => text.push_str(&decoded),
+                Err(_) => text.push_str(&String::from_utf8_lossy(&value)),
+            },
+            Ok(Event::CData(value)) if field.is_some() => {
+                text.push_str(&String::from_utf8_lossy(&value));
+            }
+            Ok(Event::End(_)) => {
+                let Some(current) = entry.as_mut() else {
+                    buf.clear();
+                    continue;
+                };
+                if depth == 0 {
+                    entries.extend(entry.take());
+                } else {
+                    if depth == 1 {
+                        if let Some(field) = field.take() {
+                            set_feed_field(current, field, std::mem::take(&mut text));
+                        }
+                    }
+                    depth -= 1;
+                }
             }
             Ok(Event::Eof) => break,
             Ok(_) => {}
-            Err(err) => {
-                return Err(anyhow::anyhow!(
-                    "error while parsing {} at byte {}: {err}",
-                    path.display(),
-                    reader.buffer_position()
-                ));
-            }
+            Err(err) => return Err(anyhow!("failed parsing feed: {err}")),
         }
 
         buf.clear();
     }
 
-    Ok(stats)
+    Ok(entries)
 }
 
-fn process_stackexchange_row<F>(
-    config: &AppConfig,
-    source_name: &str,
-    path: &Path,
-    tag: &BytesStart<'_>,
-    on_doc: &mut F,
-    stats: &mut IngestStats,
-) -> Result<()>
-where
-    F: FnMut(RawDocument) -> Result<()>,
-{
-    stats.scanned += 1;
-
-    let mut id: Option<String> = None;
-    let mut title: Option<String> = None;
-    let mut body: Option<String> = None;
-    let mut last_activity: Option<String> = None;
-
-    for attr in tag.attributes().with_checks(false) {
-        let attr = match attr {
-            Ok(attr) => attr,
-            Err(err) => {
-                tracing::warn!(%err, "invalid XML attribute");
-                continue;
-            }
-        };
-
-        let value = attr
-            .unescape_value()
-            .map(|v| v.into_owned())
-            .unwrap_or_default();
+fn feed_field(tag: &BytesStart<'_>) -> Option<FeedField> {
+    match tag.local_name().as_ref() {
+        b"guid" | b"id" => Some(FeedField::Id),
+        b"title" => Some(FeedField::Title),
+        b"link" => Some(FeedField::Link),
+        b"description" | b"summary" => Some(FeedField::Summary),
+        // `content:encoded` in RSS, `content` in Atom.
+        b"encoded" | b"content" => Some(FeedField::Content),
+        b"pubDate" | b"published" | b"date" => Some(FeedField::Published),
+        b"updated" => Some(FeedField::Updated),
+        _ => None,
+    }
+}
 
-        match attr.key.as_ref() {
-            b"Id" => id = Some(value),
-            b"Title" => title = Some(value),
-            b"Body" => body = Some(value),
-            b"LastActivityDate" => last_activity = Some(value),
-            _ => {}
-        }
+/// `href` of an Atom link pointing at the entry itself (no `rel`, or
+/// `rel="alternate"`).
+fn feed_link_href(tag: &BytesStart<'_>) -> Option<String> {
+    match xml_attr(tag, b"rel").as_deref() {
+        None | Some("alternate") => xml_attr(tag, b"href").filter(|href| !href.trim().is_empty()),
+        Some(_) => None,
     }
+}
 
-    let id = match id {
-        Some(id) => id,
-        None => {
-            stats.skipped += 1;
-            return Ok(());
+fn set_feed_field(entry: &mut FeedEntry, field: FeedField, value: String) {
+    let trimmed = value.trim();
+    match field {
+        FeedField::Id if !trimmed.is_empty() => entry.id = Some(trimmed.to_string()),
+        FeedField::Link if !trimmed.is_empty() => {
+            entry.link.get_or_insert_with(|| trimmed.to_string());
         }
-    };
-
-    let body_raw = body.unwrap_or_default();
-    let body_plain = if body_raw.is_empty() {
-        String::new()
-    } else {
-        html2text::from_read(body_raw.as_bytes(), 120)
-    };
-    let body = truncate_chars(&normalize_whitespace(&body_plain), config.max_indexed_chars);
-
-    if body.is_empty() && title.as_deref().unwrap_or_default().trim().is_empty() {
-        stats.skipped += 1;
-        return Ok(());
+        FeedField::Title => entry.title = value,
+        FeedField::Summary => entry.summary = value,
+        FeedField::Content => entry.content = value,
+        FeedField::Published => entry.published = parse_feed_date(trimmed),
+        FeedField::Updated => entry.updated = parse_feed_date(trimmed),
+        FeedField::Id | FeedField::Link => {}
     }
+}
 
-    let title = normalize_whitespace(&title.unwrap_or_else(|| infer_title_from_body(&body, &id)));
-    let title = if title.is_empty() {
-        format!("Post {id}")
-    } else {
-        title
-    };
-
-    let body = if body.is_empty() { title.clone() } else { body };
+/// RFC 3339 (Atom) or RFC 2822 (RSS `pubDate`) timestamps.
+fn parse_feed_date(value: &str) -> Option<i64> {
+    if let Some((local, offset)) = split_utc_offset(value) {
+        return parse_timestamp(local).map(|timestamp| timestamp - offset);
+    }
+    parse_timestamp(value).or_else(|| mail::parse_date(value))
+}
 
-    let doc = RawDocument {
-        doc_id: format!("stackexchange:{source_name}:{id}"),
-        source: source_name.to_string(),
-        title,
-        preview: preview_from_text(&body, 280),
-        body,
-        location: format!("{}#{}", path.display(), id),
-        url: None,
-        fingerprint: format!("{}:{}", last_activity.unwrap_or_default(), body_raw.len()),
+/// Splits a trailing `+hh:mm`/`-hh:mm` off an RFC 3339 date-time, returning
+/// the offset in seconds.
+fn split_utc_offset(value: &str) -> Option<(&str, i64)> {
+    let split = value.len().checked_sub(6)?;
+    let (local, offset) = (value.get(..split)?, value.get(split..)?);
+    if !local.contains('T') || offset.as_bytes()[3] != b':' {
+        return None;
+    }
+    let sign = match offset.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
     };
+    let hours: i64 = offset[1..3].parse().ok()?;
+    let minutes: i64 = offset[4..].parse().ok()?;
+    Some((local, sign * (hours * 3600 + minutes * 60)))
+}
 
-    on_doc(doc)?;
-    stats.emitted += 1;
-    Ok(())
+/// The decoded `text/plain` content of a message, falling back to converted
+/// `text/html` when no plain part exists. Attachments are ignored.
+fn mail_plain_text(config: &AppConfig, mail: &Mail<'_>) -> Option<String> {
+    mail.text_part("text/plain").or_else(|| {
+        mail.text_part("text/html")
+            .map(|html| html_to_text(config, html.as_bytes()))
+    })
 }
 
 fn path_to_title(path: impl AsRef<Path>) -> String {
@@ -435,6 +3312,78 @@ fn path_to_title(path: impl AsRef<Path>) -> String {
     path.to_string_lossy().to_string()
 }
 
+/// Title of a plain-text file under `title_from`, if the rule finds one.
+fn text_title(text: &str, ext: &str, title_from: TitleFrom) -> Option<String> {
+    if title_from == TitleFrom::Filename {
+        return None;
+    }
+    if matches!(ext, "md" | "markdown") {
+        if let Some(heading) = markdown_heading(text) {
+            return Some(heading);
+        }
+    }
+    if title_from == TitleFrom::FirstLine {
+        let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+        return Some(truncate_chars(line, MAX_LINE_TITLE_CHARS));
+    }
+    None
+}
+
+/// Text of the first ATX (`# Title`) or setext (`Title` underlined with
+/// `===`/`---`) heading, skipping YAML front matter and fenced code.
+fn markdown_heading(text: &str) -> Option<String> {
+    let mut lines = text.lines().peekable();
+    if lines.peek().is_some_and(|line| line.trim_end() == "---") {
+        lines.next();
+        for line in lines.by_ref() {
+            if matches!(line.trim_end(), "---" | "...") {
+                break;
+            }
+        }
+    }
+
+    let mut in_fence = false;
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            paragraph.clear();
+            continue;
+        }
+        // Four spaces of indentation make a code block.
+        if in_fence || line.len() - line.trim_start().len() >= 4 {
+            paragraph.clear();
+            continue;
+        }
+
+        let hashes = trimmed.bytes().take_while(|&byte| byte == b'#').count();
+        if (1..=6).contains(&hashes)
+            && (trimmed.len() == hashes || trimmed[hashes..].starts_with([' ', '\t']))
+        {
+            let heading = trimmed[hashes..].trim().trim_end_matches('#').trim();
+            if !heading.is_empty() {
+                return Some(heading.to_string());
+            }
+            paragraph.clear();
+            continue;
+        }
+
+        let underline = !trimmed.is_empty()
+            && (trimmed.bytes().all(|byte| byte == b'=')
+                || trimmed.bytes().all(|byte| byte == b'-'));
+        if underline && !paragraph.is_empty() {
+            return Some(paragraph.join(" "));
+        }
+        if trimmed.is_empty() || underline {
+            paragraph.clear();
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    None
+}
+
 fn extract_html_title(raw_html: &str) -> Option<String> {
     HTML_TITLE_RE
         .captures(raw_html)
@@ -466,18 +3415,16 @@ fn truncate_chars(input: &str, max_chars: usize) -> String {
         return String::new();
     }
 
-    let mut char_count = 0usize;
-    for (byte_idx, _) in input.char_indices() {
+    for (char_count, (byte_idx, _)) in input.char_indices().enumerate() {
         if char_count == max_chars {
             return input[..byte_idx].to_string();
         }
-        char_count += 1;
     }
 
     input.to_string()
 }
 
-fn preview_from_text(input: &str, max_chars: usize) -> String {
+pub fn preview_from_text(input: &str, max_chars: usize) -> String {
     let truncated = truncate_chars(input, max_chars);
     if truncated.len() < input.len() {
         format!("{truncated}...")
@@ -495,6 +3442,16 @@ fn value_to_string(value: Option<&Value>) -> Option<String> {
     }
 }
 
+fn value_to_timestamp(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number
+            .as_i64()
+            .or_else(|| number.as_f64().map(|secs| secs as i64)),
+        Value::String(text) => parse_timestamp(text),
+        _ => None,
+    }
+}
+
 fn is_extension_allowed(path: &Path, whitelist: &[String]) -> bool {
     let ext = file_extension(path);
     match ext {
@@ -534,6 +3491,16 @@ fn fingerprint_for_file(path: &Path) -> Result<String> {
     Ok(format!("{}:{}", meta.len(), modified))
 }
 
+fn file_mtime(path: &Path) -> Option<i64> {
+    fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs() as i64)
+}
+
 #[allow(dead_code)]
 fn _normalize_path(path: &Path) -> PathBuf {
     PathBuf::from(path.to_string_lossy().replace('\\', "/"))
@@ -2,19 +2,33 @@ use std::collections::{BTreeMap, HashSet};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
+use futures_util::stream::{self, StreamExt};
+use html2text::render::text_renderer::TrivialDecorator;
 use once_cell::sync::Lazy;
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
 use regex::Regex;
 use reqwest::{Client, Url};
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
 
 use crate::config::KiwixConfig;
-use crate::search::SearchHit;
+use crate::retry::RetryPolicy;
+use crate::search::{escape_html, SearchHit};
 
 static HEADER_TOTAL_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?i)\bof\s+([0-9,]+)\b").expect("valid total regex"));
 
+/// Length in bytes of a snippet cut from a fetched article.
+/// Score of a collection's first hit; each later hit scores one less.
+pub const TOP_HIT_SCORE: f32 = 500.0;
+
+const FETCHED_SNIPPET_LEN: usize = 280;
+
+/// Line width handed to html2text; wide enough that articles are not
+/// wrapped before their whitespace is collapsed.
+const ARTICLE_WRAP_WIDTH: usize = 10_000;
+
 static CONTENT_ID_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"/content/([^/?#]+)").expect("valid content id regex"));
 
@@ -25,10 +39,27 @@ pub struct KiwixCollection {
     pub category: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct KiwixSearchResult {
     pub total_hits: usize,
     pub hits: Vec<SearchHit>,
+    /// Total hits per collection, keyed by `kiwix:<collection id>`.
+    pub source_totals: BTreeMap<String, usize>,
+    /// True when at least one collection timed out and is missing.
+    pub partial: bool,
+    /// Doc ids of hits that came without a `<cite>` snippet and carry the
+    /// `From <collection>` preview.
+    pub without_snippet: HashSet<String>,
+}
+
+/// One entry of a Kiwix `/suggest` response. The last entry is usually a
+/// `kind: "pattern"` link to a full-text search, which is not a title.
+#[derive(Debug, Deserialize)]
+struct KiwixSuggestion {
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    kind: String,
 }
 
 #[derive(Clone)]
@@ -37,6 +68,12 @@ pub struct KiwixClient {
     base_url: Url,
     collections: Vec<KiwixCollection>,
     max_hits_per_collection: usize,
+    max_concurrent_requests: usize,
+    retry: RetryPolicy,
+    /// Top hits whose missing snippets are fetched (0 with `fetch_snippets`
+    /// off).
+    fetch_snippets_max_hits: usize,
+    fetch_snippets_timeout: Duration,
 }
 
 impl KiwixClient {
@@ -46,6 +83,7 @@ impl KiwixClient {
             .timeout(Duration::from_secs(config.timeout_secs))
             .build()
             .context("failed to build Kiwix HTTP client")?;
+        let retry = RetryPolicy::new(config.max_retries, config.retry_base_delay_ms);
 
         let categories: HashSet<String> = config
             .categories
@@ -58,7 +96,7 @@ impl KiwixClient {
             || !categories.is_empty()
             || config.collections.is_empty()
         {
-            discover_collections(&client, &base_url).await?
+            discover_collections(&client, &base_url, retry).await?
         } else {
             Vec::new()
         };
@@ -99,6 +137,14 @@ impl KiwixClient {
             base_url,
             collections,
             max_hits_per_collection: config.max_hits_per_collection.max(1),
+            max_concurrent_requests: config.max_concurrent_requests.max(1),
+            retry,
+            fetch_snippets_max_hits: if config.fetch_snippets {
+                config.fetch_snippets_max_hits
+            } else {
+                0
+            },
+            fetch_snippets_timeout: Duration::from_millis(config.fetch_snippets_timeout_ms),
         })
     }
 
@@ -113,36 +159,73 @@ impl KiwixClient {
         self.collections.len()
     }
 
+    /// Fetches the server's landing page once, without retries, to tell
+    /// whether Kiwix is reachable.
+    pub async fn ping(&self, timeout: Duration) -> Result<()> {
+        self.client
+            .get(self.base_url.clone())
+            .timeout(timeout)
+            .send()
+            .await
+            .context("failed to reach Kiwix")?
+            .error_for_status()
+            .context("Kiwix returned non-success status")?;
+        Ok(())
+    }
+
     pub async fn search(
         &self,
         query: &str,
-        source_filter: Option<&str>,
+        source_filters: &[&str],
         limit: usize,
+        timeout: Duration,
     ) -> Result<KiwixSearchResult> {
         if query.trim().is_empty() || limit == 0 {
-            return Ok(KiwixSearchResult {
-                total_hits: 0,
-                hits: Vec::new(),
-            });
+            return Ok(KiwixSearchResult::default());
         }
 
-        let selected = self.filtered_collections(source_filter);
+        let selected = self.filtered_collections(source_filters);
         if selected.is_empty() {
-            return Ok(KiwixSearchResult {
-                total_hits: 0,
-                hits: Vec::new(),
-            });
+            return Ok(KiwixSearchResult::default());
         }
 
         let mut total_hits = 0usize;
         let mut hits = Vec::new();
+        let mut source_totals = BTreeMap::new();
+        let mut partial = false;
+        let mut without_snippet = HashSet::new();
         let page_len = self.max_hits_per_collection.max(limit.max(1)).min(75);
 
-        for collection in selected {
-            match self.search_collection(collection, query, page_len).await {
+        // Collections are queried concurrently; `buffered` still yields the
+        // outcomes in collection order so equal scores merge deterministically.
+        let searches = selected
+            .into_iter()
+            .map(|collection| {
+                let search = self.search_collection(collection, query, page_len);
+                async move { (collection, tokio::time::timeout(timeout, search).await) }
+            })
+            .collect::<Vec<_>>();
+        let mut outcomes = stream::iter(searches).buffered(self.max_concurrent_requests);
+
+        while let Some((collection, outcome)) = outcomes.next().await {
+            let Ok(outcome) = outcome else {
+                tracing::warn!(
+                    collection = %collection.id,
+                    timeout_ms = timeout.as_millis() as u64,
+                    "Kiwix collection query timed out"
+                );
+                partial = true;
+                continue;
+            };
+
+            match outcome {
                 Ok(result) => {
                     total_hits += result.total_hits;
+                    if result.total_hits > 0 {
+                        source_totals.insert(format!("kiwix:{}", collection.id), result.total_hits);
+                    }
                     hits.extend(result.hits);
+                    without_snippet.extend(result.without_snippet);
                 }
                 Err(err) => {
                     tracing::warn!(
@@ -155,31 +238,156 @@ impl KiwixClient {
         }
 
         hits.sort_by(|left, right| right.score.total_cmp(&left.score));
+        if self.fetch_snippets_max_hits > 0 {
+            self.fetch_snippets(query, &mut hits, &without_snippet)
+                .await;
+        }
 
-        Ok(KiwixSearchResult { total_hits, hits })
+        Ok(KiwixSearchResult {
+            total_hits,
+            hits,
+            source_totals,
+            partial,
+            without_snippet,
+        })
     }
 
-    fn filtered_collections(&self, source_filter: Option<&str>) -> Vec<&KiwixCollection> {
-        let Some(filter) = source_filter
-            .map(str::trim)
-            .filter(|value| !value.is_empty())
-        else {
-            return self.collections.iter().collect();
-        };
+    /// Replaces the `From <collection>` previews among the top
+    /// `fetch_snippets_max_hits` hits with snippets cut from their articles.
+    /// Articles that fail or take longer than `fetch_snippets_timeout` keep
+    /// the fallback.
+    async fn fetch_snippets(
+        &self,
+        query: &str,
+        hits: &mut [SearchHit],
+        without_snippet: &HashSet<String>,
+    ) {
+        let fetches = hits
+            .iter()
+            .enumerate()
+            .take(self.fetch_snippets_max_hits)
+            .filter(|(_, hit)| without_snippet.contains(&hit.doc_id))
+            .filter_map(|(idx, hit)| Some((idx, hit.url.clone()?)))
+            .map(|(idx, url)| async move {
+                let text = self.article_text(&url);
+                let outcome = tokio::time::timeout(self.fetch_snippets_timeout, text).await;
+                (idx, url, outcome)
+            })
+            .collect::<Vec<_>>();
+        let mut outcomes = stream::iter(fetches).buffer_unordered(self.max_concurrent_requests);
+
+        while let Some((idx, url, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(Ok(text)) => {
+                    if let Some((preview, highlighted)) =
+                        query_snippet(&text, query, FETCHED_SNIPPET_LEN)
+                    {
+                        hits[idx].preview = preview;
+                        hits[idx].highlighted_preview = highlighted;
+                    }
+                }
+                Ok(Err(err)) => {
+                    tracing::debug!(url, error = %format!("{err:#}"), "failed to fetch Kiwix article for a snippet");
+                }
+                Err(_) => {
+                    tracing::debug!(
+                        url,
+                        timeout_ms = self.fetch_snippets_timeout.as_millis() as u64,
+                        "Kiwix article fetch for a snippet timed out"
+                    );
+                }
+            }
+        }
+    }
 
-        if filter.eq_ignore_ascii_case("kiwix") {
-            return self.collections.iter().collect();
+    /// The text of the article at `url`, whitespace-collapsed.
+    async fn article_text(&self, url: &str) -> Result<String> {
+        let html = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .context("failed to call Kiwix content endpoint")?
+            .error_for_status()
+            .context("Kiwix content returned non-success status")?
+            .bytes()
+            .await
+            .context("failed reading Kiwix article body")?;
+        tokio::task::spawn_blocking(move || {
+            let text = html2text::from_read_with_decorator(
+                &html[..],
+                ARTICLE_WRAP_WIDTH,
+                TrivialDecorator::new(),
+            );
+            normalize_ws(&text)
+        })
+        .await
+        .context("failed to convert Kiwix article to text")
+    }
+
+    /// Article titles starting with `partial`, from each collection's
+    /// `/suggest` endpoint, in collection order. Collections that fail or run
+    /// over `timeout` are logged and left out.
+    pub async fn suggest(&self, partial: &str, limit: usize, timeout: Duration) -> Vec<String> {
+        let partial = partial.trim();
+        if partial.is_empty() || limit == 0 {
+            return Vec::new();
         }
 
-        if let Some(collection_id) = filter.strip_prefix("kiwix:") {
-            return self
-                .collections
+        let lookups = self
+            .collections
+            .iter()
+            .map(|collection| {
+                let lookup = self.suggest_collection(collection, partial, limit);
+                async move { (collection, tokio::time::timeout(timeout, lookup).await) }
+            })
+            .collect::<Vec<_>>();
+        let mut outcomes = stream::iter(lookups).buffered(self.max_concurrent_requests);
+
+        let mut suggestions = Vec::new();
+        while let Some((collection, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(Ok(titles)) => suggestions.extend(titles),
+                Ok(Err(err)) => {
+                    tracing::warn!(
+                        collection = %collection.id,
+                        error = %err,
+                        "Kiwix suggest query failed"
+                    );
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        collection = %collection.id,
+                        timeout_ms = timeout.as_millis() as u64,
+                        "Kiwix suggest query timed out"
+                    );
+                }
+            }
+        }
+
+        suggestions
+    }
+
+    /// Collections matching any of `source_filters` (`kiwix` or
+    /// `kiwix:<id>`); all of them when there is no filter.
+    fn filtered_collections(&self, source_filters: &[&str]) -> Vec<&KiwixCollection> {
+        if source_filters.is_empty()
+            || source_filters
                 .iter()
-                .filter(|entry| entry.id == collection_id)
-                .collect();
+                .any(|filter| filter.eq_ignore_ascii_case("kiwix"))
+        {
+            return self.collections.iter().collect();
         }
 
-        Vec::new()
+        self.collections
+            .iter()
+            .filter(|entry| {
+                source_filters
+                    .iter()
+                    .filter_map(|filter| filter.strip_prefix("kiwix:"))
+                    .any(|collection_id| entry.id == collection_id)
+            })
+            .collect()
     }
 
     async fn search_collection(
@@ -196,15 +404,15 @@ impl KiwixClient {
         let page_len_str = page_len.to_string();
 
         let response = self
-            .client
-            .get(search_url)
-            .query(&[
-                ("content", collection.id.as_str()),
-                ("pattern", query),
-                ("start", "0"),
-                ("pageLength", page_len_str.as_str()),
-            ])
-            .send()
+            .retry
+            .send("kiwix search", || {
+                self.client.get(search_url.clone()).query(&[
+                    ("content", collection.id.as_str()),
+                    ("pattern", query),
+                    ("start", "0"),
+                    ("pageLength", page_len_str.as_str()),
+                ])
+            })
             .await
             .context("failed to call Kiwix search endpoint")?
             .error_for_status()
@@ -217,6 +425,45 @@ impl KiwixClient {
 
         parse_search_html(&self.base_url, collection, &body)
     }
+
+    async fn suggest_collection(
+        &self,
+        collection: &KiwixCollection,
+        partial: &str,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let suggest_url = self
+            .base_url
+            .join("suggest")
+            .context("failed to construct Kiwix suggest URL")?;
+
+        let count = limit.to_string();
+
+        let entries: Vec<KiwixSuggestion> = self
+            .client
+            .get(suggest_url)
+            .query(&[
+                ("content", collection.id.as_str()),
+                ("term", partial),
+                ("count", count.as_str()),
+            ])
+            .send()
+            .await
+            .context("failed to call Kiwix suggest endpoint")?
+            .error_for_status()
+            .context("Kiwix suggest returned non-success status")?
+            .json()
+            .await
+            .context("failed to parse Kiwix suggest response")?;
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.kind != "pattern")
+            .map(|entry| entry.value.trim().to_string())
+            .filter(|title| !title.is_empty())
+            .take(limit)
+            .collect())
+    }
 }
 
 fn normalize_base_url(raw: &str) -> Result<Url> {
@@ -228,14 +475,17 @@ fn normalize_base_url(raw: &str) -> Result<Url> {
     Url::parse(&base).with_context(|| format!("invalid Kiwix base_url '{raw}'"))
 }
 
-async fn discover_collections(client: &Client, base_url: &Url) -> Result<Vec<KiwixCollection>> {
+async fn discover_collections(
+    client: &Client,
+    base_url: &Url,
+    retry: RetryPolicy,
+) -> Result<Vec<KiwixCollection>> {
     let catalog_url = base_url
         .join("catalog/v2/entries")
         .context("failed to build Kiwix OPDS URL")?;
 
-    let xml = client
-        .get(catalog_url)
-        .send()
+    let xml = retry
+        .send("kiwix catalog", || client.get(catalog_url.clone()))
         .await
         .context("failed to fetch Kiwix OPDS feed")?
         .error_for_status()
@@ -396,6 +646,7 @@ fn parse_search_html(
         .unwrap_or_default();
 
     let mut hits = Vec::new();
+    let mut without_snippet = HashSet::new();
     for (idx, row) in document.select(&RESULT_SELECTOR).enumerate() {
         let Some(link) = row.select(&LINK_SELECTOR).next() else {
             continue;
@@ -413,11 +664,17 @@ fn parse_search_html(
             .map(|snippet| snippet.inner_html())
             .unwrap_or_default();
 
-        let preview = preview_from_html(&preview_html);
-        let preview = if preview.is_empty() {
-            format!("From {}", collection.title)
+        let (preview, highlighted_preview) = preview_from_html(&preview_html);
+        let doc_id = format!("kiwix:{}:{}", collection.id, href);
+        if preview.is_empty() {
+            without_snippet.insert(doc_id.clone());
+        }
+        let (preview, highlighted_preview) = if preview.is_empty() {
+            let fallback = format!("From {}", collection.title);
+            let escaped = escape_html(&fallback);
+            (fallback, escaped)
         } else {
-            preview
+            (preview, highlighted_preview)
         };
 
         let absolute_url = if href.starts_with('/') {
@@ -427,29 +684,164 @@ fn parse_search_html(
         }
         .map(|url| url.to_string());
 
+        let title = if title.is_empty() {
+            "Untitled".to_string()
+        } else {
+            title
+        };
         hits.push(SearchHit {
-            score: 500.0 - idx as f32,
-            doc_id: format!("kiwix:{}:{}", collection.id, href),
+            score: TOP_HIT_SCORE - idx as f32,
+            doc_id,
             source: format!("kiwix:{}", collection.id),
-            title: if title.is_empty() {
-                "Untitled".to_string()
-            } else {
-                title
-            },
+            highlighted_title: escape_html(&title),
+            title,
+            highlighted_preview,
             preview,
             location: href,
             url: absolute_url,
+            date: None,
+            lang: None,
+            tags: Vec::new(),
+            explanation: None,
         });
     }
 
     let total_hits = parse_total_from_header(&header_text).unwrap_or(hits.len());
 
-    Ok(KiwixSearchResult { total_hits, hits })
+    Ok(KiwixSearchResult {
+        total_hits,
+        hits,
+        without_snippet,
+        ..KiwixSearchResult::default()
+    })
 }
 
-fn preview_from_html(html: &str) -> String {
-    let text = html2text::from_read(html.as_bytes(), 120);
-    normalize_ws(&text)
+/// A stretch of about `max_len` bytes of `text` holding as many query
+/// words as possible (the start of the text when none occurs), as plain
+/// text and HTML-escaped with the query words in `<mark>`. `None` when
+/// `text` is empty.
+fn query_snippet(text: &str, query: &str, max_len: usize) -> Option<(String, String)> {
+    if text.is_empty() {
+        return None;
+    }
+
+    let query_words: HashSet<String> = words(query)
+        .filter(|(_, word)| !matches!(*word, "AND" | "OR" | "NOT"))
+        .map(|(_, word)| word.to_lowercase())
+        .collect();
+    let matches: Vec<(usize, usize)> = words(text)
+        .filter(|(_, word)| query_words.contains(&word.to_lowercase()))
+        .map(|(start, word)| (start, start + word.len()))
+        .collect();
+
+    // The densest run of matches fits in the last three quarters of the
+    // window, leaving the first quarter for context before it.
+    let reach = max_len * 3 / 4;
+    let densest = (0..matches.len()).max_by_key(|&first| {
+        let covered = matches[first..]
+            .iter()
+            .take_while(|(_, end)| end - matches[first].0 <= reach)
+            .count();
+        (covered, std::cmp::Reverse(first))
+    });
+    let mut start = densest.map_or(0, |first| matches[first].0.saturating_sub(max_len - reach));
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    if start > 0 {
+        // Begin at a word, unless that would skip a match.
+        let first_match = densest.map_or(text.len(), |first| matches[first].0);
+        if let Some(space) = text[start..first_match].find(' ') {
+            start += space + 1;
+        }
+    }
+    let mut end = (start + max_len).min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    if end < text.len() {
+        if let Some(space) = text[start..end].rfind(' ') {
+            end = start + space;
+        }
+    }
+
+    let mut highlighted = String::with_capacity(end - start + 32);
+    let mut copied = start;
+    for &(match_start, match_end) in &matches {
+        if match_start < start || match_end > end {
+            continue;
+        }
+        highlighted.push_str(&escape_html(&text[copied..match_start]));
+        highlighted.push_str("<mark>");
+        highlighted.push_str(&escape_html(&text[match_start..match_end]));
+        highlighted.push_str("</mark>");
+        copied = match_end;
+    }
+    highlighted.push_str(&escape_html(&text[copied..end]));
+    Some((text[start..end].to_string(), highlighted))
+}
+
+/// Runs of letters and digits in `text` with their byte offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut rest = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = rest.find(|(_, ch)| ch.is_alphanumeric())?;
+        let mut end = text.len();
+        while let Some(&(index, ch)) = rest.peek() {
+            if !ch.is_alphanumeric() {
+                end = index;
+                break;
+            }
+            rest.next();
+        }
+        Some((start, &text[start..end]))
+    })
+}
+
+// Private-use characters bracketing highlighted text while the snippet is
+// normalized; they never occur in Kiwix snippets.
+const MARK_OPEN: char = '\u{E000}';
+const MARK_CLOSE: char = '\u{E001}';
+
+/// Converts a Kiwix `<cite>` snippet into plain text and an HTML-escaped
+/// variant in which Kiwix's `<b>`/`<strong>`/`<mark>` emphasis becomes the
+/// `<mark>` used for local snippets.
+fn preview_from_html(html: &str) -> (String, String) {
+    let fragment = Html::parse_fragment(html);
+    let mut marked = String::with_capacity(html.len());
+    collect_marked_text(fragment.root_element(), false, &mut marked);
+    let marked = normalize_ws(&marked);
+
+    let text: String = marked
+        .chars()
+        .filter(|ch| *ch != MARK_OPEN && *ch != MARK_CLOSE)
+        .collect();
+    let highlighted = escape_html(&marked)
+        .replace(MARK_OPEN, "<mark>")
+        .replace(MARK_CLOSE, "</mark>")
+        .replace("<mark></mark>", "");
+    (text, highlighted)
+}
+
+fn collect_marked_text(element: ElementRef<'_>, in_mark: bool, out: &mut String) {
+    for child in element.children() {
+        if let Some(text) = child.value().as_text() {
+            out.push_str(text);
+            continue;
+        }
+        let Some(child) = ElementRef::wrap(child) else {
+            continue;
+        };
+        match child.value().name() {
+            "br" => out.push(' '),
+            "b" | "strong" | "mark" if !in_mark => {
+                out.push(MARK_OPEN);
+                collect_marked_text(child, true, out);
+                out.push(MARK_CLOSE);
+            }
+            _ => collect_marked_text(child, in_mark, out),
+        }
+    }
 }
 
 fn parse_total_from_header(header_text: &str) -> Option<usize> {
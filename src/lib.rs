@@ -0,0 +1,63 @@
+//! Local full-text search for offline datasets.
+//!
+//! The `bunker-search` binary is a thin CLI over this crate. To embed search
+//! in another Rust program, load an [`AppConfig`], build or update its index
+//! with [`index_sources`], and query it through [`SearchEngine`]:
+//!
+//! ```no_run
+//! use bunker_search::{
+//!     index_sources, AppConfig, Progress, QueryMode, SearchEngine, SearchFilters, SortOrder,
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let config = AppConfig::from_file("config.toml")?;
+//! index_sources(&config, false, &Progress::new(false))?;
+//!
+//! let engine = SearchEngine::open(&config)?;
+//! let result = engine.search(
+//!     "solar panels",
+//!     10,
+//!     0,
+//!     &SearchFilters::default(),
+//!     QueryMode::default(),
+//!     false,
+//!     SortOrder::default(),
+//! )?;
+//! for hit in result.hits {
+//!     println!("{:.2} {}", hit.score, hit.title);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The items re-exported here are the supported surface; the modules also
+//! expose what the CLI needs (`server::serve`, `watch::watch_indexes`,
+//! `embeddings::update_embeddings`, `bench::run_bench`) and may change more
+//! freely.
+
+pub mod bench;
+pub mod config;
+pub mod embeddings;
+pub mod indexer;
+pub mod progress;
+pub mod search;
+pub mod server;
+pub mod watch;
+
+mod auth;
+mod cache;
+mod cjk_tokenizer;
+mod code_tokenizer;
+mod ingest;
+mod kiwix;
+mod mail;
+mod manifest;
+mod ollama;
+mod query_log;
+mod rate_limit;
+mod retry;
+
+pub use config::AppConfig;
+pub use indexer::{index_sources, IndexStats};
+pub use progress::Progress;
+pub use search::{QueryMode, SearchEngine, SearchFilters, SearchHit, SearchResult, SortOrder};
@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::{DecodePaddingMode, Engine};
+use encoding_rs::{Encoding, UTF_8};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::search::parse_timestamp;
+
+/// Base64 as mail clients write it: padding is optional.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// An RFC 2047 encoded word: `=?charset?B|Q?text?=`.
+static ENCODED_WORD_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"=\?([^?\s]+)\?([BbQq])\?([^?\s]*)\?=").expect("valid encoded word regex")
+});
+
+/// One message (or MIME part): its unfolded headers and undecoded body.
+/// Only what indexing needs is read: header values, the first text part
+/// of a possibly multipart body, and dates.
+pub struct Mail<'a> {
+    headers: Vec<(String, String)>,
+    body: &'a [u8],
+}
+
+impl<'a> Mail<'a> {
+    pub fn parse(raw: &'a [u8]) -> Result<Self> {
+        let (head, body) = split_head(raw);
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in String::from_utf8_lossy(head).lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            match line.split_once(':') {
+                Some((name, value)) if !name.is_empty() && !name.contains(' ') => {
+                    headers.push((name.to_string(), value.trim().to_string()));
+                }
+                _ if headers.is_empty() => bail!("malformed header line `{line}`"),
+                // A stray line after valid headers is ignored.
+                _ => {}
+            }
+        }
+        Ok(Self { headers, body })
+    }
+
+    /// The first value of header `name`, with encoded words decoded.
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.raw_header(name).map(decode_encoded_words)
+    }
+
+    /// The decoded content of the first non-attachment part of type
+    /// `mimetype`, searching multipart bodies depth first.
+    pub fn text_part(&self, mimetype: &str) -> Option<String> {
+        let disposition = self.raw_header("Content-Disposition").unwrap_or_default();
+        if header_value(disposition).eq_ignore_ascii_case("attachment") {
+            return None;
+        }
+
+        let content_type = self.raw_header("Content-Type").unwrap_or("text/plain");
+        let own_type = header_value(content_type);
+        if own_type.to_ascii_lowercase().starts_with("multipart/") {
+            let boundary = header_param(content_type, "boundary")?;
+            return multipart_parts(self.body, &boundary)
+                .filter_map(|part| Mail::parse(part).ok())
+                .find_map(|part| part.text_part(mimetype));
+        }
+        if !own_type.eq_ignore_ascii_case(mimetype) {
+            return None;
+        }
+
+        let encoding = self
+            .raw_header("Content-Transfer-Encoding")
+            .unwrap_or_default();
+        let bytes: Cow<'_, [u8]> = match header_value(encoding).to_ascii_lowercase().as_str() {
+            "base64" => {
+                let compact: Vec<u8> = self
+                    .body
+                    .iter()
+                    .copied()
+                    .filter(|byte| !byte.is_ascii_whitespace())
+                    .collect();
+                Cow::Owned(BASE64.decode(compact).ok()?)
+            }
+            "quoted-printable" => Cow::Owned(
+                quoted_printable::decode(self.body, quoted_printable::ParseMode::Robust).ok()?,
+            ),
+            _ => Cow::Borrowed(self.body),
+        };
+        let charset = header_param(content_type, "charset").unwrap_or_default();
+        Some(decode_charset(&charset, &bytes))
+    }
+
+    fn raw_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits a message at the first blank line into header block and body.
+fn split_head(raw: &[u8]) -> (&[u8], &[u8]) {
+    if raw.starts_with(b"\r\n") {
+        return (&[], &raw[2..]);
+    }
+    if raw.starts_with(b"\n") {
+        return (&[], &raw[1..]);
+    }
+    let mut line_start = 0;
+    while let Some(end) = raw[line_start..].iter().position(|byte| *byte == b'\n') {
+        let next = line_start + end + 1;
+        match &raw[next..] {
+            [b'\n', ..] => return (&raw[..next], &raw[next + 1..]),
+            [b'\r', b'\n', ..] => return (&raw[..next], &raw[next + 2..]),
+            _ => line_start = next,
+        }
+    }
+    (raw, &[])
+}
+
+/// The parts between `--boundary` lines, up to the closing `--boundary--`.
+fn multipart_parts<'a>(body: &'a [u8], boundary: &str) -> impl Iterator<Item = &'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut part_start = None;
+    let mut offset = 0;
+    for line in body.split_inclusive(|byte| *byte == b'\n') {
+        let trimmed = line.trim_ascii_end();
+        if let Some(rest) = trimmed.strip_prefix(delimiter.as_bytes()) {
+            if rest.is_empty() || rest == b"--" {
+                if let Some(start) = part_start {
+                    // The line break before a delimiter belongs to it.
+                    let end = body[..offset]
+                        .strip_suffix(b"\r\n")
+                        .or_else(|| body[..offset].strip_suffix(b"\n"))
+                        .map_or(offset, <[u8]>::len);
+                    parts.push(&body[start..end.max(start)]);
+                }
+                if rest == b"--" {
+                    break;
+                }
+                part_start = Some(offset + line.len());
+            }
+        }
+        offset += line.len();
+    }
+    parts.into_iter()
+}
+
+/// The value of a structured header without its `; name=value` parameters.
+fn header_value(value: &str) -> &str {
+    value.split(';').next().unwrap_or_default().trim()
+}
+
+/// Parameter `name` of a structured header, unquoted.
+fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    let encoding = Encoding::for_label(charset.trim().as_bytes()).unwrap_or(UTF_8);
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// Decodes RFC 2047 encoded words, dropping the whitespace between two
+/// adjacent ones as the RFC asks.
+fn decode_encoded_words(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut copied = 0;
+    for word in ENCODED_WORD_RE.captures_iter(value) {
+        let whole = word.get(0).expect("match has a whole group");
+        let gap = &value[copied..whole.start()];
+        if copied == 0 || !gap.trim().is_empty() {
+            decoded.push_str(gap);
+        }
+        let text = &word[3];
+        let bytes = if word[2].eq_ignore_ascii_case("b") {
+            BASE64.decode(text).ok()
+        } else {
+            quoted_printable::decode(text.replace('_', " "), quoted_printable::ParseMode::Robust)
+                .ok()
+        };
+        match bytes {
+            // `charset*language` names a language too; only the charset matters.
+            Some(bytes) => {
+                let charset = word[1].split('*').next().unwrap_or_default();
+                decoded.push_str(&decode_charset(charset, &bytes));
+            }
+            None => decoded.push_str(whole.as_str()),
+        }
+        copied = whole.end();
+    }
+    decoded.push_str(&value[copied..]);
+    decoded
+}
+
+/// Unix seconds of an RFC 2822 date such as `Tue, 1 Jul 2003 10:52:37
+/// +0200`. The day name and seconds are optional, two-digit years are read
+/// as RFC 2822 says, and obsolete zone names are understood.
+pub fn parse_date(value: &str) -> Option<i64> {
+    let value = value
+        .split('(')
+        .next()
+        .unwrap_or_default()
+        .replace(',', " ");
+    let mut words = value.split_whitespace().peekable();
+    words.next_if(|word| word.chars().all(|ch| ch.is_ascii_alphabetic()));
+
+    let day: u32 = words.next()?.parse().ok()?;
+    let month = words.next()?.to_ascii_lowercase();
+    let month = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ]
+    .iter()
+    .position(|name| month.starts_with(name))?
+        + 1;
+    let year = words.next()?;
+    let mut year: i64 = year.parse().ok()?;
+    if year < 50 {
+        year += 2000;
+    } else if year < 1000 {
+        year += 1900;
+    }
+    let time = words.next().unwrap_or("00:00");
+    let offset = match words.next() {
+        Some(zone) => zone_offset(zone)?,
+        None => 0,
+    };
+
+    let local = parse_timestamp(&format!("{year:04}-{month:02}-{day:02}T{time}"))?;
+    Some(local - offset)
+}
+
+/// Seconds east of UTC of a numeric (`-0700`) or obsolete named zone.
+fn zone_offset(zone: &str) -> Option<i64> {
+    let hours = match zone.to_ascii_uppercase().as_str() {
+        "UT" | "UTC" | "GMT" | "Z" => 0,
+        "EDT" => -4,
+        "EST" | "CDT" => -5,
+        "CST" | "MDT" => -6,
+        "MST" | "PDT" => -7,
+        "PST" => -8,
+        _ => {
+            let sign = match zone.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let digits = zone.get(1..5)?;
+            let hours: i64 = digits[..2].parse().ok()?;
+            let minutes: i64 = digits[2..].parse().ok()?;
+            return Some(sign * (hours * 3600 + minutes * 60));
+        }
+    };
+    Some(hours * 3600)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_are_unfolded_and_decoded() {
+        let raw = b"Subject: =?UTF-8?B?S2VybmVs?=\n =?iso-8859-1?Q?_p=E4nic?= report\nFrom: a@example.org\n\nbody\n";
+        let mail = Mail::parse(raw).unwrap();
+        assert_eq!(
+            mail.header("subject").as_deref(),
+            Some("Kernel pänic report")
+        );
+        assert_eq!(mail.header("FROM").as_deref(), Some("a@example.org"));
+        assert_eq!(mail.text_part("text/plain").as_deref(), Some("body\n"));
+    }
+
+    #[test]
+    fn multipart_bodies_yield_their_text_part() {
+        let raw = concat!(
+            "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+            "preamble\r\n",
+            "--outer\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "Content-Disposition: attachment\r\n\r\n",
+            "attached notes\r\n",
+            "--outer\r\n",
+            "Content-Type: text/plain; charset=iso-8859-1\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+            "caf=E9 =\r\nopen\r\n",
+            "--outer--\r\n",
+        );
+        let mail = Mail::parse(raw.as_bytes()).unwrap();
+        assert_eq!(mail.text_part("text/plain").as_deref(), Some("café open"));
+        assert_eq!(mail.text_part("text/html"), None);
+    }
+
+    #[test]
+    fn base64_parts_are_decoded() {
+        let raw = b"Content-Type: text/html\nContent-Transfer-Encoding: base64\n\nPHA+aGk8L3A+\n";
+        let mail = Mail::parse(raw).unwrap();
+        assert_eq!(mail.text_part("text/html").as_deref(), Some("<p>hi</p>"));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        assert!(Mail::parse(b"not a header\n\nbody").is_err());
+    }
+
+    #[test]
+    fn rfc2822_dates() {
+        assert_eq!(
+            parse_date("Tue, 1 Jul 2003 10:52:37 +0200"),
+            Some(1_057_049_557)
+        );
+        assert_eq!(parse_date("1 Jul 2003 08:52 GMT"), Some(1_057_049_520));
+        assert_eq!(
+            parse_date("Tue,1 Jul 03 03:52:37 EST (comment)"),
+            Some(1_057_049_557)
+        );
+        assert_eq!(parse_date("not a date"), None);
+    }
+}
@@ -1,16 +1,11 @@
-mod config;
-mod indexer;
-mod ingest;
-mod kiwix;
-mod ollama;
-mod search;
-mod server;
-
 use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use bunker_search::bench::{self, BenchOptions, BenchReport};
+use bunker_search::config::LogFormat;
+use bunker_search::{embeddings, indexer, server, watch, AppConfig, IndexStats, Progress};
 use clap::{Parser, Subcommand};
-use config::AppConfig;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Debug, Parser)]
@@ -19,6 +14,10 @@ use tracing_subscriber::EnvFilter;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Log one JSON object per line (overrides `log_format`).
+    #[arg(long, global = true)]
+    log_json: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -32,6 +31,69 @@ enum Commands {
         /// Ignore manifest and rebuild all documents.
         #[arg(long)]
         rebuild: bool,
+
+        /// Keep running and re-index filesystem sources as files change.
+        #[arg(long)]
+        watch: bool,
+
+        /// Only build this named index (default: all indexes).
+        #[arg(long)]
+        index: Option<String>,
+
+        /// Show a progress bar (when stderr is a terminal).
+        #[arg(long)]
+        progress: bool,
+
+        /// Only index this source, leaving other sources' documents alone;
+        /// with --rebuild, rewrite all of its documents.
+        #[arg(
+            long,
+            value_name = "NAME",
+            conflicts_with_all = ["watch", "drop_source", "optimize", "vacuum", "dry_run", "export"]
+        )]
+        source: Option<String>,
+
+        /// Delete every document of this source instead of indexing.
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["rebuild", "watch"])]
+        drop_source: Option<String>,
+
+        /// Merge the index segments instead of indexing.
+        #[arg(long, conflicts_with_all = ["rebuild", "watch", "drop_source", "export"])]
+        optimize: bool,
+
+        /// Read every source and report what would be indexed, without
+        /// writing the index or its manifest.
+        #[arg(
+            long,
+            conflicts_with_all = ["watch", "drop_source", "optimize", "export"]
+        )]
+        dry_run: bool,
+
+        /// Drop manifest entries of documents missing from the index and
+        /// compact the manifest instead of indexing.
+        #[arg(
+            long,
+            conflicts_with_all = ["rebuild", "watch", "drop_source", "optimize", "dry_run", "export"]
+        )]
+        vacuum: bool,
+
+        /// Segments left by --optimize.
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "optimize")]
+        max_segments: usize,
+
+        /// Write every indexed document to this JSON Lines file instead of
+        /// indexing.
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["rebuild", "watch", "drop_source"]
+        )]
+        export: Option<PathBuf>,
+
+        /// Fail on the first unreadable file or invalid record instead of
+        /// skipping it with a warning.
+        #[arg(long)]
+        strict: bool,
     },
 
     /// Serve search API and embeddable widget.
@@ -40,35 +102,325 @@ enum Commands {
         #[arg(short, long, default_value = "config.toml")]
         config: PathBuf,
     },
+
+    /// Time a file of sample queries against the index and report latency
+    /// percentiles, hit counts and, with judgments, relevance metrics.
+    Bench {
+        /// Path to TOML config.
+        #[arg(short, long, default_value = "config.toml")]
+        config: PathBuf,
+
+        /// File with one query per line (`#` comments and blank lines are
+        /// skipped).
+        queries: PathBuf,
+
+        /// JSON Lines file of `{"query", "doc_id", "grade"}` relevance
+        /// judgments.
+        #[arg(long, value_name = "FILE")]
+        judgments: Option<PathBuf>,
+
+        /// Index to search (default: the first configured).
+        #[arg(long)]
+        index: Option<String>,
+
+        /// Hits fetched per query, and the cutoff of the relevance metrics.
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+
+        /// Times each query is run.
+        #[arg(long, default_value_t = 1)]
+        runs: usize,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_target(false)
-        .compact()
-        .init();
-
     let cli = Cli::parse();
 
+    let config_path = match &cli.command {
+        Commands::Index { config, .. }
+        | Commands::Serve { config }
+        | Commands::Bench { config, .. } => config,
+    };
+    let mut app_config = AppConfig::from_file(config_path)?;
+    init_logging(cli.log_json || app_config.log_format == LogFormat::Json);
+
     match cli.command {
-        Commands::Index { config, rebuild } => {
-            let app_config = AppConfig::from_file(config)?;
-            let stats = indexer::index_sources(&app_config, rebuild)?;
-            tracing::info!(
-                scanned = stats.scanned,
-                indexed = stats.indexed,
-                skipped = stats.skipped,
-                removed = stats.removed,
-                "indexing completed"
-            );
+        Commands::Index {
+            rebuild,
+            watch,
+            index,
+            progress,
+            drop_source,
+            optimize,
+            vacuum,
+            max_segments,
+            export,
+            dry_run,
+            source,
+            strict,
+            ..
+        } => {
+            if strict {
+                app_config.strict_ingest = true;
+            }
+            let mut index_configs = app_config.index_configs();
+            if let Some(index) = &index {
+                index_configs.retain(|(name, _)| name == index);
+                if index_configs.is_empty() {
+                    bail!("no index named `{index}` in the config");
+                }
+            }
+            if let Some(source) = &source {
+                index_configs.retain(|(_, index_config)| {
+                    index_config
+                        .sources
+                        .iter()
+                        .any(|config| config.name() == source)
+                });
+                if index_configs.is_empty() {
+                    bail!("no source named `{source}` in the config");
+                }
+            }
+
+            if let Some(path) = export {
+                let [(name, index_config)] = index_configs.as_slice() else {
+                    bail!("--export writes one index; pick it with --index <name>");
+                };
+                let (name, index_config) = (name.clone(), index_config.clone());
+                let exported = tokio::task::spawn_blocking(move || {
+                    indexer::export_documents(&index_config, &path)
+                })
+                .await
+                .context("export task failed")??;
+                tracing::info!(index = %name, exported, "index exported");
+                return Ok(());
+            }
+
+            if let Some(source) = drop_source {
+                for (name, index_config) in index_configs {
+                    let source = source.clone();
+                    let removed = tokio::task::spawn_blocking(move || {
+                        indexer::drop_source(&index_config, &source)
+                    })
+                    .await
+                    .context("drop-source task failed")??;
+                    tracing::info!(index = %name, removed, "source documents removed");
+                }
+                return Ok(());
+            }
+
+            if optimize {
+                for (name, index_config) in index_configs {
+                    let stats = tokio::task::spawn_blocking(move || {
+                        indexer::optimize_index(&index_config, max_segments)
+                    })
+                    .await
+                    .context("optimize task failed")??;
+                    tracing::info!(
+                        index = %name,
+                        segments_before = stats.segments_before,
+                        segments_after = stats.segments_after,
+                        "index optimized"
+                    );
+                }
+                return Ok(());
+            }
+
+            if vacuum {
+                for (name, index_config) in index_configs {
+                    let stats = tokio::task::spawn_blocking(move || {
+                        indexer::vacuum_manifest(&index_config)
+                    })
+                    .await
+                    .context("vacuum task failed")??;
+                    tracing::info!(
+                        index = %name,
+                        removed = stats.removed,
+                        bytes_before = stats.bytes_before,
+                        bytes_after = stats.bytes_after,
+                        "manifest vacuumed"
+                    );
+                }
+                return Ok(());
+            }
+
+            if dry_run {
+                for (name, index_config) in index_configs {
+                    let stats = tokio::task::spawn_blocking(move || {
+                        indexer::dry_run(&index_config, rebuild, &Progress::new(progress))
+                    })
+                    .await
+                    .context("dry-run task failed")??;
+                    tracing::info!(
+                        index = %name,
+                        scanned = stats.scanned,
+                        indexed = stats.indexed,
+                        skipped = stats.skipped,
+                        removed = stats.removed,
+                        failed = stats.failed,
+                        elapsed_ms = stats.elapsed.as_millis() as u64,
+                        "dry run completed; nothing was written"
+                    );
+                    print_index_summary(&name, &stats);
+                }
+                return Ok(());
+            }
+
+            for (name, index_config) in &index_configs {
+                // Indexing blocks (file reads, remote downloads), so it runs
+                // off the async runtime.
+                let blocking_config = index_config.clone();
+                let only_source = source.clone();
+                let stats = tokio::task::spawn_blocking(move || {
+                    let progress = Progress::new(progress);
+                    match only_source {
+                        Some(source) => {
+                            indexer::index_source(&blocking_config, &source, rebuild, &progress)
+                        }
+                        None => indexer::index_sources(&blocking_config, rebuild, &progress),
+                    }
+                })
+                .await
+                .context("indexing task failed")??;
+                tracing::info!(
+                    index = %name,
+                    scanned = stats.scanned,
+                    indexed = stats.indexed,
+                    skipped = stats.skipped,
+                    removed = stats.removed,
+                    failed = stats.failed,
+                    evicted = stats.evicted,
+                    elapsed_ms = stats.elapsed.as_millis() as u64,
+                    "indexing completed"
+                );
+                print_index_summary(name, &stats);
+
+                if let Some(embedding_stats) =
+                    embeddings::update_embeddings(index_config, rebuild && source.is_none()).await?
+                {
+                    tracing::info!(
+                        index = %name,
+                        embedded = embedding_stats.embedded,
+                        unchanged = embedding_stats.unchanged,
+                        removed = embedding_stats.removed,
+                        "embeddings updated"
+                    );
+                }
+            }
+
+            if watch {
+                watch::watch_indexes(&index_configs).await?;
+            }
         }
-        Commands::Serve { config } => {
-            let app_config = AppConfig::from_file(config)?;
+        Commands::Serve { .. } => {
             server::serve(app_config).await?;
         }
+        Commands::Bench {
+            queries,
+            judgments,
+            index,
+            limit,
+            runs,
+            ..
+        } => {
+            let options = BenchOptions {
+                index: index.as_deref(),
+                limit,
+                runs,
+            };
+            let report =
+                bench::run_bench(&app_config, &queries, judgments.as_deref(), &options).await?;
+            print_bench_report(&report);
+        }
     }
 
     Ok(())
 }
+
+/// Prints the per-source breakdown of an indexing pass to stdout, so the
+/// slow source of a long run stands out.
+fn print_index_summary(index: &str, stats: &IndexStats) {
+    let width = stats
+        .sources
+        .iter()
+        .map(|source| source.name.len())
+        .chain(["SOURCE".len(), "total".len()])
+        .max()
+        .unwrap_or_default();
+    let row =
+        |name: &str, elapsed: Duration, scanned: u64, indexed: u64, skipped: u64, failed: u64| {
+            println!(
+                "{name:<width$}  {:>9}  {scanned:>9}  {indexed:>9}  {skipped:>9}  {failed:>6}",
+                format!("{:.2}s", elapsed.as_secs_f64())
+            );
+        };
+
+    println!("index `{index}`");
+    println!(
+        "{:<width$}  {:>9}  {:>9}  {:>9}  {:>9}  {:>6}",
+        "SOURCE", "TIME", "SCANNED", "INDEXED", "SKIPPED", "FAILED"
+    );
+    for source in &stats.sources {
+        row(
+            &source.name,
+            source.elapsed,
+            source.scanned,
+            source.indexed,
+            source.skipped,
+            source.failed,
+        );
+    }
+    row(
+        "total",
+        stats.elapsed,
+        stats.scanned,
+        stats.indexed,
+        stats.skipped,
+        stats.failed,
+    );
+}
+
+fn init_logging(json: bool) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_target(false);
+    if json {
+        builder.json().flatten_event(true).init();
+    } else {
+        builder.compact().init();
+    }
+}
+
+/// Prints a `bench` run to stdout.
+fn print_bench_report(report: &BenchReport) {
+    let ms = |latency: Duration| format!("{:.1}ms", latency.as_secs_f64() * 1000.0);
+    println!(
+        "{} queries x {} runs: p50 {}  p90 {}  p99 {}  max {}",
+        report.queries,
+        report.runs,
+        ms(report.latency_p50),
+        ms(report.latency_p90),
+        ms(report.latency_p99),
+        ms(report.latency_max)
+    );
+    println!(
+        "mean total hits {:.1}, {} queries without hits",
+        report.mean_total_hits,
+        report.zero_hit_queries.len()
+    );
+    for query in &report.zero_hit_queries {
+        println!("  no hits: {query}");
+    }
+    if let Some(relevance) = &report.relevance {
+        let k = relevance.cutoff;
+        println!(
+            "{} judged queries: P@{k} {:.3}  R@{k} {:.3}  MRR {:.3}  nDCG@{k} {:.3}",
+            relevance.judged_queries,
+            relevance.precision,
+            relevance.recall,
+            relevance.mrr,
+            relevance.ndcg
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Deserialize;
+
+use crate::ingest::HttpValidators;
+
+const MANIFEST_FILE: &str = "manifest.sqlite";
+/// The single JSON document older versions kept; imported on first open.
+const LEGACY_MANIFEST_FILE: &str = "manifest.json";
+const HTTP_VALIDATORS_KEY: &str = "http_validators";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS docs (
+    doc_id TEXT PRIMARY KEY NOT NULL,
+    fingerprint TEXT NOT NULL,
+    indexed_at INTEGER,
+    evicted_source TEXT
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+) WITHOUT ROWID;
+";
+
+/// Doc ids a run has read. A temp table spills to disk like the others and
+/// vanishes with the connection.
+const SEEN_SCHEMA: &str =
+    "CREATE TEMP TABLE IF NOT EXISTS seen (doc_id TEXT PRIMARY KEY NOT NULL) WITHOUT ROWID;";
+
+/// Fingerprint, write time and eviction of every indexed document, plus the
+/// HTTP validators of remote sources, in an SQLite file beside the index.
+/// Lookups go to disk, so a run over millions of documents never holds the
+/// whole manifest in memory.
+///
+/// Every change since opening is one transaction, made durable by `commit`;
+/// dropping the manifest without committing rolls them back, so it never
+/// gets ahead of the last index commit.
+pub struct Manifest {
+    connection: Connection,
+}
+
+/// `manifest.json` as older versions wrote it.
+#[derive(Debug, Default, Deserialize)]
+struct LegacyManifest {
+    docs: BTreeMap<String, String>,
+    #[serde(default)]
+    http: HttpValidators,
+    #[serde(default)]
+    indexed_at: BTreeMap<String, i64>,
+    #[serde(default)]
+    evicted: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Opens (or creates) the manifest of `index_dir`, importing and then
+    /// removing a `manifest.json` left by an older version.
+    pub fn open(index_dir: &Path) -> Result<Self> {
+        let path = index_dir.join(MANIFEST_FILE);
+        let legacy_path = index_dir.join(LEGACY_MANIFEST_FILE);
+        let legacy = if legacy_path.exists() && !path.exists() {
+            Some(load_legacy(&legacy_path)?)
+        } else {
+            None
+        };
+
+        fs::create_dir_all(index_dir)
+            .with_context(|| format!("failed to create manifest dir {}", index_dir.display()))?;
+        let connection = Connection::open(&path)
+            .with_context(|| format!("failed to open manifest at {}", path.display()))?;
+        let manifest = Self::with_schema(connection, &path)?;
+        manifest.begin()?;
+        if let Some(legacy) = legacy {
+            if let Err(err) = manifest.import(legacy) {
+                drop(manifest);
+                let _ = fs::remove_file(&path);
+                return Err(err.context(format!(
+                    "failed to import manifest from {}",
+                    legacy_path.display()
+                )));
+            }
+            tracing::info!(
+                from = %legacy_path.display(),
+                to = %path.display(),
+                "moved the manifest to SQLite"
+            );
+        }
+        // Also covers a crash between the import commit and this removal.
+        if legacy_path.exists() {
+            fs::remove_file(&legacy_path)
+                .with_context(|| format!("failed to remove {}", legacy_path.display()))?;
+        }
+        Ok(manifest)
+    }
+
+    /// Opens the manifest of `index_dir` without ever writing to disk, for
+    /// dry runs: changes stay in the (never committed) transaction, and a
+    /// legacy `manifest.json` is read into memory instead of imported.
+    pub fn open_read_only(index_dir: &Path) -> Result<Self> {
+        let path = index_dir.join(MANIFEST_FILE);
+        let legacy_path = index_dir.join(LEGACY_MANIFEST_FILE);
+        let manifest = if path.exists() {
+            let connection =
+                Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                    .with_context(|| format!("failed to open manifest at {}", path.display()))?;
+            connection
+                .execute_batch(SEEN_SCHEMA)
+                .with_context(|| format!("failed to prepare manifest at {}", path.display()))?;
+            Self { connection }
+        } else if legacy_path.exists() {
+            let manifest = Self::in_memory()?;
+            manifest.import(load_legacy(&legacy_path)?)?;
+            manifest
+        } else {
+            return Self::in_memory();
+        };
+        manifest.begin()?;
+        Ok(manifest)
+    }
+
+    /// An empty manifest that lives in memory only.
+    pub fn in_memory() -> Result<Self> {
+        let connection =
+            Connection::open_in_memory().context("failed to open in-memory manifest")?;
+        let manifest = Self::with_schema(connection, Path::new(":memory:"))?;
+        manifest.begin()?;
+        Ok(manifest)
+    }
+
+    fn with_schema(connection: Connection, path: &Path) -> Result<Self> {
+        connection
+            .execute_batch(SCHEMA)
+            .and_then(|()| connection.execute_batch(SEEN_SCHEMA))
+            .with_context(|| format!("failed to create manifest tables in {}", path.display()))?;
+        Ok(Self { connection })
+    }
+
+    /// Adds the entries of `legacy` and commits them.
+    fn import(&self, legacy: LegacyManifest) -> Result<()> {
+        for (doc_id, fingerprint) in &legacy.docs {
+            self.connection
+                .prepare_cached("INSERT OR REPLACE INTO docs VALUES (?1, ?2, ?3, ?4)")?
+                .execute(params![
+                    doc_id,
+                    fingerprint,
+                    legacy.indexed_at.get(doc_id),
+                    legacy.evicted.get(doc_id),
+                ])?;
+        }
+        self.set_http_validators(&legacy.http)?;
+        self.commit()
+    }
+
+    fn begin(&self) -> Result<()> {
+        self.connection
+            .execute_batch("BEGIN")
+            .context("failed to start manifest transaction")
+    }
+
+    /// Makes every change since opening durable and starts a new
+    /// transaction.
+    pub fn commit(&self) -> Result<()> {
+        self.connection
+            .execute_batch("COMMIT")
+            .context("failed to write manifest")?;
+        self.begin()
+    }
+
+    /// Forgets every document and HTTP validator, for a full rebuild.
+    pub fn clear(&self) -> Result<()> {
+        self.connection
+            .execute_batch("DELETE FROM docs; DELETE FROM meta;")
+            .context("failed to clear manifest")
+    }
+
+    pub fn fingerprint(&self, doc_id: &str) -> Result<Option<String>> {
+        self.connection
+            .prepare_cached("SELECT fingerprint FROM docs WHERE doc_id = ?1")?
+            .query_row([doc_id], |row| row.get(0))
+            .optional()
+            .context("failed to read manifest")
+    }
+
+    pub fn contains(&self, doc_id: &str) -> Result<bool> {
+        Ok(self.fingerprint(doc_id)?.is_some())
+    }
+
+    /// Records a document just written to the index at `indexed_at`; a
+    /// rewritten document is no longer evicted.
+    pub fn record_indexed(&self, doc_id: &str, fingerprint: &str, indexed_at: i64) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO docs VALUES (?1, ?2, ?3, NULL)
+                 ON CONFLICT (doc_id) DO UPDATE SET fingerprint = excluded.fingerprint,
+                     indexed_at = excluded.indexed_at, evicted_source = NULL",
+            )?
+            .execute(params![doc_id, fingerprint, indexed_at])
+            .context("failed to update manifest")?;
+        Ok(())
+    }
+
+    /// Records a document evicted from the index to stay under
+    /// `max_index_bytes`. It keeps its fingerprint, so it is only indexed
+    /// again once it changes.
+    pub fn record_evicted(&self, doc_id: &str, source: &str) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "UPDATE docs SET indexed_at = NULL, evicted_source = ?2 WHERE doc_id = ?1",
+            )?
+            .execute(params![doc_id, source])
+            .context("failed to update manifest")?;
+        Ok(())
+    }
+
+    /// Forgets `doc_id`; false when it wasn't in the manifest.
+    pub fn remove(&self, doc_id: &str) -> Result<bool> {
+        let removed = self
+            .connection
+            .prepare_cached("DELETE FROM docs WHERE doc_id = ?1")?
+            .execute([doc_id])
+            .context("failed to update manifest")?;
+        Ok(removed > 0)
+    }
+
+    /// Ids of the documents whose id starts with `prefix`.
+    pub fn doc_ids_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare_cached("SELECT doc_id FROM docs WHERE doc_id >= ?1 AND doc_id < ?2")?;
+        let rows = statement.query_map(params![prefix, prefix_end(prefix)], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()
+            .context("failed to read manifest")
+    }
+
+    /// Ids of the evicted documents of `source`.
+    pub fn evicted_doc_ids(&self, source: &str) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare_cached("SELECT doc_id FROM docs WHERE evicted_source = ?1")?;
+        let rows = statement.query_map([source], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()
+            .context("failed to read manifest")
+    }
+
+    /// Forgets the evicted documents of `source`.
+    pub fn remove_evicted(&self, source: &str) -> Result<u64> {
+        let removed = self
+            .connection
+            .execute("DELETE FROM docs WHERE evicted_source = ?1", [source])
+            .context("failed to update manifest")?;
+        Ok(removed as u64)
+    }
+
+    /// Notes that this run read `doc_id`.
+    pub fn mark_seen(&self, doc_id: &str) -> Result<()> {
+        self.connection
+            .prepare_cached("INSERT OR IGNORE INTO seen VALUES (?1)")?
+            .execute([doc_id])
+            .context("failed to update manifest")?;
+        Ok(())
+    }
+
+    pub fn is_seen(&self, doc_id: &str) -> Result<bool> {
+        self.connection
+            .prepare_cached("SELECT 1 FROM seen WHERE doc_id = ?1")?
+            .exists([doc_id])
+            .context("failed to read manifest")
+    }
+
+    /// Marks every document under `prefix` as read, for sources that kept
+    /// them without reading them again. Returns how many weren't yet.
+    pub fn mark_seen_with_prefix(&self, prefix: &str) -> Result<u64> {
+        let inserted = self
+            .connection
+            .prepare_cached(
+                "INSERT OR IGNORE INTO seen
+                 SELECT doc_id FROM docs WHERE doc_id >= ?1 AND doc_id < ?2",
+            )?
+            .execute(params![prefix, prefix_end(prefix)])
+            .context("failed to update manifest")?;
+        Ok(inserted as u64)
+    }
+
+    /// Number of documents this run hasn't read.
+    pub fn count_unseen(&self) -> Result<u64> {
+        self.connection
+            .query_row(
+                "SELECT count(*) FROM docs WHERE doc_id NOT IN (SELECT doc_id FROM seen)",
+                [],
+                |row| row.get(0),
+            )
+            .context("failed to read manifest")
+    }
+
+    /// Forgets the documents this run hasn't read, calling `on_removed`
+    /// with each id first. Returns how many there were.
+    pub fn remove_unseen(&self, mut on_removed: impl FnMut(&str)) -> Result<u64> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT doc_id FROM docs WHERE doc_id NOT IN (SELECT doc_id FROM seen)")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next().context("failed to read manifest")? {
+            on_removed(row.get_ref(0)?.as_str()?);
+        }
+        let removed = self
+            .connection
+            .execute(
+                "DELETE FROM docs WHERE doc_id NOT IN (SELECT doc_id FROM seen)",
+                [],
+            )
+            .context("failed to update manifest")?;
+        Ok(removed as u64)
+    }
+
+    /// Calls `visit` with the id of every document not evicted, those
+    /// written longest ago (or before timestamps were kept) first, until
+    /// it returns false.
+    pub fn oldest_first(&self, mut visit: impl FnMut(&str) -> Result<bool>) -> Result<()> {
+        let mut statement = self.connection.prepare(
+            "SELECT doc_id FROM docs WHERE evicted_source IS NULL
+             ORDER BY coalesce(indexed_at, 0), doc_id",
+        )?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next().context("failed to read manifest")? {
+            if !visit(row.get_ref(0)?.as_str()?)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets the documents (other than evicted ones) for which `indexed`
+    /// is false. Returns how many were removed.
+    pub fn retain_indexed(&self, mut indexed: impl FnMut(&str) -> Result<bool>) -> Result<u64> {
+        let mut stale = Vec::new();
+        {
+            let mut statement = self
+                .connection
+                .prepare("SELECT doc_id FROM docs WHERE evicted_source IS NULL")?;
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next().context("failed to read manifest")? {
+                let doc_id = row.get_ref(0)?.as_str()?;
+                if !indexed(doc_id)? {
+                    stale.push(doc_id.to_string());
+                }
+            }
+        }
+        for doc_id in &stale {
+            self.remove(doc_id)?;
+        }
+        Ok(stale.len() as u64)
+    }
+
+    /// Commits, then rewrites the file without the pages freed by removed
+    /// entries.
+    pub fn vacuum(&self) -> Result<()> {
+        self.connection
+            .execute_batch("COMMIT; VACUUM;")
+            .context("failed to vacuum manifest")?;
+        self.begin()
+    }
+
+    pub fn http_validators(&self) -> Result<HttpValidators> {
+        let value: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                [HTTP_VALIDATORS_KEY],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("failed to read manifest")?;
+        match value {
+            Some(value) => {
+                serde_json::from_str(&value).context("failed to parse HTTP validators in manifest")
+            }
+            None => Ok(HttpValidators::default()),
+        }
+    }
+
+    pub fn set_http_validators(&self, validators: &HttpValidators) -> Result<()> {
+        let value = serde_json::to_string(validators).context("failed to serialize validators")?;
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO meta VALUES (?1, ?2)",
+                params![HTTP_VALIDATORS_KEY, value],
+            )
+            .context("failed to update manifest")?;
+        Ok(())
+    }
+}
+
+pub fn manifest_path(index_dir: &Path) -> PathBuf {
+    index_dir.join(MANIFEST_FILE)
+}
+
+fn load_legacy(path: &Path) -> Result<LegacyManifest> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("failed to read manifest at {}", path.display()))?;
+    serde_json::from_str(&data)
+        .with_context(|| format!("failed to parse manifest at {}", path.display()))
+}
+
+/// Exclusive upper bound of the ids starting with `prefix`.
+fn prefix_end(prefix: &str) -> String {
+    format!("{prefix}{}", char::MAX)
+}
@@ -1,10 +1,12 @@
 use std::time::Duration;
 
 use anyhow::{Context, Result};
+use futures_util::stream::{self, BoxStream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
 use crate::config::OllamaConfig;
+use crate::retry::RetryPolicy;
 use crate::search::SearchHit;
 
 #[derive(Clone)]
@@ -12,8 +14,11 @@ pub struct OllamaClient {
     client: Client,
     base_url: String,
     model: String,
+    embedding_model: Option<String>,
     max_context_hits: usize,
     max_context_chars: usize,
+    answer_prompt_template: Option<String>,
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize)]
@@ -28,6 +33,31 @@ struct GenerateResponse {
     response: String,
 }
 
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct GenerateChunk {
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    done: bool,
+}
+
+struct ChunkReader {
+    bytes: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
 impl OllamaClient {
     pub fn from_config(config: OllamaConfig) -> Result<Self> {
         let client = Client::builder()
@@ -39,21 +69,20 @@ impl OllamaClient {
             client,
             base_url: config.base_url.trim_end_matches('/').to_string(),
             model: config.model,
+            embedding_model: config
+                .embedding_model
+                .filter(|model| !model.trim().is_empty()),
             max_context_hits: config.max_context_hits.max(1),
             max_context_chars: config.max_context_chars.max(500),
+            answer_prompt_template: config.answer_prompt_template,
+            retry: RetryPolicy::new(config.max_retries, config.retry_base_delay_ms),
         })
     }
 
     pub async fn synthesize_answer(&self, query: &str, hits: &[SearchHit]) -> Result<String> {
-        let context = self.build_context(hits);
-        if context.is_empty() {
+        let Some(prompt) = self.build_prompt(query, hits) else {
             return Ok(String::new());
-        }
-
-        let prompt = format!(
-            "You are answering questions using only the provided offline search snippets. \
-If the snippets are insufficient, say what is missing.\n\nQuestion:\n{query}\n\nSearch snippets:\n{context}\n\nInstructions:\n- Give a concise answer in plain English.\n- Include 2-5 inline citations in [source | location] format.\n- Do not invent details not present in snippets."
-        );
+        };
 
         let url = format!("{}/api/generate", self.base_url);
         let payload = GenerateRequest {
@@ -62,6 +91,42 @@ If the snippets are insufficient, say what is missing.\n\nQuestion:\n{query}\n\n
             stream: false,
         };
 
+        // Non-streaming generation has no side effects, so it is safe to
+        // repeat while Ollama is restarting or loading the model.
+        let response = self
+            .retry
+            .send("ollama generate", || self.client.post(&url).json(&payload))
+            .await
+            .context("failed to call Ollama generate endpoint")?
+            .error_for_status()
+            .context("Ollama generate returned non-success status")?;
+
+        let generated: GenerateResponse = response
+            .json()
+            .await
+            .context("failed to parse Ollama JSON response")?;
+
+        Ok(generated.response.trim().to_string())
+    }
+
+    /// Like `synthesize_answer`, but yields response fragments as Ollama
+    /// produces them.
+    pub async fn stream_answer(
+        &self,
+        query: &str,
+        hits: &[SearchHit],
+    ) -> Result<BoxStream<'static, Result<String>>> {
+        let Some(prompt) = self.build_prompt(query, hits) else {
+            return Ok(stream::empty().boxed());
+        };
+
+        let url = format!("{}/api/generate", self.base_url);
+        let payload = GenerateRequest {
+            model: &self.model,
+            prompt,
+            stream: true,
+        };
+
         let response = self
             .client
             .post(url)
@@ -72,12 +137,80 @@ If the snippets are insufficient, say what is missing.\n\nQuestion:\n{query}\n\n
             .error_for_status()
             .context("Ollama generate returned non-success status")?;
 
-        let generated: GenerateResponse = response
+        let reader = ChunkReader {
+            bytes: response
+                .bytes_stream()
+                .map(|chunk| chunk.map(|bytes| bytes.to_vec()))
+                .boxed(),
+            buffer: Vec::new(),
+            done: false,
+        };
+
+        Ok(stream::unfold(reader, next_fragment).boxed())
+    }
+
+    /// Lists the installed models once, without retries, to tell whether
+    /// Ollama is reachable.
+    pub async fn ping(&self, timeout: Duration) -> Result<()> {
+        let url = format!("{}/api/tags", self.base_url);
+        self.client
+            .get(url)
+            .timeout(timeout)
+            .send()
+            .await
+            .context("failed to call Ollama tags endpoint")?
+            .error_for_status()
+            .context("Ollama tags returned non-success status")?;
+        Ok(())
+    }
+
+    pub fn embedding_model(&self) -> Option<&str> {
+        self.embedding_model.as_deref()
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let Some(model) = self.embedding_model.as_deref() else {
+            anyhow::bail!("ollama.embedding_model is not configured");
+        };
+
+        let url = format!("{}/api/embeddings", self.base_url);
+        let payload = EmbeddingRequest {
+            model,
+            prompt: text,
+        };
+
+        let response = self
+            .client
+            .post(url)
+            .json(&payload)
+            .send()
+            .await
+            .context("failed to call Ollama embeddings endpoint")?
+            .error_for_status()
+            .context("Ollama embeddings returned non-success status")?;
+
+        let embedded: EmbeddingResponse = response
             .json()
             .await
-            .context("failed to parse Ollama JSON response")?;
+            .context("failed to parse Ollama embeddings response")?;
 
-        Ok(generated.response.trim().to_string())
+        Ok(embedded.embedding)
+    }
+
+    fn build_prompt(&self, query: &str, hits: &[SearchHit]) -> Option<String> {
+        let context = self.build_context(hits);
+        if context.is_empty() {
+            return None;
+        }
+
+        if let Some(template) = &self.answer_prompt_template {
+            return Some(fill_prompt_template(template, query, &context));
+        }
+
+        Some(format!(
+            "You are answering questions using only the provided offline search snippets. \
+If the snippets are insufficient, say what is missing.\n\nQuestion:\n{query}\n\nSearch snippets:\n{context}\n\nInstructions:\n- Give a concise answer in plain English.\n- Include 2-5 inline citations in [source | location] format.\n- Do not invent details not present in snippets."
+        ))
     }
 
     fn build_context(&self, hits: &[SearchHit]) -> String {
@@ -101,3 +234,82 @@ If the snippets are insufficient, say what is missing.\n\nQuestion:\n{query}\n\n
         out
     }
 }
+
+/// Substitutes `{query}` and `{context}` in one pass, so placeholder-like
+/// text inside the query or snippets is left alone.
+fn fill_prompt_template(template: &str, query: &str, context: &str) -> String {
+    let mut out = String::with_capacity(template.len() + query.len() + context.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("{query}") {
+            out.push_str(query);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{context}") {
+            out.push_str(context);
+            rest = after;
+        } else {
+            out.push('{');
+            rest = &rest[1..];
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Pulls the next non-empty `response` fragment out of Ollama's
+/// newline-delimited JSON stream.
+async fn next_fragment(mut reader: ChunkReader) -> Option<(Result<String>, ChunkReader)> {
+    loop {
+        if let Some(newline) = reader.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = reader.buffer.drain(..=newline).collect();
+            match parse_chunk(&line) {
+                Some(Ok(chunk)) => {
+                    reader.done |= chunk.done;
+                    if !chunk.response.is_empty() {
+                        return Some((Ok(chunk.response), reader));
+                    }
+                }
+                Some(Err(err)) => {
+                    reader.done = true;
+                    return Some((Err(err), reader));
+                }
+                None => {}
+            }
+            continue;
+        }
+
+        if reader.done {
+            return None;
+        }
+
+        match reader.bytes.next().await {
+            Some(Ok(bytes)) => reader.buffer.extend_from_slice(&bytes),
+            Some(Err(err)) => {
+                reader.done = true;
+                return Some((
+                    Err(anyhow::Error::new(err).context("failed reading Ollama stream")),
+                    reader,
+                ));
+            }
+            None => {
+                reader.done = true;
+                if !reader.buffer.is_empty() {
+                    reader.buffer.push(b'\n');
+                }
+            }
+        }
+    }
+}
+
+fn parse_chunk(line: &[u8]) -> Option<Result<GenerateChunk>> {
+    let line = std::str::from_utf8(line).ok()?.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    Some(serde_json::from_str(line).context("failed to parse Ollama stream chunk"))
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::ingest::IngestStats;
+
+/// Log a progress line after this many scanned items or this much time,
+/// whichever comes first.
+const LOG_EVERY_SCANNED: u64 = 10_000;
+const LOG_EVERY: Duration = Duration::from_secs(5);
+
+/// Live counters for an indexing run, reported as periodic log lines or, with
+/// `--progress` on a terminal, as a progress bar per source.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    state: RefCell<ProgressState>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    source: String,
+    /// Totals of the sources already finished.
+    done_scanned: u64,
+    done_skipped: u64,
+    /// Counters of the source being read.
+    scanned: u64,
+    skipped: u64,
+    indexed: u64,
+    /// Documents read but identical to the indexed copy; reported as skipped.
+    unchanged: u64,
+    logged_scanned: u64,
+    logged_at: Option<Instant>,
+}
+
+impl Progress {
+    /// A progress bar is only drawn when requested and stderr is a terminal;
+    /// otherwise progress goes to the log.
+    pub fn new(show_bar: bool) -> Self {
+        let bar = (show_bar && std::io::stderr().is_terminal())
+            .then(|| ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr()));
+        Self {
+            bar,
+            state: RefCell::new(ProgressState::default()),
+        }
+    }
+
+    /// Whether per-source totals are worth computing (they cost a pre-pass).
+    pub fn wants_totals(&self) -> bool {
+        self.bar.is_some()
+    }
+
+    pub fn start_source(&self, source: &str, total: Option<u64>) {
+        let mut state = self.state.borrow_mut();
+        state.source = source.to_string();
+        state.scanned = 0;
+        state.skipped = 0;
+
+        if let Some(bar) = &self.bar {
+            let template = match total {
+                Some(_) => "{prefix} [{bar:30}] {pos}/{len} ({percent}%) {msg}",
+                None => "{spinner} {prefix} {pos} scanned {msg}",
+            };
+            bar.set_style(
+                ProgressStyle::with_template(template)
+                    .unwrap_or_else(|_| ProgressStyle::default_bar())
+                    .progress_chars("=> "),
+            );
+            bar.set_length(total.unwrap_or(0));
+            bar.set_position(0);
+            bar.set_prefix(source.to_string());
+            if total.is_none() {
+                bar.enable_steady_tick(Duration::from_millis(120));
+            } else {
+                bar.disable_steady_tick();
+            }
+        }
+    }
+
+    /// Records the current counters of the source being read.
+    pub fn observe(&self, stats: &IngestStats) {
+        let mut state = self.state.borrow_mut();
+        state.scanned = stats.scanned;
+        state.skipped = stats.skipped;
+        self.report(&mut state);
+    }
+
+    /// Counts one document written to the index.
+    pub fn indexed(&self) {
+        let mut state = self.state.borrow_mut();
+        state.indexed += 1;
+    }
+
+    /// Counts one document that was already indexed unchanged.
+    pub fn unchanged(&self) {
+        let mut state = self.state.borrow_mut();
+        state.unchanged += 1;
+    }
+
+    pub fn finish_source(&self, stats: &IngestStats) {
+        let mut state = self.state.borrow_mut();
+        state.done_scanned += stats.scanned;
+        state.done_skipped += stats.skipped;
+        state.scanned = 0;
+        state.skipped = 0;
+        if let Some(bar) = &self.bar {
+            bar.set_position(stats.scanned);
+            bar.set_message(format!(
+                "indexed {}, skipped {}",
+                state.indexed,
+                state.done_skipped + state.unchanged
+            ));
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+
+    fn report(&self, state: &mut ProgressState) {
+        let scanned = state.done_scanned + state.scanned;
+        let skipped = state.done_skipped + state.skipped + state.unchanged;
+
+        if let Some(bar) = &self.bar {
+            bar.set_position(state.scanned);
+            bar.set_message(format!("indexed {}, skipped {skipped}", state.indexed));
+            return;
+        }
+
+        let logged_at = *state.logged_at.get_or_insert_with(Instant::now);
+        if scanned - state.logged_scanned < LOG_EVERY_SCANNED && logged_at.elapsed() < LOG_EVERY {
+            return;
+        }
+        state.logged_scanned = scanned;
+        state.logged_at = Some(Instant::now());
+        tracing::info!(
+            source = %state.source,
+            scanned,
+            indexed = state.indexed,
+            skipped,
+            "indexing progress"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// Entries waiting for the writer thread; further ones are dropped.
+const QUEUE_LEN: usize = 1024;
+
+/// One search, as a line of the `query_log` file.
+#[derive(Debug, Serialize)]
+pub struct QueryLogEntry {
+    /// Unix seconds.
+    pub timestamp: i64,
+    pub index: String,
+    pub query: String,
+    /// Source filter, empty when every source was searched.
+    pub sources: Vec<String>,
+    pub total_hits: usize,
+    /// Whether the hits were reranked (see the `rerank` search parameter).
+    pub rerank: bool,
+    pub latency_ms: u64,
+}
+
+/// Appends searches to a JSON Lines file from a background thread, so a
+/// slow disk never holds up a request.
+#[derive(Clone)]
+pub struct QueryLog {
+    sender: SyncSender<QueryLogEntry>,
+}
+
+impl QueryLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open query log {}", path.display()))?;
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_LEN);
+        thread::Builder::new()
+            .name("query-log".to_string())
+            .spawn(move || write_entries(file, receiver))
+            .context("failed to start query log writer")?;
+        Ok(Self { sender })
+    }
+
+    /// Queues `entry` for writing; dropped when the writer is behind.
+    pub fn record(&self, entry: QueryLogEntry) {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(entry) {
+            tracing::debug!("query log writer is behind; dropping entry");
+        }
+    }
+}
+
+/// Writes entries as they arrive, flushing whenever the queue runs dry.
+fn write_entries(file: File, receiver: Receiver<QueryLogEntry>) {
+    let mut out = BufWriter::new(file);
+    while let Ok(first) = receiver.recv() {
+        let result = std::iter::once(first)
+            .chain(receiver.try_iter())
+            .try_for_each(|entry| write_entry(&mut out, &entry))
+            .and_then(|()| Ok(out.flush()?));
+        if let Err(err) = result {
+            tracing::warn!(error = %format!("{err:#}"), "failed to write query log");
+        }
+    }
+}
+
+fn write_entry(out: &mut impl Write, entry: &QueryLogEntry) -> Result<()> {
+    serde_json::to_writer(&mut *out, entry)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
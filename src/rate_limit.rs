@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+
+use crate::auth::is_answer_request;
+
+// Idle clients are forgotten once this many are tracked.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Per-client-IP token buckets refilled at `requests_per_minute`, holding up
+/// to `burst` tokens. Answer requests take `answer_cost` tokens.
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    tokens_per_sec: f64,
+    burst: f64,
+    answer_cost: f64,
+    trusted_proxy: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(
+        requests_per_minute: u32,
+        burst: u32,
+        answer_cost: u32,
+        trusted_proxy: bool,
+    ) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+            tokens_per_sec: f64::from(requests_per_minute.max(1)) / 60.0,
+            burst,
+            // A cost above the burst could never be paid.
+            answer_cost: f64::from(answer_cost.max(1)).min(burst),
+            trusted_proxy,
+        }
+    }
+
+    /// Takes `cost` tokens from the client's bucket, or returns how long
+    /// until enough have been refilled.
+    fn take(&self, client: IpAddr, cost: f64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            buckets.retain(|_, bucket| self.refill(*bucket, now).tokens < self.burst);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            refilled: now,
+        });
+        *bucket = self.refill(*bucket, now);
+
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (cost - bucket.tokens) / self.tokens_per_sec,
+            ))
+        }
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        Bucket {
+            tokens: (bucket.tokens + elapsed * self.tokens_per_sec).min(self.burst),
+            refilled: now,
+        }
+    }
+
+    /// The peer address, or with `trusted_proxy` the last `X-Forwarded-For`
+    /// entry (the one the proxy appended, which clients can't forge).
+    fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        if self.trusted_proxy {
+            let forwarded = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(|entry| entry.trim().parse::<IpAddr>().ok())
+                .next_back();
+            if let Some(client) = forwarded {
+                return client;
+            }
+        }
+        peer.ip()
+    }
+}
+
+/// Middleware answering 429 with `Retry-After` once a client's bucket is
+/// empty. Only `/api/` routes are limited.
+pub async fn limit_requests(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !request.uri().path().starts_with("/api/") {
+        return next.run(request).await;
+    }
+
+    let client = limiter.client_ip(peer, request.headers());
+    let cost = if is_answer_request(&request) {
+        limiter.answer_cost
+    } else {
+        1.0
+    };
+
+    if let Err(wait) = limiter.take(client, cost) {
+        metrics::counter!("bunker_search_rate_limited_total").increment(1);
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(serde_json::json!({ "error": "rate limit exceeded" })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use reqwest::{RequestBuilder, Response};
+
+/// Exponential backoff for outgoing HTTP calls: retry `n` waits
+/// `base_delay * 2^(n-1)`.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32, base_delay_ms: u64) -> Self {
+        Self {
+            max_retries,
+            base_delay: Duration::from_millis(base_delay_ms),
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(1u32 << retry.saturating_sub(1).min(16))
+    }
+
+    /// Sends the request built by `request`, building and sending it again
+    /// after a connect error or a 5xx response. Other errors and 4xx
+    /// responses are returned as-is. Only use it for requests that are safe
+    /// to repeat. The last outcome is returned once retries run out.
+    pub async fn send(
+        &self,
+        what: &str,
+        request: impl Fn() -> RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        let mut retry = 0;
+        loop {
+            let outcome = request().send().await;
+            let reason = match &outcome {
+                Ok(response) if response.status().is_server_error() => {
+                    response.status().to_string()
+                }
+                Err(err) if err.is_connect() => err.to_string(),
+                _ => return outcome,
+            };
+            if retry >= self.max_retries {
+                return outcome;
+            }
+
+            retry += 1;
+            let delay = self.delay(retry);
+            tracing::debug!(
+                request = what,
+                retry,
+                max_retries = self.max_retries,
+                delay_ms = delay.as_millis() as u64,
+                reason = %reason,
+                "retrying HTTP request"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
@@ -1,13 +1,42 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{anyhow, Context, Result};
-use serde::Serialize;
-use tantivy::collector::{Count, TopDocs};
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
 use tantivy::directory::MmapDirectory;
-use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
-use tantivy::schema::{Field, IndexRecordOption, Schema, Value, STORED, STRING, TEXT};
-use tantivy::{Index, IndexReader, ReloadPolicy, TantivyDocument, Term};
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::postings::SegmentPostings;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, EmptyQuery, FuzzyTermQuery, Occur, PhraseQuery, Query,
+    QueryParser, RangeQuery, RegexQuery, TermQuery,
+};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, FAST, INDEXED, STORED,
+    STRING, TEXT,
+};
+use tantivy::snippet::{Snippet, SnippetGenerator};
+use tantivy::store::{Compressor, ZstdCompressor};
+use tantivy::tokenizer::{
+    Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter, TextAnalyzer,
+};
+use tantivy::{
+    DocAddress, DocId, DocSet, Index, IndexReader, IndexSettings, IndexWriter, Postings,
+    ReloadPolicy, Score, Searcher, SegmentReader, TantivyDocument, Term, TERMINATED,
+};
+use utoipa::ToSchema;
+
+use crate::cjk_tokenizer::CjkBigramTokenizer;
+use crate::code_tokenizer::CodeTokenizer;
+use crate::config::{self, AppConfig, StoredCompression, TextLanguage};
+use crate::ingest::preview_from_text;
 
 pub const DOC_ID_FIELD: &str = "doc_id";
 pub const SOURCE_FIELD: &str = "source";
@@ -16,8 +45,51 @@ pub const BODY_FIELD: &str = "body";
 pub const PREVIEW_FIELD: &str = "preview";
 pub const LOCATION_FIELD: &str = "location";
 pub const URL_FIELD: &str = "url";
+pub const DATE_FIELD: &str = "date";
+const CODE_TITLE_FIELD: &str = "title_code";
+const CODE_BODY_FIELD: &str = "body_code";
+const CODE_TOKENIZER: &str = "code";
+/// Tokenized copy of `location`, searched by `location:` queries.
+const LOCATION_TERMS_FIELD: &str = "location_terms";
+/// Lowercased title in a fast column, read by `sort=title`.
+const TITLE_SORT_FIELD: &str = "title_sort";
+/// ISO 639-3 code of the detected body language, or `und`.
+const LANG_FIELD: &str = "lang";
+/// Topic tags, one untokenized value per tag.
+const TAGS_FIELD: &str = "tags";
 
-#[derive(Debug, Clone, Copy)]
+/// Field prefixes accepted in queries (`title:kernel`) and the index field
+/// each one searches.
+const QUERYABLE_FIELDS: &[(&str, &str)] = &[
+    (TITLE_FIELD, TITLE_FIELD),
+    (BODY_FIELD, BODY_FIELD),
+    (SOURCE_FIELD, SOURCE_FIELD),
+    (LOCATION_FIELD, LOCATION_TERMS_FIELD),
+];
+
+/// A quoted phrase or parenthesis-free run of query text, the unit
+/// `expand_synonyms` looks at.
+static QUERY_TOKEN_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#""(?:[^"\\]|\\.)*"|[^\s"()]+"#).expect("valid query token regex"));
+
+/// A quoted phrase (skipped) or a `name:` field prefix.
+static FIELD_PREFIX_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#""(?:[^"\\]|\\.)*"|(?P<field>[^\s"():^+\-\\][^\s"():^\\]*):"#)
+        .expect("valid field prefix regex")
+});
+
+const MAX_SUGGEST_TERMS_SCANNED: usize = 10_000;
+const MAX_SPELLING_TERMS_SCANNED: usize = 50_000;
+// Distinctive terms of a document used to find related ones.
+const MORE_LIKE_THIS_TERMS: usize = 25;
+const MORE_LIKE_THIS_MIN_TERM_CHARS: usize = 3;
+// Words shorter than this are left alone; longer ones may be off by two edits.
+const MIN_CORRECTED_WORD_CHARS: usize = 3;
+const TWO_EDIT_WORD_CHARS: usize = 6;
+/// Joins the highlighted fragments of a multi-fragment snippet.
+const SNIPPET_FRAGMENT_SEPARATOR: &str = " … ";
+
+#[derive(Debug, Clone)]
 pub struct IndexFields {
     pub doc_id: Field,
     pub source: Field,
@@ -25,7 +97,41 @@ pub struct IndexFields {
     pub body: Field,
     pub preview: Field,
     pub location: Field,
+    pub location_terms: Field,
+    pub title_sort: Field,
     pub url: Field,
+    pub date: Field,
+    pub lang: Field,
+    pub tags: Field,
+    pub stemmed: Vec<StemmedFields>,
+    /// Present when a source has `code = true`.
+    pub code: Option<CodeFields>,
+}
+
+/// Title/body copies analyzed with one language's stemmer, filled only for
+/// documents of sources using that language.
+#[derive(Debug, Clone, Copy)]
+pub struct StemmedFields {
+    pub language: TextLanguage,
+    pub title: Field,
+    pub body: Field,
+}
+
+/// Title/body copies analyzed with the code tokenizer, filled only for
+/// documents of `code = true` sources.
+#[derive(Debug, Clone, Copy)]
+pub struct CodeFields {
+    pub title: Field,
+    pub body: Field,
+}
+
+impl IndexFields {
+    pub fn stemmed_for(&self, language: TextLanguage) -> Option<StemmedFields> {
+        self.stemmed
+            .iter()
+            .find(|fields| fields.language == language)
+            .copied()
+    }
 }
 
 #[derive(Clone)]
@@ -34,15 +140,127 @@ pub struct IndexHandle {
     pub fields: IndexFields,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct SearchHit {
     pub score: f32,
     pub doc_id: String,
     pub source: String,
     pub title: String,
+    /// The HTML-escaped title with `<mark>` around words of the query.
+    pub highlighted_title: String,
     pub preview: String,
+    pub highlighted_preview: String,
+    pub location: String,
+    pub url: Option<String>,
+    pub date: Option<i64>,
+    /// Detected language code of a local hit (`und` when uncertain).
+    pub lang: Option<String>,
+    /// Topic tags of a local hit, e.g. from Markdown front matter.
+    pub tags: Vec<String>,
+    /// With `explain=true`: the score's parts, from the base score through
+    /// each reranking boost, summing to `score`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Vec<Vec<serde_json::Value>>>)]
+    pub explanation: Option<Vec<(String, f32)>>,
+}
+
+/// Restrictions applied on top of the text query.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchFilters<'a> {
+    /// Source names a hit may come from; empty allows every source.
+    pub sources: &'a [String],
+    /// Inclusive lower bound on the document date (unix seconds).
+    pub after: Option<i64>,
+    /// Exclusive upper bound on the document date (unix seconds).
+    pub before: Option<i64>,
+    /// Detected language code a hit must have.
+    pub lang: Option<&'a str>,
+    /// Tag a hit must carry.
+    pub tag: Option<&'a str>,
+}
+
+impl SearchFilters<'_> {
+    pub fn has_date_range(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+
+    fn accepts(&self, hit: &SearchHit) -> bool {
+        if !self.sources.is_empty() && !self.sources.contains(&hit.source) {
+            return false;
+        }
+        if self
+            .lang
+            .is_some_and(|lang| hit.lang.as_deref() != Some(lang))
+        {
+            return false;
+        }
+        if self
+            .tag
+            .is_some_and(|tag| !hit.tags.iter().any(|hit_tag| hit_tag == tag))
+        {
+            return false;
+        }
+        if !self.has_date_range() {
+            return true;
+        }
+        hit.date.is_some_and(|date| {
+            self.after.is_none_or(|after| date >= after)
+                && self.before.is_none_or(|before| date < before)
+        })
+    }
+}
+
+/// How the words of a query are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryMode {
+    /// Any term may match (query parser default, OR).
+    #[default]
+    Any,
+    /// Every term must match (AND).
+    All,
+    /// Terms must appear as an exact phrase in the title or body.
+    Phrase,
+    /// The query is a regex matched against whole indexed title/body terms.
+    /// Selected with `regex=true` rather than `mode`.
+    #[serde(skip)]
+    Regex,
+}
+
+/// Order of local hits. Non-relevance orders break ties by relevance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Relevance,
+    /// Newest first; undated documents last.
+    DateDesc,
+    /// Oldest first; undated documents last.
+    DateAsc,
+    /// Alphabetical by title, ignoring case.
+    Title,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredDocument {
+    pub doc_id: String,
+    pub title: String,
+    pub body: String,
+}
+
+/// Every stored field of one local document, for `/api/document`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct IndexedDocument {
+    pub doc_id: String,
+    pub source: String,
+    pub title: String,
+    /// The full indexed text, not just a preview.
+    pub body: String,
     pub location: String,
     pub url: Option<String>,
+    pub date: Option<i64>,
+    pub lang: Option<String>,
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -51,36 +269,139 @@ pub struct SearchResult {
     pub hits: Vec<SearchHit>,
 }
 
+/// What an index holds, for `/api/stats`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct IndexStatistics {
+    pub num_docs: u64,
+    pub sources: BTreeMap<String, usize>,
+    /// Bytes of all files directly in the index directory, sidecars included.
+    pub size_bytes: u64,
+    /// `None` for indexes written before commits were versioned.
+    pub schema_version: Option<u32>,
+    /// Unix seconds of the last commit, when the commit recorded it.
+    pub last_commit: Option<i64>,
+}
+
 #[derive(Clone)]
 pub struct SearchEngine {
-    index: Index,
+    index_dir: PathBuf,
     reader: IndexReader,
+    parser: QueryParser,
+    conjunction_parser: QueryParser,
     fields: IndexFields,
+    snippet_fragments: usize,
+    snippet_fragment_chars: usize,
+    /// Preview length cut from the stored body; 0 keeps the indexed preview.
+    preview_chars: usize,
+    field_boosts: Vec<(Field, Score)>,
+    source_languages: HashMap<String, TextLanguage>,
+    code_sources: HashSet<String>,
+    bm25: Option<Bm25Params>,
+    regex_max_pattern_chars: usize,
+    regex_prefix_required_above_docs: u64,
+    fuzzy_max_distance: u8,
+    fuzzy_min_term_chars: usize,
+    /// Lowercased word to the other entries of its synonym groups.
+    synonyms: HashMap<String, Vec<String>>,
+    synonym_boost: Score,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bm25Params {
+    k1: Score,
+    b: Score,
 }
 
 impl SearchEngine {
-    pub fn open(index_dir: &Path) -> Result<Self> {
-        let handle = open_or_create_index(index_dir)?;
+    pub fn open(config: &AppConfig) -> Result<Self> {
+        let handle = open_or_create_index(config)?;
         let reader = handle
             .index
             .reader_builder()
             .reload_policy(ReloadPolicy::OnCommitWithDelay)
             .try_into()
-            .context("failed to create tantivy reader")?;
+            .map_err(|err| damaged_index(&config.index_dir, format!("{err}")))?;
+        verify_index(&handle.index, &reader, config)?;
+
+        // Stemmed copies share the boost of the field they shadow.
+        let mut field_boosts = vec![
+            (handle.fields.title, config.field_boosts.title),
+            (handle.fields.body, config.field_boosts.body),
+        ];
+        for stemmed in &handle.fields.stemmed {
+            field_boosts.push((stemmed.title, config.field_boosts.title));
+            field_boosts.push((stemmed.body, config.field_boosts.body));
+        }
+        if let Some(code) = handle.fields.code {
+            field_boosts.push((code.title, config.field_boosts.title));
+            field_boosts.push((code.body, config.field_boosts.body));
+        }
+
+        let mut parser = QueryParser::for_index(
+            &handle.index,
+            field_boosts.iter().map(|(field, _)| *field).collect(),
+        );
+        for (field, boost) in &field_boosts {
+            parser.set_field_boost(*field, *boost);
+        }
+        let mut conjunction_parser = parser.clone();
+        conjunction_parser.set_conjunction_by_default();
+
+        // Tantivy hardcodes k1/b, so non-default values are applied by
+        // rescoring matches at query time (see `Bm25Rescorer`).
+        let bm25 = (config.bm25_k1 != config::default_bm25_k1()
+            || config.bm25_b != config::default_bm25_b())
+        .then_some(Bm25Params {
+            k1: config.bm25_k1,
+            b: config.bm25_b,
+        });
 
         Ok(Self {
-            index: handle.index,
+            index_dir: config.index_dir.clone(),
             reader,
+            parser,
+            conjunction_parser,
             fields: handle.fields,
+            snippet_fragments: config.snippet_fragments,
+            snippet_fragment_chars: config.snippet_fragment_chars,
+            preview_chars: config.preview_chars,
+            field_boosts,
+            source_languages: config
+                .sources
+                .iter()
+                .map(|source| {
+                    (
+                        source.name().to_string(),
+                        config.source_language(source.name()),
+                    )
+                })
+                .collect(),
+            code_sources: config
+                .sources
+                .iter()
+                .filter(|source| source.is_code())
+                .map(|source| source.name().to_string())
+                .collect(),
+            bm25,
+            regex_max_pattern_chars: config.regex_max_pattern_chars,
+            regex_prefix_required_above_docs: config.regex_prefix_required_above_docs,
+            fuzzy_max_distance: config.fuzzy_max_distance,
+            fuzzy_min_term_chars: config.fuzzy_min_term_chars,
+            synonyms: synonym_map(config.synonyms.groups()),
+            synonym_boost: config.synonym_boost,
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn search(
         &self,
         query_text: &str,
         limit: usize,
         offset: usize,
-        source_filter: Option<&str>,
+        filters: &SearchFilters<'_>,
+        mode: QueryMode,
+        fuzzy: bool,
+        sort: SortOrder,
     ) -> Result<SearchResult> {
         let query_text = query_text.trim();
         if query_text.is_empty() {
@@ -96,90 +417,1812 @@ impl SearchEngine {
 
         let searcher = self.reader.searcher();
 
-        let parser = QueryParser::for_index(&self.index, vec![self.fields.title, self.fields.body]);
-        let parsed_query = parser
-            .parse_query(query_text)
-            .with_context(|| format!("invalid query: {query_text}"))?;
-
-        let combined_query: Box<dyn Query> = match source_filter
-            .map(str::trim)
-            .filter(|value| !value.is_empty())
-        {
-            Some(source) => {
-                let source_term = Term::from_field_text(self.fields.source, source);
-                let source_query = TermQuery::new(source_term, IndexRecordOption::Basic);
-                Box::new(BooleanQuery::new(vec![
-                    (Occur::Must, parsed_query),
-                    (Occur::Must, Box::new(source_query)),
-                ]))
-            }
-            None => parsed_query,
-        };
+        let combined_query = self.build_query(&searcher, query_text, filters, mode, fuzzy)?;
 
         let total_hits = searcher.search(combined_query.as_ref(), &Count)?;
-        let top_docs = searcher.search(
+        let top_collector = TopDocs::with_limit(limit).and_offset(offset);
+        let rescorer = self
+            .bm25
+            .map(|params| {
+                Bm25Rescorer::new(
+                    &searcher,
+                    combined_query.as_ref(),
+                    &self.field_boosts,
+                    params,
+                )
+            })
+            .transpose()?;
+        let top_docs = ranked_top_docs(
+            &searcher,
             combined_query.as_ref(),
-            &TopDocs::with_limit(limit).and_offset(offset),
+            top_collector,
+            sort,
+            rescorer,
         )?;
 
+        // Stemmed hits are highlighted with their language's analyzer first,
+        // so that e.g. "running" is marked for a query on "run", and code
+        // hits with the code analyzer; the plain body analyzer covers exact
+        // matches such as phrase queries.
+        let mut snippet_generators = HashMap::new();
         let mut hits = Vec::with_capacity(top_docs.len());
         for (score, doc_addr) in top_docs {
             let doc = searcher
                 .doc::<TantivyDocument>(doc_addr)
                 .context("failed to read indexed document")?;
 
-            let doc_id = get_field_str(&doc, self.fields.doc_id);
-            let source = get_field_str(&doc, self.fields.source);
-            let title = get_field_str(&doc, self.fields.title);
-            let preview = get_field_str(&doc, self.fields.preview);
-            let location = get_field_str(&doc, self.fields.location);
-            let url = get_field_str(&doc, self.fields.url);
-
-            hits.push(SearchHit {
-                score,
-                doc_id,
-                source,
-                title,
-                preview,
-                location,
-                url: if url.is_empty() { None } else { Some(url) },
+            let mut hit = self.hit_from_doc(&doc, score);
+            let body_text = get_field_str(&doc, self.fields.body);
+            let stemmed_body = self
+                .source_languages
+                .get(&hit.source)
+                .and_then(|language| self.fields.stemmed_for(*language))
+                .map(|stemmed| stemmed.body);
+            let code_body = self
+                .fields
+                .code
+                .filter(|_| self.code_sources.contains(&hit.source))
+                .map(|code| code.body);
+
+            for body_field in stemmed_body
+                .into_iter()
+                .chain(code_body)
+                .chain([self.fields.body])
+            {
+                let snippet_generator = match snippet_generators.entry(body_field) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let mut generator = SnippetGenerator::create(
+                            &searcher,
+                            combined_query.as_ref(),
+                            body_field,
+                        )
+                        .context("failed to create snippet generator")?;
+                        generator.set_max_num_chars(self.snippet_fragment_chars);
+                        entry.insert(generator)
+                    }
+                };
+                let fragments =
+                    best_fragments(snippet_generator, &body_text, self.snippet_fragments);
+                if !fragments.is_empty() {
+                    hit.highlighted_preview = fragments
+                        .into_iter()
+                        .map(|mut snippet| {
+                            snippet.set_snippet_prefix_postfix("<mark>", "</mark>");
+                            snippet.to_html()
+                        })
+                        .collect::<Vec<_>>()
+                        .join(SNIPPET_FRAGMENT_SEPARATOR);
+                    break;
+                }
+            }
+
+            hits.push(hit);
+        }
+
+        Ok(SearchResult { total_hits, hits })
+    }
+
+    /// Counts matches per local source for the same query and filters as
+    /// `search`, over the full result set rather than one page.
+    pub fn source_counts(
+        &self,
+        query_text: &str,
+        filters: &SearchFilters<'_>,
+        mode: QueryMode,
+        fuzzy: bool,
+    ) -> Result<BTreeMap<String, usize>> {
+        let query_text = query_text.trim();
+        if query_text.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+        let query = self.build_query(&searcher, query_text, filters, mode, fuzzy)?;
+
+        let mut counts = BTreeMap::new();
+        for source in self.indexed_sources(&searcher)? {
+            let source_query = TermQuery::new(
+                Term::from_field_text(self.fields.source, &source),
+                IndexRecordOption::Basic,
+            );
+            let count = searcher.search(
+                &BooleanQuery::new(vec![
+                    (Occur::Must, query.box_clone()),
+                    (Occur::Must, Box::new(source_query)),
+                ]),
+                &Count,
+            )?;
+            if count > 0 {
+                counts.insert(source, count);
+            }
+        }
+
+        Ok(counts)
+    }
+
+    /// Counts matches per tag for the same query and filters as `search`,
+    /// over the full result set rather than one page.
+    pub fn tag_counts(
+        &self,
+        query_text: &str,
+        filters: &SearchFilters<'_>,
+        mode: QueryMode,
+        fuzzy: bool,
+    ) -> Result<BTreeMap<String, usize>> {
+        let query_text = query_text.trim();
+        if query_text.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+        let query = self.build_query(&searcher, query_text, filters, mode, fuzzy)?;
+        let matches = searcher.search(&query, &DocSetCollector)?;
+        self.count_tags(&searcher, Some(&matches))
+    }
+
+    /// Every tag in the index with the number of documents carrying it.
+    pub fn all_tag_counts(&self) -> Result<BTreeMap<String, usize>> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        self.count_tags(&self.reader.searcher(), None)
+    }
+
+    /// Walks the postings of every tag, counting the live documents among
+    /// `matches` (or all live documents).
+    fn count_tags(
+        &self,
+        searcher: &Searcher,
+        matches: Option<&HashSet<DocAddress>>,
+    ) -> Result<BTreeMap<String, usize>> {
+        let mut counts = BTreeMap::new();
+        for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+            let inverted_index = segment_reader
+                .inverted_index(self.fields.tags)
+                .context("failed to open tag term dictionary")?;
+            let mut stream = inverted_index
+                .terms()
+                .stream()
+                .context("failed to scan tag terms")?;
+            while stream.advance() {
+                let Ok(tag) = std::str::from_utf8(stream.key()) else {
+                    continue;
+                };
+                let mut postings = inverted_index
+                    .read_postings_from_terminfo(stream.value(), IndexRecordOption::Basic)
+                    .context("failed to read tag postings")?;
+                let mut count = 0;
+                let mut doc = postings.doc();
+                while doc != TERMINATED {
+                    let counted = match matches {
+                        Some(matches) => {
+                            matches.contains(&DocAddress::new(segment_ord as u32, doc))
+                        }
+                        None => !segment_reader.is_deleted(doc),
+                    };
+                    count += usize::from(counted);
+                    doc = postings.advance();
+                }
+                if count > 0 {
+                    *counts.entry(tag.to_string()).or_default() += count;
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    pub fn index_dir(&self) -> &Path {
+        &self.index_dir
+    }
+
+    /// Document counts, disk usage and commit metadata of the index.
+    pub fn statistics(&self) -> Result<IndexStatistics> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+
+        let mut sources = BTreeMap::new();
+        for source in self.indexed_sources(&searcher)? {
+            let source_query = TermQuery::new(
+                Term::from_field_text(self.fields.source, &source),
+                IndexRecordOption::Basic,
+            );
+            let count = searcher.search(&source_query, &Count)?;
+            if count > 0 {
+                sources.insert(source, count);
+            }
+        }
+
+        let payload = commit_payload(searcher.index())?;
+
+        Ok(IndexStatistics {
+            num_docs: searcher.num_docs(),
+            sources,
+            size_bytes: index_size_bytes(&self.index_dir)?,
+            schema_version: payload.as_ref().map(|payload| payload.schema_version),
+            last_commit: payload.and_then(|payload| payload.committed_at),
+        })
+    }
+
+    /// Every value of the `source` field in the term dictionary. Sources
+    /// whose documents were all deleted may linger until segments merge.
+    fn indexed_sources(&self, searcher: &Searcher) -> Result<BTreeSet<String>> {
+        let mut sources = BTreeSet::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader
+                .inverted_index(self.fields.source)
+                .context("failed to open source term dictionary")?;
+            let mut stream = inverted_index
+                .terms()
+                .stream()
+                .context("failed to scan source terms")?;
+            while stream.advance() {
+                if let Ok(source) = std::str::from_utf8(stream.key()) {
+                    sources.insert(source.to_string());
+                }
+            }
+        }
+        Ok(sources)
+    }
+
+    fn build_query(
+        &self,
+        searcher: &Searcher,
+        query_text: &str,
+        filters: &SearchFilters<'_>,
+        mode: QueryMode,
+        fuzzy: bool,
+    ) -> Result<Box<dyn Query>> {
+        // Leading-`-` words are pulled out of any/all queries and excluded
+        // explicitly, so they never depend on the parser's conjunction mode.
+        let (included, excluded) = match mode {
+            QueryMode::Any | QueryMode::All => split_exclusions(query_text),
+            QueryMode::Phrase | QueryMode::Regex => (query_text.to_string(), Vec::new()),
+        };
+        if included.trim().is_empty() {
+            return Ok(Box::new(EmptyQuery));
+        }
+
+        let mut parsed_query = match mode {
+            QueryMode::Any => self
+                .parser
+                .parse_query(&self.expand_synonyms(&scope_query_fields(&included)?)),
+            QueryMode::All => self
+                .conjunction_parser
+                .parse_query(&self.expand_synonyms(&scope_query_fields(&included)?)),
+            QueryMode::Phrase => Ok(self.phrase_query(searcher, query_text)?),
+            QueryMode::Regex => Ok(self.regex_query(query_text)?),
+        }
+        .with_context(|| format!("invalid query: {query_text}"))?;
+        if fuzzy {
+            parsed_query = self.with_fuzzy_terms(parsed_query);
+        }
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, parsed_query)];
+        for exclusion in &excluded {
+            let excluded_query = self
+                .parser
+                .parse_query(&scope_query_fields(exclusion)?)
+                .with_context(|| format!("invalid query: {query_text}"))?;
+            clauses.push((Occur::MustNot, excluded_query));
+        }
+        if !filters.sources.is_empty() {
+            let mut sources: Vec<(Occur, Box<dyn Query>)> = filters
+                .sources
+                .iter()
+                .map(|source| {
+                    let source_term = Term::from_field_text(self.fields.source, source);
+                    let query: Box<dyn Query> =
+                        Box::new(TermQuery::new(source_term, IndexRecordOption::Basic));
+                    (Occur::Should, query)
+                })
+                .collect();
+            let source_query = if sources.len() == 1 {
+                sources.remove(0).1
+            } else {
+                Box::new(BooleanQuery::new(sources))
+            };
+            clauses.push((Occur::Must, source_query));
+        }
+        if let Some(lang) = filters.lang {
+            let lang_term = Term::from_field_text(self.fields.lang, lang);
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(lang_term, IndexRecordOption::Basic)),
+            ));
+        }
+        if let Some(tag) = filters.tag {
+            let tag_term = Term::from_field_text(self.fields.tags, tag);
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(tag_term, IndexRecordOption::Basic)),
+            ));
+        }
+        if filters.has_date_range() {
+            let lower = filters.after.map_or(Bound::Unbounded, Bound::Included);
+            let upper = filters.before.map_or(Bound::Unbounded, Bound::Excluded);
+            clauses.push((
+                Occur::Must,
+                Box::new(RangeQuery::new_i64_bounds(
+                    DATE_FIELD.to_string(),
+                    lower,
+                    upper,
+                )),
+            ));
+        }
+        Ok(if clauses.len() == 1 {
+            clauses.remove(0).1
+        } else {
+            Box::new(BooleanQuery::new(clauses))
+        })
+    }
+
+    /// Replaces each plain query word that has synonyms with a group such as
+    /// `(car OR "automobile"^0.5)`, so either word satisfies the query while
+    /// the one typed ranks higher. Quoted phrases, field-scoped, excluded
+    /// and operator words are left alone. Multi-word synonyms are searched
+    /// as phrases but only ever matched by a single query word.
+    fn expand_synonyms(&self, query: &str) -> String {
+        if self.synonyms.is_empty() {
+            return query.to_string();
+        }
+
+        let mut expanded = String::with_capacity(query.len());
+        let mut copied = 0;
+        for token in QUERY_TOKEN_RE.find_iter(query) {
+            let text = token.as_str();
+            let word = text.strip_prefix('+').unwrap_or(text);
+            if word.starts_with(['"', '-'])
+                || word.contains([':', '^', '~', '*'])
+                || matches!(word, "AND" | "OR" | "NOT")
+            {
+                continue;
+            }
+            let key = word
+                .trim_matches(|ch: char| !ch.is_alphanumeric())
+                .to_lowercase();
+            let Some(synonyms) = self.synonyms.get(&key) else {
+                continue;
+            };
+
+            expanded.push_str(&query[copied..token.start()]);
+            expanded.push_str(&text[..text.len() - word.len()]);
+            expanded.push('(');
+            expanded.push_str(word);
+            for synonym in synonyms {
+                expanded.push_str(&format!(" OR \"{synonym}\"^{}", self.synonym_boost));
+            }
+            expanded.push(')');
+            copied = token.end();
+        }
+        expanded.push_str(&query[copied..]);
+        expanded
+    }
+
+    /// Adds a fuzzy alternative for every title/body term of `query`, so
+    /// close misspellings also match. Exact matches score on both the
+    /// original and the fuzzy clause and so still rank first.
+    fn with_fuzzy_terms(&self, query: Box<dyn Query>) -> Box<dyn Query> {
+        let mut terms = Vec::new();
+        query.query_terms(&mut |term, _| {
+            if (term.field() == self.fields.title || term.field() == self.fields.body)
+                && !terms.contains(term)
+            {
+                terms.push(term.clone());
+            }
+        });
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Should, query)];
+        for term in terms {
+            let chars = term.value().as_str().map_or(0, |text| text.chars().count());
+            if chars < self.fuzzy_min_term_chars {
+                continue;
+            }
+            let distance = if chars >= TWO_EDIT_WORD_CHARS { 2 } else { 1 };
+            clauses.push((
+                Occur::Should,
+                Box::new(FuzzyTermQuery::new(
+                    term,
+                    distance.min(self.fuzzy_max_distance),
+                    true,
+                )),
+            ));
+        }
+
+        if clauses.len() == 1 {
+            clauses.remove(0).1
+        } else {
+            Box::new(BooleanQuery::new(clauses))
+        }
+    }
+
+    /// Builds an exact-phrase query over the unstemmed title and body,
+    /// tokenized the same way each field was indexed.
+    fn phrase_query(&self, searcher: &Searcher, query_text: &str) -> Result<Box<dyn Query>> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+        for &(field, boost) in self
+            .field_boosts
+            .iter()
+            .filter(|(field, _)| *field == self.fields.title || *field == self.fields.body)
+        {
+            let mut analyzer = searcher
+                .index()
+                .tokenizer_for_field(field)
+                .context("missing tokenizer for phrase query")?;
+            let mut terms = Vec::new();
+            let mut stream = analyzer.token_stream(query_text);
+            while stream.advance() {
+                let token = stream.token();
+                terms.push((token.position, Term::from_field_text(field, &token.text)));
+            }
+
+            let field_query: Box<dyn Query> = match terms.len() {
+                0 => continue,
+                1 => Box::new(TermQuery::new(
+                    terms.remove(0).1,
+                    IndexRecordOption::WithFreqsAndPositions,
+                )),
+                _ => Box::new(PhraseQuery::new_with_offset(terms)),
+            };
+            clauses.push((Occur::Should, Box::new(BoostQuery::new(field_query, boost))));
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// Rejects regex patterns that are too long, fail to compile, or (on a
+    /// large index) could match any term because they have no literal prefix.
+    pub fn check_regex(&self, pattern: &str) -> Result<()> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Ok(());
+        }
+        let chars = pattern.chars().count();
+        if chars > self.regex_max_pattern_chars {
+            bail!(
+                "regex is {chars} characters long; the limit is {}",
+                self.regex_max_pattern_chars
+            );
+        }
+        if let Err(err) = RegexQuery::from_pattern(pattern, self.fields.title) {
+            bail!("invalid regex `{pattern}`: {err}");
+        }
+        if regex_literal_prefix(pattern).is_empty()
+            && self.num_docs()? > self.regex_prefix_required_above_docs
+        {
+            bail!(
+                "regex `{pattern}` must start with a literal (e.g. `err.*` rather than `.*err`) on an index this large"
+            );
+        }
+        Ok(())
+    }
+
+    /// Matches `pattern` against the unstemmed title and body terms.
+    fn regex_query(&self, pattern: &str) -> Result<Box<dyn Query>> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+        for &(field, boost) in self
+            .field_boosts
+            .iter()
+            .filter(|(field, _)| *field == self.fields.title || *field == self.fields.body)
+        {
+            let field_query = RegexQuery::from_pattern(pattern, field)
+                .with_context(|| format!("invalid regex `{pattern}`"))?;
+            clauses.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(field_query), boost)),
+            ));
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// Looks up hits by `doc_id`, keeping the given scores and order. Unknown
+    /// ids and hits rejected by `filters` are dropped.
+    pub fn hits_for_doc_ids(
+        &self,
+        scored_ids: &[(String, f32)],
+        filters: &SearchFilters<'_>,
+    ) -> Result<Vec<SearchHit>> {
+        let searcher = self.reader.searcher();
+
+        let mut hits = Vec::with_capacity(scored_ids.len());
+        for (doc_id, score) in scored_ids {
+            let term_query = TermQuery::new(
+                Term::from_field_text(self.fields.doc_id, doc_id),
+                IndexRecordOption::Basic,
+            );
+            let Some((_, doc_addr)) = searcher
+                .search(&term_query, &TopDocs::with_limit(1))?
+                .into_iter()
+                .next()
+            else {
+                continue;
+            };
+
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_addr)
+                .context("failed to read indexed document")?;
+            let hit = self.hit_from_doc(&doc, *score);
+            if !filters.accepts(&hit) {
+                continue;
+            }
+            hits.push(hit);
+        }
+
+        Ok(hits)
+    }
+
+    /// Documents similar to `doc_id`: its title and body terms with the
+    /// highest TF-IDF, matched against other documents. `None` when no
+    /// document has that id.
+    pub fn more_like_this(&self, doc_id: &str, limit: usize) -> Result<Option<Vec<SearchHit>>> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+
+        let id_term = Term::from_field_text(self.fields.doc_id, doc_id);
+        let id_query = TermQuery::new(id_term.clone(), IndexRecordOption::Basic);
+        let Some((_, doc_addr)) = searcher
+            .search(&id_query, &TopDocs::with_limit(1))?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        if limit == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let doc = searcher
+            .doc::<TantivyDocument>(doc_addr)
+            .context("failed to read indexed document")?;
+        let text = format!(
+            "{}\n{}",
+            get_field_str(&doc, self.fields.title),
+            get_field_str(&doc, self.fields.body)
+        );
+
+        let mut analyzer = searcher
+            .index()
+            .tokenizer_for_field(self.fields.body)
+            .context("missing tokenizer for body field")?;
+        let mut term_freqs: HashMap<String, u32> = HashMap::new();
+        let mut stream = analyzer.token_stream(&text);
+        while stream.advance() {
+            let token = &stream.token().text;
+            if token.chars().count() >= MORE_LIKE_THIS_MIN_TERM_CHARS
+                && !token.chars().all(|ch| ch.is_ascii_digit())
+            {
+                *term_freqs.entry(token.clone()).or_default() += 1;
+            }
+        }
+
+        let total_docs = searcher.num_docs() as Score;
+        let mut weighted = Vec::with_capacity(term_freqs.len());
+        for (text, freq) in term_freqs {
+            let doc_freq = searcher.doc_freq(&Term::from_field_text(self.fields.body, &text))?;
+            // Terms found only in this document can't match any other one.
+            if doc_freq <= 1 {
+                continue;
+            }
+            let idf = (total_docs / doc_freq as Score).ln() + 1.0;
+            weighted.push((text, freq as Score * idf));
+        }
+        weighted.sort_by(|left, right| {
+            right
+                .1
+                .total_cmp(&left.1)
+                .then_with(|| left.0.cmp(&right.0))
+        });
+        weighted.truncate(MORE_LIKE_THIS_TERMS);
+        if weighted.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for (text, weight) in &weighted {
+            for field in [self.fields.title, self.fields.body] {
+                let term_query = TermQuery::new(
+                    Term::from_field_text(field, text),
+                    IndexRecordOption::WithFreqs,
+                );
+                clauses.push((
+                    Occur::Should,
+                    Box::new(BoostQuery::new(Box::new(term_query), *weight)),
+                ));
+            }
+        }
+        clauses.push((Occur::MustNot, Box::new(id_query)));
+        let query = BooleanQuery::new(clauses);
+
+        let mut hits = Vec::new();
+        for (score, doc_addr) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_addr)
+                .context("failed to read indexed document")?;
+            hits.push(self.hit_from_doc(&doc, score));
+        }
+
+        Ok(Some(hits))
+    }
+
+    /// One page of the documents of `source`, ordered by title (ignoring
+    /// case), with their total count. Hits carry a score of 0.
+    pub fn browse(&self, source: &str, limit: usize, offset: usize) -> Result<SearchResult> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+
+        let source_query = TermQuery::new(
+            Term::from_field_text(self.fields.source, source),
+            IndexRecordOption::Basic,
+        );
+        let total_hits = searcher.search(&source_query, &Count)?;
+        if offset >= total_hits {
+            return Ok(SearchResult {
+                total_hits,
+                hits: Vec::new(),
             });
         }
+        let top_docs = ranked_top_docs(
+            &searcher,
+            &source_query,
+            TopDocs::with_limit(limit).and_offset(offset),
+            SortOrder::Title,
+            None,
+        )?;
+
+        let mut hits = Vec::with_capacity(top_docs.len());
+        for (_, doc_addr) in top_docs {
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_addr)
+                .context("failed to read indexed document")?;
+            hits.push(self.hit_from_doc(&doc, 0.0));
+        }
 
         Ok(SearchResult { total_hits, hits })
     }
+
+    /// The stored fields of the document with `doc_id`; `None` when no
+    /// document has that id.
+    pub fn document(&self, doc_id: &str) -> Result<Option<IndexedDocument>> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+
+        let id_query = TermQuery::new(
+            Term::from_field_text(self.fields.doc_id, doc_id),
+            IndexRecordOption::Basic,
+        );
+        let Some((_, doc_addr)) = searcher
+            .search(&id_query, &TopDocs::with_limit(1))?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+
+        let doc = searcher
+            .doc::<TantivyDocument>(doc_addr)
+            .context("failed to read indexed document")?;
+        let url = get_field_str(&doc, self.fields.url);
+        Ok(Some(IndexedDocument {
+            doc_id: get_field_str(&doc, self.fields.doc_id),
+            source: get_field_str(&doc, self.fields.source),
+            title: get_field_str(&doc, self.fields.title),
+            body: get_field_str(&doc, self.fields.body),
+            location: get_field_str(&doc, self.fields.location),
+            url: if url.is_empty() { None } else { Some(url) },
+            date: doc
+                .get_first(self.fields.date)
+                .and_then(|value| value.as_i64()),
+            lang: doc
+                .get_first(self.fields.lang)
+                .and_then(|value| value.as_str())
+                .map(str::to_string),
+            tags: stored_tags(&doc, self.fields.tags),
+        }))
+    }
+
+    /// Reads back every live document with its stored body.
+    pub fn stored_documents(&self) -> Result<Vec<StoredDocument>> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+
+        let doc_addrs = searcher.search(&AllQuery, &DocSetCollector)?;
+        let mut docs = Vec::with_capacity(doc_addrs.len());
+        for doc_addr in doc_addrs {
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_addr)
+                .context("failed to read indexed document")?;
+            docs.push(StoredDocument {
+                doc_id: get_field_str(&doc, self.fields.doc_id),
+                title: get_field_str(&doc, self.fields.title),
+                body: get_field_str(&doc, self.fields.body),
+            });
+        }
+
+        Ok(docs)
+    }
+
+    fn hit_from_doc(&self, doc: &TantivyDocument, score: f32) -> SearchHit {
+        let body = match self.preview_chars {
+            0 => String::new(),
+            _ => get_field_str(doc, self.fields.body),
+        };
+        let preview = if body.is_empty() {
+            get_field_str(doc, self.fields.preview)
+        } else {
+            preview_from_text(&body, self.preview_chars)
+        };
+        let url = get_field_str(doc, self.fields.url);
+        let title = get_field_str(doc, self.fields.title);
+
+        SearchHit {
+            score,
+            doc_id: get_field_str(doc, self.fields.doc_id),
+            source: get_field_str(doc, self.fields.source),
+            highlighted_title: escape_html(&title),
+            title,
+            highlighted_preview: escape_html(&preview),
+            preview,
+            location: get_field_str(doc, self.fields.location),
+            url: if url.is_empty() { None } else { Some(url) },
+            date: doc
+                .get_first(self.fields.date)
+                .and_then(|value| value.as_i64()),
+            lang: doc
+                .get_first(self.fields.lang)
+                .and_then(|value| value.as_str())
+                .map(str::to_string),
+            tags: stored_tags(doc, self.fields.tags),
+            explanation: None,
+        }
+    }
+
+    /// Identifies the commit the reader sees by its segments and their
+    /// delete opstamps, so it changes whenever a commit changes the index
+    /// and stays the same across server restarts.
+    pub fn commit_generation(&self) -> String {
+        let searcher = self.reader.searcher();
+        searcher
+            .generation()
+            .segments()
+            .iter()
+            .map(|(segment_id, delete_opstamp)| {
+                format!(
+                    "{}:{}",
+                    segment_id.uuid_string(),
+                    delete_opstamp.unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Number of documents in the index, as of the latest commit.
+    pub fn num_docs(&self) -> Result<u64> {
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        Ok(self.reader.searcher().num_docs())
+    }
+
+    /// Completes the last word of `partial` from terms in the title field,
+    /// most frequent first.
+    pub fn suggest(&self, partial: &str, limit: usize) -> Result<Vec<String>> {
+        let normalized = partial.trim().to_lowercase();
+        let words: Vec<&str> = normalized
+            .split(|ch: char| !ch.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let Some((prefix, leading)) = words.split_last() else {
+            return Ok(Vec::new());
+        };
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+
+        let mut doc_freqs: HashMap<String, u64> = HashMap::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader
+                .inverted_index(self.fields.title)
+                .context("failed to open title term dictionary")?;
+            let mut stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream()
+                .context("failed to scan title terms")?;
+
+            let mut scanned = 0usize;
+            while stream.advance() && scanned < MAX_SUGGEST_TERMS_SCANNED {
+                let Ok(term) = std::str::from_utf8(stream.key()) else {
+                    continue;
+                };
+                if !term.starts_with(prefix) {
+                    break;
+                }
+                *doc_freqs.entry(term.to_string()).or_default() +=
+                    u64::from(stream.value().doc_freq);
+                scanned += 1;
+            }
+        }
+
+        let mut ranked: Vec<(String, u64)> = doc_freqs.into_iter().collect();
+        ranked.sort_by(|left, right| right.1.cmp(&left.1).then_with(|| left.0.cmp(&right.0)));
+
+        let lead = leading.join(" ");
+        Ok(ranked
+            .into_iter()
+            .take(limit)
+            .map(|(term, _)| {
+                if lead.is_empty() {
+                    term
+                } else {
+                    format!("{lead} {term}")
+                }
+            })
+            .collect())
+    }
+
+    /// Proposes a corrected query by replacing each word that has no match in
+    /// the title or body with the closest indexed term (Levenshtein distance,
+    /// then document frequency). Returns `None` when nothing was corrected.
+    pub fn did_you_mean(&self, query: &str) -> Result<Option<String>> {
+        // A respelled query would drop the field prefixes.
+        if has_field_prefix(query) {
+            return Ok(None);
+        }
+
+        let normalized = query.trim().to_lowercase();
+        let words: Vec<&str> = normalized
+            .split(|ch: char| !ch.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        if words.is_empty() {
+            return Ok(None);
+        }
+
+        self.reader
+            .reload()
+            .context("failed to refresh index reader")?;
+        let searcher = self.reader.searcher();
+        let fields = [self.fields.title, self.fields.body];
+
+        let mut corrected = false;
+        let mut suggestion = Vec::with_capacity(words.len());
+        for word in words {
+            let known = fields.iter().try_fold(false, |known, field| {
+                Ok::<_, anyhow::Error>(
+                    known || searcher.doc_freq(&Term::from_field_text(*field, word))? > 0,
+                )
+            })?;
+            if known || word.chars().count() < MIN_CORRECTED_WORD_CHARS {
+                suggestion.push(word.to_string());
+                continue;
+            }
+
+            match self.closest_term(&searcher, &fields, word)? {
+                Some(term) => {
+                    corrected = true;
+                    suggestion.push(term);
+                }
+                None => suggestion.push(word.to_string()),
+            }
+        }
+
+        Ok(corrected.then(|| suggestion.join(" ")))
+    }
+
+    /// The indexed term nearest to `word` among terms sharing its first
+    /// character, if any is within the allowed edit distance.
+    fn closest_term(
+        &self,
+        searcher: &Searcher,
+        fields: &[Field],
+        word: &str,
+    ) -> Result<Option<String>> {
+        let word_chars: Vec<char> = word.chars().collect();
+        let max_distance = if word_chars.len() >= TWO_EDIT_WORD_CHARS {
+            2
+        } else {
+            1
+        };
+        let first = word_chars[0].to_string();
+
+        let mut candidates: HashMap<String, (usize, u64)> = HashMap::new();
+        for segment_reader in searcher.segment_readers() {
+            for field in fields {
+                let inverted_index = segment_reader
+                    .inverted_index(*field)
+                    .context("failed to open term dictionary")?;
+                let mut stream = inverted_index
+                    .terms()
+                    .range()
+                    .ge(first.as_bytes())
+                    .into_stream()
+                    .context("failed to scan terms")?;
+
+                let mut scanned = 0usize;
+                while stream.advance() && scanned < MAX_SPELLING_TERMS_SCANNED {
+                    scanned += 1;
+                    let Ok(term) = std::str::from_utf8(stream.key()) else {
+                        continue;
+                    };
+                    if !term.starts_with(&first) {
+                        break;
+                    }
+                    let Some(distance) = bounded_levenshtein(&word_chars, term, max_distance)
+                    else {
+                        continue;
+                    };
+                    let entry = candidates.entry(term.to_string()).or_insert((distance, 0));
+                    entry.1 += u64::from(stream.value().doc_freq);
+                }
+            }
+        }
+
+        Ok(candidates
+            .into_iter()
+            .min_by(
+                |(left, (left_distance, left_freq)), (right, (right_distance, right_freq))| {
+                    left_distance
+                        .cmp(right_distance)
+                        .then_with(|| right_freq.cmp(left_freq))
+                        .then_with(|| left.cmp(right))
+                },
+            )
+            .map(|(term, _)| term))
+    }
+}
+
+/// The literal characters every match of `pattern` must start with. Empty
+/// when the pattern opens with a metacharacter, or has a top-level `|` whose
+/// alternatives could start anywhere.
+fn regex_literal_prefix(pattern: &str) -> &str {
+    let mut depth = 0usize;
+    let mut in_class = false;
+    let mut escaped = false;
+    for ch in pattern.chars() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => depth += 1,
+            ')' if !in_class => depth = depth.saturating_sub(1),
+            '|' if !in_class && depth == 0 => return "",
+            _ => {}
+        }
+    }
+
+    let end = pattern
+        .find(|ch: char| ".^$*+?()[]{}|\\".contains(ch))
+        .unwrap_or(pattern.len());
+    let prefix = &pattern[..end];
+    // A literal followed by `*`, `?` or `{0,..}` may be absent.
+    match pattern[end..].chars().next() {
+        Some('*' | '?' | '{') => {
+            let last = prefix.char_indices().last().map_or(0, |(index, _)| index);
+            &prefix[..last]
+        }
+        _ => prefix,
+    }
 }
 
-pub fn open_or_create_index(index_dir: &Path) -> Result<IndexHandle> {
+/// Levenshtein distance between `word` and `candidate`, or `None` when it
+/// exceeds `max_distance`.
+fn bounded_levenshtein(word: &[char], candidate: &str, max_distance: usize) -> Option<usize> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    if candidate.len().abs_diff(word.len()) > max_distance {
+        return None;
+    }
+
+    let mut previous: Vec<usize> = (0..=candidate.len()).collect();
+    let mut current = vec![0; candidate.len() + 1];
+    for (i, word_char) in word.iter().enumerate() {
+        current[0] = i + 1;
+        let mut row_min = current[0];
+        for (j, candidate_char) in candidate.iter().enumerate() {
+            let substitution = previous[j] + usize::from(word_char != candidate_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            row_min = row_min.min(current[j + 1]);
+        }
+        if row_min > max_distance {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[candidate.len()];
+    (distance <= max_distance).then_some(distance)
+}
+
+/// BM25 with configurable k1/b, summed over the query's title/body terms.
+/// Mirrors tantivy's own formula (idf * (k1 + 1) * tf / (tf + k1 * norm)).
+struct Bm25Rescorer {
+    params: Bm25Params,
+    terms: Vec<Bm25Term>,
+}
+
+struct Bm25Term {
+    term: Term,
+    weight: Score,
+    average_fieldnorm: Score,
+}
+
+impl Bm25Rescorer {
+    fn new(
+        searcher: &Searcher,
+        query: &dyn Query,
+        field_boosts: &[(Field, Score)],
+        params: Bm25Params,
+    ) -> Result<Self> {
+        let mut query_terms = Vec::new();
+        query.query_terms(&mut |term, _| {
+            if field_boosts.iter().any(|(field, _)| *field == term.field()) {
+                query_terms.push(term.clone());
+            }
+        });
+        query_terms.sort();
+        query_terms.dedup();
+
+        let total_docs = searcher.num_docs() as Score;
+        let mut terms = Vec::with_capacity(query_terms.len());
+        for term in query_terms {
+            let doc_freq = searcher.doc_freq(&term)? as Score;
+            let idf = (1.0 + (total_docs - doc_freq + 0.5) / (doc_freq + 0.5)).ln();
+            let boost = field_boosts
+                .iter()
+                .find(|(field, _)| *field == term.field())
+                .map(|(_, boost)| *boost)
+                .unwrap_or(1.0);
+
+            let mut total_tokens = 0u64;
+            for segment_reader in searcher.segment_readers() {
+                total_tokens += segment_reader
+                    .inverted_index(term.field())?
+                    .total_num_tokens();
+            }
+            let average_fieldnorm = if total_docs > 0.0 {
+                (total_tokens as Score / total_docs).max(1.0)
+            } else {
+                1.0
+            };
+
+            terms.push(Bm25Term {
+                term,
+                weight: idf * boost,
+                average_fieldnorm,
+            });
+        }
+
+        Ok(Self { params, terms })
+    }
+
+    fn for_segment(&self, segment_reader: &SegmentReader) -> impl FnMut(DocId, Score) -> Score {
+        let mut postings: Vec<(SegmentPostings, FieldNormReader, Score, Score)> = Vec::new();
+        for entry in &self.terms {
+            let field = entry.term.field();
+            let Ok(inverted_index) = segment_reader.inverted_index(field) else {
+                continue;
+            };
+            let Ok(Some(term_postings)) =
+                inverted_index.read_postings(&entry.term, IndexRecordOption::WithFreqs)
+            else {
+                continue;
+            };
+            let Ok(fieldnorms) = segment_reader.get_fieldnorms_reader(field) else {
+                continue;
+            };
+            postings.push((
+                term_postings,
+                fieldnorms,
+                entry.weight,
+                entry.average_fieldnorm,
+            ));
+        }
+
+        let Bm25Params { k1, b } = self.params;
+        // Collectors visit docs in increasing order, so postings only seek forward.
+        move |doc, _| {
+            let mut score = 0.0;
+            for (term_postings, fieldnorms, weight, average_fieldnorm) in postings.iter_mut() {
+                if term_postings.doc() < doc {
+                    term_postings.seek(doc);
+                }
+                if term_postings.doc() != doc {
+                    continue;
+                }
+
+                let term_freq = term_postings.term_freq() as Score;
+                let norm = 1.0 - b + b * fieldnorms.fieldnorm(doc) as Score / *average_fieldnorm;
+                score += *weight * (k1 + 1.0) * term_freq / (term_freq + k1 * norm);
+            }
+            score
+        }
+    }
+}
+
+/// Up to `count` non-overlapping highlighted fragments of `text`, in text
+/// order. tantivy only picks the single best fragment, so the text around
+/// each chosen one is snippeted again and the fragment with the most matches
+/// wins the next slot.
+fn best_fragments(generator: &SnippetGenerator, text: &str, count: usize) -> Vec<Snippet> {
+    // (byte offset in `text`, unsplit slice, its best fragment)
+    let mut candidates = vec![(0, text, generator.snippet(text))];
+    let mut chosen = Vec::new();
+    while chosen.len() < count {
+        let Some(best) = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, snippet))| !snippet.is_empty())
+            .max_by_key(|(_, (offset, _, snippet))| (snippet.highlighted().len(), Reverse(*offset)))
+            .map(|(position, _)| position)
+        else {
+            break;
+        };
+        let (offset, slice, snippet) = candidates.swap_remove(best);
+        let start = slice.find(snippet.fragment()).unwrap_or(0);
+        let end = (start + snippet.fragment().len()).min(slice.len());
+        for (rest_offset, rest) in [(offset, &slice[..start]), (offset + end, &slice[end..])] {
+            if !rest.trim().is_empty() {
+                candidates.push((rest_offset, rest, generator.snippet(rest)));
+            }
+        }
+        chosen.push((offset + start, snippet));
+    }
+    chosen.sort_by_key(|(offset, _)| *offset);
+    chosen.into_iter().map(|(_, snippet)| snippet).collect()
+}
+
+/// Relevance of a segment's matches: tantivy's own score, or the configured
+/// BM25 when k1/b were changed.
+fn segment_relevance(
+    rescorer: Option<&Bm25Rescorer>,
+    segment_reader: &SegmentReader,
+) -> Box<dyn FnMut(DocId, Score) -> Score> {
+    match rescorer {
+        Some(rescorer) => Box::new(rescorer.for_segment(segment_reader)),
+        None => Box::new(|_, score| score),
+    }
+}
+
+/// Collects one page of matches in `sort` order. Date and title sorts read
+/// fast columns, compare titles as strings across segments, break ties by
+/// relevance and key undated documents below every date.
+fn ranked_top_docs(
+    searcher: &Searcher,
+    query: &dyn Query,
+    top_collector: TopDocs,
+    sort: SortOrder,
+    rescorer: Option<Bm25Rescorer>,
+) -> Result<Vec<(Score, DocAddress)>> {
+    let top_docs = match sort {
+        SortOrder::Relevance => match rescorer {
+            Some(rescorer) => searcher.search(
+                query,
+                &top_collector.tweak_score(move |segment_reader: &SegmentReader| {
+                    rescorer.for_segment(segment_reader)
+                }),
+            )?,
+            None => searcher.search(query, &top_collector)?,
+        },
+        SortOrder::DateDesc | SortOrder::DateAsc => {
+            let ascending = sort == SortOrder::DateAsc;
+            let collector = top_collector.tweak_score(move |segment_reader: &SegmentReader| {
+                let dates = segment_reader.fast_fields().i64(DATE_FIELD).ok();
+                let mut relevance = segment_relevance(rescorer.as_ref(), segment_reader);
+                move |doc, score| {
+                    let date = dates.as_ref().and_then(|dates| dates.first(doc));
+                    let key = date.map_or(i64::MIN, |date| {
+                        if ascending {
+                            date.saturating_neg()
+                        } else {
+                            date
+                        }
+                    });
+                    (key, relevance(doc, score))
+                }
+            });
+            searcher
+                .search(query, &collector)?
+                .into_iter()
+                .map(|((_, score), doc_addr)| (score, doc_addr))
+                .collect()
+        }
+        SortOrder::Title => {
+            let collector = top_collector.tweak_score(move |segment_reader: &SegmentReader| {
+                let titles = segment_reader
+                    .fast_fields()
+                    .str(TITLE_SORT_FIELD)
+                    .ok()
+                    .flatten();
+                let mut relevance = segment_relevance(rescorer.as_ref(), segment_reader);
+                move |doc, score| {
+                    let mut title = String::new();
+                    if let Some(titles) = &titles {
+                        if let Some(ord) = titles.term_ords(doc).next() {
+                            let _ = titles.ord_to_str(ord, &mut title);
+                        }
+                    }
+                    (Reverse(title), relevance(doc, score))
+                }
+            });
+            searcher
+                .search(query, &collector)?
+                .into_iter()
+                .map(|((_, score), doc_addr)| (score, doc_addr))
+                .collect()
+        }
+    };
+    Ok(top_docs)
+}
+
+/// Bump whenever the schema or analysis changes in a way that requires
+/// re-indexing. Stored as the commit payload in the index `meta.json`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Files tantivy keeps next to its segments; listed in `.managed.json`.
+const TANTIVY_MANAGED_FILE: &str = ".managed.json";
+const TANTIVY_LOCK_FILES: &[&str] = &[".tantivy-meta.lock", ".tantivy-writer.lock"];
+
+/// Tantivy's built-in tokenizer, used by the `TEXT` title and body fields.
+const DEFAULT_TOKENIZER: &str = "default";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CommitPayload {
+    schema_version: u32,
+    /// Unix seconds; missing from commits made before it was recorded.
+    #[serde(default)]
+    committed_at: Option<i64>,
+    /// Hash of the `stop_words` list the index was built with; absent when
+    /// the list was empty.
+    #[serde(default)]
+    stop_words: Option<String>,
+}
+
+fn commit_payload(index: &Index) -> Result<Option<CommitPayload>> {
+    Ok(index
+        .load_metas()
+        .context("failed to read index metadata")?
+        .payload
+        .and_then(|payload| serde_json::from_str::<CommitPayload>(&payload).ok()))
+}
+
+fn stop_words_fingerprint(config: &AppConfig) -> Option<String> {
+    let words = config.stop_words.words();
+    if words.is_empty() {
+        return None;
+    }
+    Some(
+        blake3::hash(words.join("\n").as_bytes())
+            .to_hex()
+            .to_string(),
+    )
+}
+
+/// True when an existing index was built with a different `stop_words`
+/// list than the config has now, so it needs `index --rebuild`.
+pub fn stop_words_changed(config: &AppConfig) -> Result<bool> {
+    let index_dir = config.index_dir.as_path();
+    if !index_dir.exists() {
+        return Ok(false);
+    }
+    let mmap_dir = MmapDirectory::open(index_dir)
+        .with_context(|| format!("bad index dir {}", index_dir.display()))?;
+    if !Index::exists(&mmap_dir)
+        .with_context(|| format!("failed to inspect index at {}", index_dir.display()))?
+    {
+        return Ok(false);
+    }
+
+    let index = Index::open(mmap_dir)
+        .with_context(|| format!("failed to open index at {}", index_dir.display()))?;
+    let built_with = commit_payload(&index)
+        .with_context(|| format!("failed to read commit of {}", index_dir.display()))?
+        .and_then(|payload| payload.stop_words);
+    Ok(built_with != stop_words_fingerprint(config))
+}
+
+/// True when an existing index stores documents with a different codec
+/// than `stored_compression` asks for. tantivy fixes the codec when an
+/// index is created, so only recreating the index applies the new one.
+pub fn stored_compression_changed(config: &AppConfig) -> Result<bool> {
+    let index_dir = config.index_dir.as_path();
+    if !index_dir.exists() {
+        return Ok(false);
+    }
+    let mmap_dir = MmapDirectory::open(index_dir)
+        .with_context(|| format!("bad index dir {}", index_dir.display()))?;
+    if !Index::exists(&mmap_dir)
+        .with_context(|| format!("failed to inspect index at {}", index_dir.display()))?
+    {
+        return Ok(false);
+    }
+
+    let index = Index::open(mmap_dir)
+        .with_context(|| format!("failed to open index at {}", index_dir.display()))?;
+    Ok(index.settings().docstore_compression != docstore_compressor(config))
+}
+
+/// How an existing index on disk relates to the schema this binary builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaStatus {
+    Missing,
+    Current,
+    /// `additive` is true when every existing field is unchanged and the
+    /// new schema only adds fields, so a plain re-index migrates it.
+    Outdated {
+        found_version: Option<u32>,
+        additive: bool,
+    },
+}
+
+pub fn schema_status(config: &AppConfig) -> Result<SchemaStatus> {
+    let index_dir = config.index_dir.as_path();
+    if !index_dir.exists() {
+        return Ok(SchemaStatus::Missing);
+    }
+
+    let mmap_dir = MmapDirectory::open(index_dir)
+        .with_context(|| format!("bad index dir {}", index_dir.display()))?;
+    if !Index::exists(&mmap_dir)
+        .with_context(|| format!("failed to inspect index at {}", index_dir.display()))?
+    {
+        return Ok(SchemaStatus::Missing);
+    }
+
+    // An unreadable `meta.json` is the usual sign of an interrupted commit.
+    let index = Index::open(mmap_dir).map_err(|err| damaged_index(index_dir, format!("{err}")))?;
+    let found_version = commit_payload(&index)
+        .map_err(|err| damaged_index(index_dir, format!("{err:#}")))?
+        .map(|payload| payload.schema_version);
+
+    let existing = index.schema();
+    let expected = build_schema(&config.stemmed_languages(), config.has_code_sources());
+    let additive = existing.fields().all(|(_, entry)| {
+        expected
+            .get_field(entry.name())
+            .is_ok_and(|field| expected.get_field_entry(field) == entry)
+    });
+    let same_fields = additive && existing.num_fields() == expected.num_fields();
+
+    // Indexes written before the marker existed carry no payload; accept
+    // them as long as the schema itself matches.
+    if same_fields && found_version.unwrap_or(SCHEMA_VERSION) == SCHEMA_VERSION {
+        return Ok(SchemaStatus::Current);
+    }
+
+    Ok(SchemaStatus::Outdated {
+        found_version,
+        additive: additive && found_version.unwrap_or(SCHEMA_VERSION) <= SCHEMA_VERSION,
+    })
+}
+
+pub fn open_or_create_index(config: &AppConfig) -> Result<IndexHandle> {
+    let index_dir = config.index_dir.as_path();
     fs::create_dir_all(index_dir)
         .with_context(|| format!("failed to create index dir {}", index_dir.display()))?;
 
-    let schema = build_schema();
+    if let SchemaStatus::Outdated {
+        found_version,
+        additive,
+    } = schema_status(config)?
+    {
+        let found = found_version
+            .map(|version| format!("schema version {version}"))
+            .unwrap_or_else(|| "an unversioned schema".to_string());
+        let fix = if additive {
+            "run `bunker-search index` to migrate it (only new fields were added)"
+        } else {
+            "run `bunker-search index --rebuild` to recreate it"
+        };
+        bail!(
+            "index at {} ({found}) does not match schema version {SCHEMA_VERSION} or the configured `language` settings; {fix}",
+            index_dir.display()
+        );
+    }
+
+    let languages = config.stemmed_languages();
+    let code = config.has_code_sources();
+    let schema = build_schema(&languages, code);
     let mmap_dir = MmapDirectory::open(index_dir)
         .with_context(|| format!("bad index dir {}", index_dir.display()))?;
-    let index = Index::open_or_create(mmap_dir, schema)
+    let settings = IndexSettings {
+        docstore_compression: docstore_compressor(config),
+        ..IndexSettings::default()
+    };
+    let index = Index::builder()
+        .schema(schema)
+        .settings(settings.clone())
+        .open_or_create(mmap_dir)
         .with_context(|| format!("failed to open/create index at {}", index_dir.display()))?;
+    // Settings are fixed when an index is created; segments keep whatever
+    // codec they were written with.
+    if index.settings().docstore_compression != settings.docstore_compression {
+        tracing::warn!(
+            index_dir = %index_dir.display(),
+            "stored_compression differs from the existing index's; run `bunker-search index --rebuild` to apply it"
+        );
+    }
+
+    for language in &languages {
+        index.tokenizers().register(
+            &stemmed_tokenizer_name(*language),
+            stemmed_analyzer(*language),
+        );
+    }
+
+    // Custom stop words replace tantivy's default tokenizer (used by the
+    // plain title and body) instead of changing the schema, so an empty
+    // list leaves analysis exactly as before.
+    let stop_words = config.stop_words.words();
+    if !stop_words.is_empty() {
+        index
+            .tokenizers()
+            .register(DEFAULT_TOKENIZER, stop_word_analyzer(stop_words));
+    }
+
+    if code {
+        index.tokenizers().register(CODE_TOKENIZER, code_analyzer());
+    }
 
-    let fields = fields_from_schema(index.schema())?;
+    let fields = fields_from_schema(index.schema(), &languages, code)?;
 
     Ok(IndexHandle { index, fields })
 }
 
-fn build_schema() -> Schema {
+fn docstore_compressor(config: &AppConfig) -> Compressor {
+    match config.stored_compression {
+        StoredCompression::Lz4 => Compressor::Lz4,
+        StoredCompression::Zstd => Compressor::Zstd(ZstdCompressor {
+            compression_level: config.stored_compression_level,
+        }),
+        StoredCompression::None => Compressor::None,
+    }
+}
+
+fn damaged_index(index_dir: &Path, detail: String) -> anyhow::Error {
+    anyhow!(
+        "index at {} is damaged ({detail}); run `bunker-search index --rebuild` to recreate it",
+        index_dir.display()
+    )
+}
+
+/// Fails with a rebuild hint when the committed index can't be read back:
+/// a segment `meta.json` lists won't open or count its documents, or (with
+/// `verify_index_checksums`) a segment file no longer matches its checksum,
+/// as after a power loss mid-commit.
+fn verify_index(index: &Index, reader: &IndexReader, config: &AppConfig) -> Result<()> {
+    let index_dir = config.index_dir.as_path();
+    reader
+        .searcher()
+        .search(&AllQuery, &Count)
+        .map_err(|err| damaged_index(index_dir, format!("{err}")))?;
+
+    if config.verify_index_checksums {
+        let mut damaged: Vec<String> = index
+            .validate_checksum()
+            .map_err(|err| damaged_index(index_dir, format!("{err}")))?
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        if !damaged.is_empty() {
+            damaged.sort();
+            return Err(damaged_index(
+                index_dir,
+                format!("checksum mismatch in {}", damaged.join(", ")),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the tantivy files in `index_dir` so the next open creates an
+/// empty index with the current schema. Sidecar files such as the manifest
+/// are left alone.
+pub fn remove_index_files(index_dir: &Path) -> Result<()> {
+    let managed_path = index_dir.join(TANTIVY_MANAGED_FILE);
+    let managed: Vec<String> = match fs::read_to_string(&managed_path) {
+        Ok(data) => serde_json::from_str(&data)
+            .with_context(|| format!("failed to parse {}", managed_path.display()))?,
+        Err(_) => Vec::new(),
+    };
+
+    for name in managed
+        .iter()
+        .map(String::as_str)
+        .chain(["meta.json", TANTIVY_MANAGED_FILE])
+        .chain(TANTIVY_LOCK_FILES.iter().copied())
+    {
+        let path = index_dir.join(name);
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Total size of the files directly inside `index_dir`.
+pub fn index_size_bytes(index_dir: &Path) -> Result<u64> {
+    let mut size_bytes = 0;
+    let entries = fs::read_dir(index_dir)
+        .with_context(|| format!("failed to list {}", index_dir.display()))?;
+    for entry in entries.flatten() {
+        if let Ok(metadata) = entry.metadata() {
+            if metadata.is_file() {
+                size_bytes += metadata.len();
+            }
+        }
+    }
+    Ok(size_bytes)
+}
+
+/// Commits pending changes and stamps the commit with `SCHEMA_VERSION`, the
+/// current time and the stop-word list in use.
+pub fn commit_index(writer: &mut IndexWriter, config: &AppConfig) -> Result<()> {
+    let committed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_secs() as i64);
+    let payload = serde_json::to_string(&CommitPayload {
+        schema_version: SCHEMA_VERSION,
+        committed_at,
+        stop_words: stop_words_fingerprint(config),
+    })
+    .context("failed to serialize commit payload")?;
+
+    let mut prepared = writer
+        .prepare_commit()
+        .context("failed to prepare index commit")?;
+    prepared.set_payload(&payload);
+    prepared
+        .commit()
+        .context("failed to commit index changes")?;
+    Ok(())
+}
+
+fn build_schema(languages: &BTreeSet<TextLanguage>, code: bool) -> Schema {
     let mut builder = Schema::builder();
 
     builder.add_text_field(DOC_ID_FIELD, STRING | STORED);
     builder.add_text_field(SOURCE_FIELD, STRING | STORED);
     builder.add_text_field(TITLE_FIELD, TEXT | STORED);
-    builder.add_text_field(BODY_FIELD, TEXT);
+    builder.add_text_field(BODY_FIELD, TEXT | STORED);
     builder.add_text_field(PREVIEW_FIELD, STORED);
     builder.add_text_field(LOCATION_FIELD, STORED);
+    builder.add_text_field(LOCATION_TERMS_FIELD, TEXT);
+    builder.add_text_field(TITLE_SORT_FIELD, FAST);
     builder.add_text_field(URL_FIELD, STORED);
+    builder.add_i64_field(DATE_FIELD, INDEXED | FAST | STORED);
+    builder.add_text_field(LANG_FIELD, STRING | STORED);
+    builder.add_text_field(TAGS_FIELD, STRING | STORED);
+
+    for language in languages {
+        let options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(&stemmed_tokenizer_name(*language))
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        builder.add_text_field(&stemmed_field_name(TITLE_FIELD, *language), options.clone());
+        builder.add_text_field(&stemmed_field_name(BODY_FIELD, *language), options);
+    }
+
+    if code {
+        let options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(CODE_TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        builder.add_text_field(CODE_TITLE_FIELD, options.clone());
+        builder.add_text_field(CODE_BODY_FIELD, options);
+    }
 
     builder.build()
 }
 
-fn fields_from_schema(schema: Schema) -> Result<IndexFields> {
+/// Maps every entry of the synonym groups to the other entries of the
+/// groups it belongs to. Quotes are dropped so entries can be quoted safely.
+fn synonym_map(groups: &[Vec<String>]) -> HashMap<String, Vec<String>> {
+    let mut synonyms: HashMap<String, Vec<String>> = HashMap::new();
+    for group in groups {
+        for entry in group {
+            let others = synonyms.entry(entry.clone()).or_default();
+            for other in group {
+                let other = other.replace('"', "");
+                if other != *entry && !others.contains(&other) {
+                    others.push(other);
+                }
+            }
+        }
+    }
+    synonyms
+}
+
+/// Splits `query` into the part to match and its top-level exclusions: each
+/// word or quoted phrase prefixed with `-`, returned without the `-`.
+pub fn split_exclusions(query: &str) -> (String, Vec<String>) {
+    let mut included = String::with_capacity(query.len());
+    let mut excluded = Vec::new();
+    let mut copied = 0;
+    let mut previous = "";
+    let mut tokens = QUERY_TOKEN_RE.find_iter(query).peekable();
+    while let Some(token) = tokens.next() {
+        let before = &query[..token.start()];
+        let nested = before.matches('(').count() > before.matches(')').count();
+        // Operands of AND/OR/NOT stay with the parser to keep the operator valid.
+        let operand = matches!(previous, "AND" | "OR" | "NOT");
+        previous = token.as_str();
+        if nested || operand || !token.as_str().starts_with('-') {
+            continue;
+        }
+        let (excluded_text, end) = match &token.as_str()[1..] {
+            // `-"a phrase"` tokenizes as `-` followed by the quoted phrase.
+            "" => match tokens.next_if(|next| next.start() == token.end()) {
+                Some(phrase) => (phrase.as_str(), phrase.end()),
+                None => continue,
+            },
+            word => (word, token.end()),
+        };
+        included.push_str(&query[copied..token.start()]);
+        excluded.push(excluded_text.to_string());
+        copied = end;
+    }
+    included.push_str(&query[copied..]);
+    (included, excluded)
+}
+
+/// Rewrites the field prefixes of a parsed query to the index fields they
+/// search, rejecting unknown ones with the list of valid names.
+fn scope_query_fields(query: &str) -> Result<String> {
+    let mut scoped = String::with_capacity(query.len());
+    let mut copied = 0;
+    for field in FIELD_PREFIX_RE
+        .captures_iter(query)
+        .filter_map(|captures| captures.name("field"))
+    {
+        let Some((_, index_field)) = QUERYABLE_FIELDS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(field.as_str()))
+        else {
+            let names: Vec<&str> = QUERYABLE_FIELDS.iter().map(|(name, _)| *name).collect();
+            bail!(
+                "unknown field `{}` in query; searchable fields are {}",
+                field.as_str(),
+                names.join(", ")
+            );
+        };
+        scoped.push_str(&query[copied..field.start()]);
+        scoped.push_str(index_field);
+        copied = field.end();
+    }
+    scoped.push_str(&query[copied..]);
+    Ok(scoped)
+}
+
+/// Fails with a readable message when `query` uses a field prefix other
+/// than the queryable ones.
+pub fn check_field_prefixes(query: &str) -> Result<()> {
+    scope_query_fields(query).map(drop)
+}
+
+fn has_field_prefix(query: &str) -> bool {
+    FIELD_PREFIX_RE
+        .captures_iter(query)
+        .any(|captures| captures.name("field").is_some())
+}
+
+fn stemmed_field_name(base: &str, language: TextLanguage) -> String {
+    format!("{base}_{}", language.name())
+}
+
+fn stemmed_tokenizer_name(language: TextLanguage) -> String {
+    format!("stem_{}", language.name())
+}
+
+/// Tantivy's default tokenizer chain plus stop words and a stemmer; CJK
+/// bigrams for `cjk`.
+fn stemmed_analyzer(language: TextLanguage) -> TextAnalyzer {
+    let stemmer_language = match language {
+        TextLanguage::Cjk => return cjk_analyzer(),
+        TextLanguage::Arabic => Language::Arabic,
+        TextLanguage::Danish => Language::Danish,
+        TextLanguage::Dutch => Language::Dutch,
+        TextLanguage::Finnish => Language::Finnish,
+        TextLanguage::French => Language::French,
+        TextLanguage::German => Language::German,
+        TextLanguage::Greek => Language::Greek,
+        TextLanguage::Hungarian => Language::Hungarian,
+        TextLanguage::Italian => Language::Italian,
+        TextLanguage::Norwegian => Language::Norwegian,
+        TextLanguage::Portuguese => Language::Portuguese,
+        TextLanguage::Romanian => Language::Romanian,
+        TextLanguage::Russian => Language::Russian,
+        TextLanguage::Spanish => Language::Spanish,
+        TextLanguage::Swedish => Language::Swedish,
+        TextLanguage::Tamil => Language::Tamil,
+        TextLanguage::Turkish => Language::Turkish,
+        TextLanguage::None | TextLanguage::English => Language::English,
+    };
+
+    let builder = TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser)
+        .dynamic();
+    let builder = match StopWordFilter::new(stemmer_language) {
+        Some(stop_words) => builder.filter_dynamic(stop_words),
+        None => builder,
+    };
+    builder
+        .filter_dynamic(Stemmer::new(stemmer_language))
+        .build()
+}
+
+/// Identifier-aware tokenizer for `code = true` sources.
+fn code_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(CodeTokenizer::default())
+        .filter(RemoveLongFilter::limit(80))
+        .filter(LowerCaser)
+        .build()
+}
+
+/// Character bigrams for `language = "cjk"` sources.
+fn cjk_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(CjkBigramTokenizer::default())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser)
+        .build()
+}
+
+/// Tantivy's default tokenizer chain plus the configured stop words.
+fn stop_word_analyzer(words: &[String]) -> TextAnalyzer {
+    TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser)
+        .filter(StopWordFilter::remove(words.iter().cloned()))
+        .build()
+}
+
+fn fields_from_schema(
+    schema: Schema,
+    languages: &BTreeSet<TextLanguage>,
+    code: bool,
+) -> Result<IndexFields> {
+    let stemmed = languages
+        .iter()
+        .map(|language| {
+            Ok(StemmedFields {
+                language: *language,
+                title: field_or_err(&schema, &stemmed_field_name(TITLE_FIELD, *language))?,
+                body: field_or_err(&schema, &stemmed_field_name(BODY_FIELD, *language))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
     Ok(IndexFields {
         doc_id: field_or_err(&schema, DOC_ID_FIELD)?,
         source: field_or_err(&schema, SOURCE_FIELD)?,
@@ -187,7 +2230,21 @@ fn fields_from_schema(schema: Schema) -> Result<IndexFields> {
         body: field_or_err(&schema, BODY_FIELD)?,
         preview: field_or_err(&schema, PREVIEW_FIELD)?,
         location: field_or_err(&schema, LOCATION_FIELD)?,
+        location_terms: field_or_err(&schema, LOCATION_TERMS_FIELD)?,
+        title_sort: field_or_err(&schema, TITLE_SORT_FIELD)?,
         url: field_or_err(&schema, URL_FIELD)?,
+        date: field_or_err(&schema, DATE_FIELD)?,
+        lang: field_or_err(&schema, LANG_FIELD)?,
+        tags: field_or_err(&schema, TAGS_FIELD)?,
+        stemmed,
+        code: if code {
+            Some(CodeFields {
+                title: field_or_err(&schema, CODE_TITLE_FIELD)?,
+                body: field_or_err(&schema, CODE_BODY_FIELD)?,
+            })
+        } else {
+            None
+        },
     })
 }
 
@@ -197,9 +2254,188 @@ fn field_or_err(schema: &Schema, field_name: &str) -> Result<Field> {
         .map_err(|_| anyhow!("missing field '{field_name}' in tantivy schema"))
 }
 
+fn stored_tags(doc: &TantivyDocument, field: Field) -> Vec<String> {
+    doc.get_all(field)
+        .filter_map(|value| value.as_str())
+        .map(str::to_string)
+        .collect()
+}
+
 fn get_field_str(doc: &TantivyDocument, field: Field) -> String {
     doc.get_first(field)
         .and_then(|value| value.as_str())
         .unwrap_or_default()
         .to_string()
 }
+
+pub fn escape_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for ch in input.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Parses a unix timestamp in seconds, or an ISO-8601 style date/datetime
+/// such as `2021-03-04`, `2021-03-04T05:06:07.123` or `2021-03-04 05:06:07Z`
+/// (interpreted as UTC).
+pub fn parse_timestamp(input: &str) -> Option<i64> {
+    let input = input.trim();
+    if let Ok(secs) = input.parse::<i64>() {
+        return Some(secs);
+    }
+
+    let (date, time) = match input.find(['T', ' ']) {
+        Some(idx) => (&input[..idx], Some(&input[idx + 1..])),
+        None => (input, None),
+    };
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut seconds_of_day = 0i64;
+    if let Some(time) = time {
+        let time = time.trim_end_matches('Z');
+        let time = time.split('.').next().unwrap_or_default();
+        let mut time_parts = time.splitn(3, ':');
+        let hour: i64 = time_parts.next()?.parse().ok()?;
+        let minute: i64 = time_parts.next().unwrap_or("0").parse().ok()?;
+        let second: i64 = time_parts.next().unwrap_or("0").parse().ok()?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        seconds_of_day = hour * 3600 + minute * 60 + second;
+    }
+
+    Some(days_from_civil(year, month, day) * 86_400 + seconds_of_day)
+}
+
+// Howard Hinnant's days-from-civil: days since 1970-01-01 (proleptic Gregorian).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Indexes `files` (name and contents) as a filesystem source named `docs`
+/// under a temporary directory, returning the directory and its config.
+#[cfg(test)]
+pub(crate) fn index_fixture(files: &[(&str, &str)]) -> (tempfile::TempDir, AppConfig) {
+    let dir = tempfile::tempdir().unwrap();
+    let docs = dir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    for (name, contents) in files {
+        fs::write(docs.join(name), contents).unwrap();
+    }
+    let config: AppConfig = toml::from_str(&format!(
+        "index_dir = {:?}\n[[sources]]\ntype = \"filesystem\"\nname = \"docs\"\npath = {:?}\n",
+        dir.path().join("index"),
+        docs,
+    ))
+    .unwrap();
+    crate::indexer::index_sources(&config, false, &crate::progress::Progress::new(false)).unwrap();
+    (dir, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_exclusions_pulls_out_excluded_words() {
+        assert_eq!(
+            split_exclusions("kernel -windows"),
+            ("kernel ".to_string(), vec!["windows".to_string()])
+        );
+        assert_eq!(
+            split_exclusions("-windows kernel -macos panic"),
+            (
+                " kernel  panic".to_string(),
+                vec!["windows".to_string(), "macos".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn split_exclusions_pulls_out_excluded_phrases() {
+        assert_eq!(
+            split_exclusions(r#"crash -"blue screen""#),
+            ("crash ".to_string(), vec![r#""blue screen""#.to_string()])
+        );
+        assert_eq!(
+            split_exclusions(r#"-"blue screen""#),
+            (String::new(), vec![r#""blue screen""#.to_string()])
+        );
+    }
+
+    #[test]
+    fn split_exclusions_keeps_other_dashes() {
+        assert_eq!(split_exclusions("-"), ("-".to_string(), Vec::new()));
+        assert_eq!(
+            split_exclusions("kernel - panic"),
+            ("kernel - panic".to_string(), Vec::new())
+        );
+        assert_eq!(
+            split_exclusions("x-ray film"),
+            ("x-ray film".to_string(), Vec::new())
+        );
+        assert_eq!(
+            split_exclusions("NOT -windows (linux -bsd)"),
+            ("NOT -windows (linux -bsd)".to_string(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn excluded_words_drop_matching_documents() {
+        let (_dir, config) = index_fixture(&[
+            ("linux.txt", "kernel panic on linux"),
+            ("windows.txt", "kernel panic on windows"),
+            ("bsd.txt", "kernel tuning for bsd"),
+        ]);
+        let engine = SearchEngine::open(&config).unwrap();
+
+        let titles = |query: &str, mode: QueryMode| {
+            let result = engine
+                .search(
+                    query,
+                    10,
+                    0,
+                    &SearchFilters::default(),
+                    mode,
+                    false,
+                    SortOrder::Title,
+                )
+                .unwrap();
+            result
+                .hits
+                .into_iter()
+                .map(|hit| hit.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            titles("kernel", QueryMode::Any),
+            ["bsd", "linux", "windows"]
+        );
+        assert_eq!(titles("kernel -windows", QueryMode::Any), ["bsd", "linux"]);
+        assert_eq!(titles("kernel -windows", QueryMode::All), ["bsd", "linux"]);
+        assert_eq!(titles("panic -windows -bsd", QueryMode::Any), ["linux"]);
+        assert_eq!(titles(r#"kernel -"panic on""#, QueryMode::Any), ["bsd"]);
+    }
+}
@@ -1,290 +1,2602 @@
-use anyhow::{Context, Result};
-use axum::extract::{Query, State};
-use axum::http::{header, HeaderValue, Method, StatusCode};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use axum::middleware;
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
+use axum_extra::extract::Query;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
+
+use crate::auth::{is_answer_uri, require_api_token, ApiAuth};
+use crate::cache::TtlCache;
+use crate::config::{AppConfig, RerankRule, SourceConfig, SourceRanking, DEFAULT_INDEX};
+use crate::embeddings::EmbeddingStore;
+use crate::indexer;
+use crate::kiwix::{self, KiwixClient};
+use crate::ollama::OllamaClient;
+use crate::query_log::{QueryLog, QueryLogEntry};
+use crate::rate_limit::{limit_requests, RateLimiter};
+use crate::search::{
+    self, parse_timestamp, IndexStatistics, IndexedDocument, QueryMode, SearchEngine,
+    SearchFilters, SearchHit, SortOrder,
+};
+
+const EMBED_JS: &str = include_str!("static/bunker-search.js");
+
+const OPENAPI_PATH: &str = "/api/openapi.json";
+
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// How long a `/healthz?deep=true` result is served before checking again.
+const DEEP_HEALTH_TTL: Duration = Duration::from_secs(5);
+
+// Histogram buckets (seconds) for the per-stage latency metrics.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+// Cosine similarity is in [-1, 1]; scale it into the range of the keyword
+// scores it gets merged with before reranking.
+const SEMANTIC_SCORE_SCALE: f32 = 100.0;
+
+// `Cache-Control` max-age of the widget script under its content-hashed
+// name, which never serves anything else.
+const EMBED_JS_MAX_AGE_SECS: u64 = 365 * 24 * 3600;
+
+// Stable name of the widget script, redirecting to the hashed one.
+const EMBED_JS_STABLE_FILE: &str = "bunker-search.js";
+
+// Placeholder in the widget script replaced by `EmbedParams` defaults.
+const EMBED_JS_DEFAULTS: &str = "const defaults = {};";
+
+static EMBED_JS_ETAG: Lazy<String> =
+    Lazy::new(|| format!("\"{}\"", &blake3::hash(EMBED_JS.as_bytes()).to_hex()[..32]));
+
+/// `bunker-search.<hash>.js`, the name the widget script is served under.
+static EMBED_JS_FILE: Lazy<String> = Lazy::new(|| {
+    format!(
+        "bunker-search.{}.js",
+        &blake3::hash(EMBED_JS.as_bytes()).to_hex()[..16]
+    )
+});
+
+const KIWIX_SORT_NOTE: &str =
+    "Kiwix results have no date or sort key; they are listed after the sorted local results";
+
+#[derive(Clone)]
+struct AppState {
+    indexes: Arc<BTreeMap<String, LocalIndex>>,
+    /// Index searched when a request doesn't name one.
+    default_index: String,
+    kiwix: Option<KiwixClient>,
+    ollama: Option<OllamaClient>,
+    default_limit: usize,
+    max_limit: usize,
+    query_timeout: Duration,
+    did_you_mean_below_hits: usize,
+    min_score: f32,
+    min_score_ratio: f32,
+    rerank: bool,
+    sources: Vec<String>,
+    metrics: Option<PrometheusHandle>,
+    cache: Option<Arc<TtlCache<SearchCacheKey, SearchResponse>>>,
+    /// `max-age` of the `Cache-Control` header on searches and sources.
+    http_cache_max_age_secs: u64,
+    query_log: Option<QueryLog>,
+    /// Last `/healthz?deep=true` result and when it was checked.
+    deep_health: Arc<tokio::sync::Mutex<Option<(Instant, HealthResponse)>>>,
+}
+
+/// One opened local index with its embeddings and source names.
+struct LocalIndex {
+    engine: Arc<SearchEngine>,
+    embeddings: Option<Arc<EmbeddingStore>>,
+    sources: Vec<String>,
+    /// Per-source `weight`, `max_results` and `rerank_rules`, by source name
+    /// (Kiwix collections as `kiwix:<id>`).
+    rankings: HashMap<String, SourceRanking>,
+    /// `source_priority` from the config, breaking score ties.
+    source_priority: Vec<String>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "bunker-search",
+        description = "Local full-text search for offline datasets"
+    ),
+    paths(
+        search_handler,
+        source_search_handler,
+        sources_handler,
+        suggest_handler,
+        related_handler,
+        document_handler,
+        browse_handler,
+        tags_handler,
+        stats_handler,
+        healthz
+    ),
+    components(schemas(QueryMode, SortOrder, ResponseFormat))
+)]
+struct ApiDoc;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchParams {
+    /// Query text; `title:`, `body:`, `source:` and `location:` prefixes
+    /// scope a word in `any`/`all` mode.
+    q: Option<String>,
+    /// Local index to search (default: the first configured).
+    index: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    /// Restrict to these source names (or `kiwix`); repeat the parameter
+    /// or separate names with commas to match any of them.
+    #[serde(default)]
+    source: Vec<String>,
+    /// Add an Ollama answer built from the returned hits.
+    answer: Option<bool>,
+    /// Merge embedding-similarity hits with the keyword hits.
+    semantic: Option<bool>,
+    mode: Option<QueryMode>,
+    /// Treat `q` as a regex matched against whole indexed terms.
+    regex: Option<bool>,
+    /// Also match words a typo or two away from the query terms.
+    fuzzy: Option<bool>,
+    /// Order of local hits (default `relevance`); Kiwix hits follow the
+    /// local ones under the other orders.
+    sort: Option<SortOrder>,
+    /// Inclusive lower date bound (unix seconds or `YYYY-MM-DD`).
+    after: Option<String>,
+    /// Exclusive upper date bound (unix seconds or `YYYY-MM-DD`).
+    before: Option<String>,
+    /// Detected document language as an ISO 639-3 code (`eng`, `fra`, ...),
+    /// or `und` for undetermined; excludes Kiwix hits.
+    lang: Option<String>,
+    /// Only documents carrying this tag; excludes Kiwix hits.
+    tag: Option<String>,
+    /// Drop local hits scoring below this after reranking (default
+    /// `min_score` from the config).
+    min_score: Option<f32>,
+    /// Drop hits scoring below this fraction (0-1) of the top hit's score
+    /// after reranking; Kiwix hits are measured against the top Kiwix hit.
+    min_score_ratio: Option<f32>,
+    /// Add title, coverage and per-source boosts and penalties to the index
+    /// scores (default `rerank` from the config); `false` keeps the index
+    /// order, Kiwix hits scaled to the local scores.
+    rerank: Option<bool>,
+    /// Include match counts per source and per tag.
+    facets: Option<bool>,
+    /// Attach each hit's score breakdown as `explanation`.
+    explain: Option<bool>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    /// `ndjson` streams one JSON line per hit after a metadata line; also
+    /// chosen by `Accept: application/x-ndjson`.
+    format: Option<ResponseFormat>,
+}
+
+/// Body format of `/api/search`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ResponseFormat {
+    Json,
+    Ndjson,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SuggestParams {
+    /// Title prefix typed so far.
+    q: Option<String>,
+    index: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Widget defaults baked into `/embed/bunker-search.js`; the script tag's
+/// data attributes of the same names override them.
+#[derive(Debug, Deserialize, Serialize)]
+struct EmbedParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    answer: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fuzzy: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiInfo {
+    service: &'static str,
+    docs: &'static str,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct SourcesResponse {
+    sources: Vec<String>,
+    /// Local source names by the index they belong to.
+    indexes: BTreeMap<String, Vec<String>>,
+    default_index: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct SuggestResponse {
+    suggestions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RelatedParams {
+    doc_id: Option<String>,
+    index: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RelatedResponse {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DocumentParams {
+    doc_id: Option<String>,
+    index: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BrowseParams {
+    /// Local source to list (required).
+    source: Option<String>,
+    index: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BrowseResponse {
+    source: String,
+    /// Documents of the source.
+    total_hits: usize,
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TagsParams {
+    index: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TagsResponse {
+    /// Number of documents carrying each tag, by tag.
+    tags: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StatsParams {
+    index: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct StatsResponse {
+    index: String,
+    /// An `index` run (or other writer) currently holds the index's write
+    /// lock; its changes show up here as it commits.
+    indexing: bool,
+    #[serde(flatten)]
+    statistics: IndexStatistics,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HealthzParams {
+    /// Check every index, Kiwix and Ollama instead of just answering.
+    deep: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct HealthResponse {
+    /// `ok`, `degraded` (an optional component is down) or `down` (a
+    /// required one is).
+    status: &'static str,
+    /// By component: `index:<name>` for each local index, `kiwix` and
+    /// `ollama` when configured.
+    components: BTreeMap<String, ComponentHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct ComponentHealth {
+    ok: bool,
+    /// A required component being down makes the check fail with 503.
+    required: bool,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct SearchResponse {
+    total_hits: usize,
+    hits: Vec<SearchHit>,
+    answer: Option<String>,
+    /// Spelling-corrected query, offered when the search found few hits.
+    suggestion: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facets: Option<BTreeMap<String, usize>>,
+    /// With `facets=true`: local matches per tag.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag_facets: Option<BTreeMap<String, usize>>,
+    next_cursor: Option<String>,
+    /// Set when a stage hit `query_timeout_ms` and its results are missing.
+    partial: bool,
+    /// Explains where unsorted Kiwix hits went under a date or title sort.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort_note: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ApiErrorBody {
+    error: String,
+}
+
+struct ApiError {
+    status: StatusCode,
+    error: anyhow::Error,
+}
+
+impl ApiError {
+    fn not_found(error: anyhow::Error) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            error,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(ApiErrorBody {
+                error: self.error.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+impl<E> From<E> for ApiError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(value: E) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            error: value.into(),
+        }
+    }
+}
+
+pub async fn serve(config: AppConfig) -> Result<()> {
+    let cors = build_cors(&config)?;
+
+    let app_state = app_state(&config).await?;
+
+    let mut app = Router::new()
+        .route("/", get(api_info))
+        .route("/healthz", get(healthz))
+        .route("/api/search", get(search_handler))
+        .route("/api/search/source/:name", get(source_search_handler))
+        .route("/api/sources", get(sources_handler))
+        .route("/api/suggest", get(suggest_handler))
+        .route("/api/related", get(related_handler))
+        .route("/api/document", get(document_handler))
+        .route("/api/browse", get(browse_handler))
+        .route("/api/tags", get(tags_handler))
+        .route("/api/stats", get(stats_handler))
+        .route("/api/answer/stream", get(answer_stream_handler))
+        .route(OPENAPI_PATH, get(openapi_handler))
+        .route("/embed/:file", get(embed_js));
+    if app_state.metrics.is_some() {
+        app = app.route("/metrics", get(metrics_handler));
+    }
+    if config.api_docs_enabled {
+        app = app.merge(SwaggerUi::new("/api/docs").config(SwaggerConfig::from(OPENAPI_PATH)));
+    }
+    let auth = config
+        .api_token
+        .as_ref()
+        .map(|token| Arc::new(ApiAuth::new(token.clone(), config.require_auth_for_search)));
+    let limiter = (config.rate_limit_requests_per_minute > 0).then(|| {
+        Arc::new(RateLimiter::new(
+            config.rate_limit_requests_per_minute,
+            config.rate_limit_burst,
+            config.rate_limit_answer_cost,
+            config.trusted_proxy,
+        ))
+    });
+    let guard = |router: Router<AppState>| {
+        let mut router = router.with_state(app_state.clone());
+        if let Some(auth) = &auth {
+            router = router.layer(middleware::from_fn_with_state(
+                auth.clone(),
+                require_api_token,
+            ));
+        }
+        if let Some(limiter) = &limiter {
+            router = router.layer(middleware::from_fn_with_state(
+                limiter.clone(),
+                limit_requests,
+            ));
+        }
+        router
+    };
+    // CORS is outermost, so preflight `OPTIONS` requests are answered before
+    // auth and rate limiting see them.
+    let app = guard(app).layer(cors);
+
+    let listener = tokio::net::TcpListener::bind(&config.bind)
+        .await
+        .with_context(|| format!("failed to bind {}", config.bind))?;
+
+    tracing::info!(
+        bind = %config.bind,
+        embed_js = %format!("/embed/{}", *EMBED_JS_FILE),
+        "search API listening"
+    );
+
+    // Peer addresses are needed to rate-limit by client IP.
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .context("HTTP server failed")?;
+
+    Ok(())
+}
+
+/// Runs searches through the `/api/search` pipeline (reranking, Kiwix,
+/// score thresholds) without the HTTP layer, response cache or query log,
+/// for `bench`.
+pub struct SearchPipeline {
+    state: AppState,
+}
+
+impl SearchPipeline {
+    pub async fn new(config: &AppConfig) -> Result<Self> {
+        let config = AppConfig {
+            cache_enabled: false,
+            metrics_enabled: false,
+            query_log: None,
+            ..config.clone()
+        };
+        Ok(Self {
+            state: app_state(&config).await?,
+        })
+    }
+
+    /// Total hits of `query` on `index` (the default one when `None`) and
+    /// the doc ids of its first `limit` hits.
+    pub async fn search(
+        &self,
+        query: &str,
+        index: Option<&str>,
+        limit: usize,
+    ) -> Result<(usize, Vec<String>)> {
+        let params = SearchParams {
+            q: Some(query.to_string()),
+            index: index.map(str::to_string),
+            limit: Some(limit),
+            ..SearchParams::default()
+        };
+        let response = search_response(&self.state, &params)
+            .await
+            .map_err(|err| err.error)?;
+        let doc_ids = response.hits.into_iter().map(|hit| hit.doc_id).collect();
+        Ok((response.total_hits, doc_ids))
+    }
+}
+
+/// Opens the indexes and clients `config` asks for.
+async fn app_state(config: &AppConfig) -> Result<AppState> {
+    let kiwix = if let Some(kiwix_config) = config.kiwix.clone() {
+        let client = KiwixClient::from_config(kiwix_config)
+            .await
+            .context("failed to initialize Kiwix integration")?;
+        tracing::info!(
+            collections = client.collection_count(),
+            "Kiwix integration enabled"
+        );
+        Some(client)
+    } else {
+        None
+    };
+
+    let ollama = if let Some(ollama_config) = config.ollama.clone() {
+        Some(
+            OllamaClient::from_config(ollama_config)
+                .context("failed to initialize Ollama integration")?,
+        )
+    } else {
+        None
+    };
+
+    let index_configs = config.index_configs();
+    let default_index = index_configs
+        .first()
+        .map(|(name, _)| name.clone())
+        .unwrap_or_else(|| DEFAULT_INDEX.to_string());
+
+    let mut indexes = BTreeMap::new();
+    let mut sources = Vec::new();
+    for (name, index_config) in index_configs {
+        let engine = SearchEngine::open(&index_config).with_context(|| {
+            format!(
+                "failed to open search index `{name}` at {}",
+                index_config.index_dir.display()
+            )
+        })?;
+
+        if indexer::indexing_in_progress(&index_config.index_dir) {
+            tracing::info!(
+                index = %name,
+                "an indexing run is in progress; its changes are picked up as it commits"
+            );
+        } else if indexer::last_run_interrupted(&index_config.index_dir) {
+            tracing::warn!(
+                index = %name,
+                "the last indexing run did not finish; re-run `bunker-search index` to bring the index up to date"
+            );
+        }
+
+        if search::stop_words_changed(&index_config)? {
+            tracing::warn!(
+                index = %name,
+                "stop_words changed since this index was built; run `bunker-search index --rebuild`"
+            );
+        }
+
+        let embeddings = match &ollama {
+            Some(client) if client.embedding_model().is_some() => {
+                let store = EmbeddingStore::load(&index_config.index_dir)
+                    .with_context(|| format!("failed to load document embeddings of `{name}`"))?;
+                tracing::info!(index = %name, documents = store.len(), "semantic search enabled");
+                Some(Arc::new(store))
+            }
+            _ => None,
+        };
+
+        let index_sources = collect_local_sources(&index_config.sources);
+        sources.extend(index_sources.iter().cloned());
+        indexes.insert(
+            name,
+            LocalIndex {
+                engine: Arc::new(engine),
+                embeddings,
+                sources: index_sources,
+                rankings: index_config
+                    .sources
+                    .iter()
+                    .map(|source| (source.name().to_string(), source.ranking().clone()))
+                    .chain(config.kiwix.iter().flat_map(|kiwix| {
+                        kiwix.rerank_rules.iter().map(|(collection, rules)| {
+                            let ranking = SourceRanking {
+                                rerank_rules: Some(rules.clone()),
+                                ..SourceRanking::default()
+                            };
+                            (format!("kiwix:{collection}"), ranking)
+                        })
+                    }))
+                    .collect(),
+                source_priority: config.source_priority.clone(),
+            },
+        );
+    }
+    if let Some(kiwix_client) = &kiwix {
+        sources.extend(kiwix_client.source_names());
+    }
+    sources.sort();
+    sources.dedup();
+
+    let metrics = if config.metrics_enabled {
+        Some(install_metrics_recorder()?)
+    } else {
+        None
+    };
+
+    Ok(AppState {
+        indexes: Arc::new(indexes),
+        default_index,
+        kiwix,
+        ollama,
+        default_limit: config.default_result_limit,
+        max_limit: config.max_result_limit,
+        query_timeout: Duration::from_millis(config.query_timeout_ms),
+        did_you_mean_below_hits: config.did_you_mean_below_hits,
+        min_score: config.min_score,
+        min_score_ratio: config.min_score_ratio,
+        rerank: config.rerank,
+        sources,
+        metrics,
+        cache: config.cache_enabled.then(|| {
+            Arc::new(TtlCache::new(
+                config.cache_max_entries,
+                Duration::from_secs(config.cache_ttl_secs),
+            ))
+        }),
+        http_cache_max_age_secs: config.http_cache_max_age_secs,
+        query_log: config
+            .query_log
+            .as_deref()
+            .map(QueryLog::open)
+            .transpose()?,
+        deep_health: Arc::default(),
+    })
+}
+
+async fn api_info() -> Json<ApiInfo> {
+    Json(ApiInfo {
+        service: "bunker-search",
+        docs: "GET /api/search?q=...&limit=20&source=kiwix OR source=<local>; GET /api/sources; GET /api/suggest?q=...&limit=10; GET /api/related?doc_id=...&limit=10; GET /api/document?doc_id=...; GET /api/browse?source=...&limit=20&offset=0; GET /api/tags; GET /api/stats; GET /api/answer/stream?q=... (SSE); GET /api/openapi.json",
+    })
+}
+
+async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Liveness check; with `deep=true`, a readiness check of every component.
+///
+/// The deep check runs a search on each local index and pings Kiwix and
+/// Ollama, each under `query_timeout_ms`. Indexes and Kiwix are required,
+/// since searches fail without them; Ollama only serves answers. Its result
+/// is reused for `DEEP_HEALTH_TTL`, so probes can't multiply the load on
+/// the backends.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    params(HealthzParams),
+    responses(
+        (status = 200, description = "The service is up", content(
+            (String = "text/plain"),
+            (HealthResponse = "application/json")
+        )),
+        (status = 503, description = "A required component is down", body = HealthResponse)
+    )
+)]
+async fn healthz(State(state): State<AppState>, Query(params): Query<HealthzParams>) -> Response {
+    if !params.deep.unwrap_or(false) {
+        return "ok".into_response();
+    }
+
+    // Held across the check, so concurrent probes wait for one result.
+    let mut cached = state.deep_health.lock().await;
+    let health = match &*cached {
+        Some((checked, health)) if checked.elapsed() < DEEP_HEALTH_TTL => health.clone(),
+        _ => {
+            let health = check_health(&state).await;
+            *cached = Some((Instant::now(), health.clone()));
+            health
+        }
+    };
+    drop(cached);
+
+    let code = if health.status == "down" {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (code, Json(health)).into_response()
+}
+
+async fn check_health(state: &AppState) -> HealthResponse {
+    let indexes = state.indexes.iter().map(|(name, index)| async move {
+        let check = async {
+            run_local(state, index, "health check", |engine| {
+                engine.search(
+                    "healthz",
+                    1,
+                    0,
+                    &SearchFilters::default(),
+                    QueryMode::Any,
+                    false,
+                    SortOrder::Relevance,
+                )
+            })
+            .await?
+            .ok_or_else(|| anyhow!("search timed out"))
+            .map(drop)
+        };
+        (format!("index:{name}"), check_component(true, check).await)
+    });
+    let mut components: BTreeMap<String, ComponentHealth> = futures_util::future::join_all(indexes)
+        .await
+        .into_iter()
+        .collect();
+
+    let kiwix = async {
+        match &state.kiwix {
+            Some(client) => Some(check_component(true, client.ping(state.query_timeout)).await),
+            None => None,
+        }
+    };
+    let ollama = async {
+        match &state.ollama {
+            Some(client) => Some(check_component(false, client.ping(state.query_timeout)).await),
+            None => None,
+        }
+    };
+    let (kiwix, ollama) = tokio::join!(kiwix, ollama);
+    components.extend(kiwix.map(|health| ("kiwix".to_string(), health)));
+    components.extend(ollama.map(|health| ("ollama".to_string(), health)));
+
+    let down = components.values().filter(|health| !health.ok);
+    let status = if down.clone().any(|health| health.required) {
+        "down"
+    } else if down.count() > 0 {
+        "degraded"
+    } else {
+        "ok"
+    };
+    for (name, health) in &components {
+        if let Some(error) = &health.error {
+            tracing::warn!(component = %name, error = %error, "health check failed");
+        }
+    }
+    HealthResponse { status, components }
+}
+
+async fn check_component(
+    required: bool,
+    check: impl Future<Output = Result<()>>,
+) -> ComponentHealth {
+    let started = Instant::now();
+    let result = check.await;
+    ComponentHealth {
+        ok: result.is_ok(),
+        required,
+        latency_ms: started.elapsed().as_millis() as u64,
+        error: result.err().map(|err| format!("{err:#}")),
+    }
+}
+
+fn install_metrics_recorder() -> Result<PrometheusHandle> {
+    let recorder = PrometheusBuilder::new()
+        .set_buckets(LATENCY_BUCKETS)
+        .context("invalid metrics histogram buckets")?
+        .build_recorder();
+    let handle = recorder.handle();
+    metrics::set_global_recorder(recorder)
+        .map_err(|err| anyhow!("failed to install metrics recorder: {err}"))?;
+    tracing::info!("Prometheus metrics enabled at /metrics");
+    Ok(handle)
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    let Some(handle) = &state.metrics else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    for (name, index) in state.indexes.iter() {
+        let engine = Arc::clone(&index.engine);
+        match tokio::task::spawn_blocking(move || engine.num_docs()).await {
+            Ok(Ok(documents)) => {
+                metrics::gauge!("bunker_search_index_documents", "index" => name.clone())
+                    .set(documents as f64);
+            }
+            Ok(Err(err)) => {
+                tracing::warn!(index = %name, error = %err, "failed to count index documents")
+            }
+            Err(err) => {
+                tracing::warn!(index = %name, error = %err, "failed to count index documents")
+            }
+        }
+    }
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        handle.render(),
+    )
+        .into_response()
+}
+
+/// Label for the searches-by-source counter. Unknown filters share one
+/// label so arbitrary query strings can't blow up the series count.
+fn source_label(state: &AppState, sources: &[&str]) -> String {
+    match sources {
+        [] => "all".to_string(),
+        ["kiwix"] => "kiwix".to_string(),
+        [source] if state.sources.iter().any(|known| known == source) => source.to_string(),
+        [_] => "unknown".to_string(),
+        _ => "multiple".to_string(),
+    }
+}
+
+/// Source names that can be used as `source=` filters.
+#[utoipa::path(
+    get,
+    path = "/api/sources",
+    responses((status = 200, body = SourcesResponse))
+)]
+async fn sources_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let cache_control = cache_control(state.http_cache_max_age_secs, true);
+    let sources = Json(SourcesResponse {
+        sources: state.sources,
+        indexes: state
+            .indexes
+            .iter()
+            .map(|(name, index)| (name.clone(), index.sources.clone()))
+            .collect(),
+        default_index: state.default_index,
+    });
+    ([(header::CACHE_CONTROL, cache_control)], sources)
+}
+
+/// Title completions for a partial query, local titles first.
+#[utoipa::path(
+    get,
+    path = "/api/suggest",
+    params(SuggestParams),
+    responses(
+        (status = 200, body = SuggestResponse),
+        (status = 400, body = ApiErrorBody)
+    )
+)]
+async fn suggest_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SuggestParams>,
+) -> Result<Json<SuggestResponse>, ApiError> {
+    let limit = params.limit.unwrap_or(10).clamp(1, state.max_limit);
+    let query = params.q.unwrap_or_default();
+
+    let (_, index) = state.local_index(params.index.as_deref())?;
+    let mut suggestions = index
+        .engine
+        .suggest(&query, limit)
+        .context("suggest query failed")?;
+
+    // Kiwix titles follow the local ones, in collection order.
+    if let Some(kiwix_client) = &state.kiwix {
+        if suggestions.len() < limit {
+            let mut seen: HashSet<String> = suggestions
+                .iter()
+                .map(|suggestion| suggestion.to_lowercase())
+                .collect();
+            for title in kiwix_client
+                .suggest(&query, limit, state.query_timeout)
+                .await
+            {
+                if suggestions.len() >= limit {
+                    break;
+                }
+                if seen.insert(title.to_lowercase()) {
+                    suggestions.push(title);
+                }
+            }
+        }
+    }
+
+    Ok(Json(SuggestResponse { suggestions }))
+}
+
+/// Documents similar to an indexed one.
+#[utoipa::path(
+    get,
+    path = "/api/related",
+    params(RelatedParams),
+    responses(
+        (status = 200, body = RelatedResponse),
+        (status = 400, body = ApiErrorBody),
+        (status = 404, description = "Unknown `doc_id`", body = ApiErrorBody)
+    )
+)]
+async fn related_handler(
+    State(state): State<AppState>,
+    Query(params): Query<RelatedParams>,
+) -> Result<Json<RelatedResponse>, ApiError> {
+    let doc_id = params
+        .doc_id
+        .map(|doc_id| doc_id.trim().to_string())
+        .filter(|doc_id| !doc_id.is_empty())
+        .ok_or_else(|| anyhow!("doc_id is required"))?;
+    let limit = params.limit.unwrap_or(10).clamp(1, state.max_limit);
+    let (_, index) = state.local_index(params.index.as_deref())?;
+
+    let lookup_id = doc_id.clone();
+    let related = run_local(&state, index, "related documents", move |engine| {
+        engine
+            .more_like_this(&lookup_id, limit)
+            .context("related documents query failed")
+    })
+    .await?
+    .ok_or_else(|| anyhow!("related documents query timed out"))?;
+
+    match related {
+        Some(hits) => Ok(Json(RelatedResponse { hits })),
+        None => Err(ApiError::not_found(anyhow!("unknown doc_id `{doc_id}`"))),
+    }
+}
+
+/// The stored fields of one local document, including its full body.
+#[utoipa::path(
+    get,
+    path = "/api/document",
+    params(DocumentParams),
+    responses(
+        (status = 200, body = IndexedDocument),
+        (status = 400, body = ApiErrorBody),
+        (status = 404, description = "Unknown `doc_id`", body = ApiErrorBody)
+    )
+)]
+async fn document_handler(
+    State(state): State<AppState>,
+    Query(params): Query<DocumentParams>,
+) -> Result<Json<IndexedDocument>, ApiError> {
+    let doc_id = params
+        .doc_id
+        .map(|doc_id| doc_id.trim().to_string())
+        .filter(|doc_id| !doc_id.is_empty())
+        .ok_or_else(|| anyhow!("doc_id is required"))?;
+    let (_, index) = state.local_index(params.index.as_deref())?;
+
+    let lookup_id = doc_id.clone();
+    let document = run_local(&state, index, "document lookup", move |engine| {
+        engine
+            .document(&lookup_id)
+            .context("document lookup failed")
+    })
+    .await?
+    .ok_or_else(|| anyhow!("document lookup timed out"))?;
+
+    document
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(anyhow!("unknown doc_id `{doc_id}`")))
+}
+
+/// The documents of one local source ordered by title, for a table of
+/// contents. Listing every source at once is refused, as it is unbounded.
+#[utoipa::path(
+    get,
+    path = "/api/browse",
+    params(BrowseParams),
+    responses(
+        (status = 200, body = BrowseResponse),
+        (status = 400, body = ApiErrorBody),
+        (status = 404, description = "Unknown `source`", body = ApiErrorBody)
+    )
+)]
+async fn browse_handler(
+    State(state): State<AppState>,
+    Query(params): Query<BrowseParams>,
+) -> Result<Json<BrowseResponse>, ApiError> {
+    let source = params
+        .source
+        .map(|source| source.trim().to_string())
+        .filter(|source| !source.is_empty())
+        .ok_or_else(|| {
+            anyhow!("source is required; browsing every source at once is not supported")
+        })?;
+    if is_kiwix_filter(&source) {
+        return Err(anyhow!("Kiwix collections cannot be browsed; pick a local source").into());
+    }
+    let (index_name, index) = state.local_index(params.index.as_deref())?;
+    if !index.sources.contains(&source) {
+        return Err(ApiError::not_found(anyhow!(
+            "unknown source `{source}` in index `{index_name}`"
+        )));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(state.default_limit)
+        .clamp(1, state.max_limit);
+    let offset = params.offset.unwrap_or(0);
+    let max_offset = max_window(state.max_limit, limit) - limit;
+    if offset > max_offset {
+        return Err(anyhow!("offset may be at most {max_offset}").into());
+    }
+
+    let browsed = source.clone();
+    let result = run_local(&state, index, "browse", move |engine| {
+        engine
+            .browse(&browsed, limit, offset)
+            .context("browse query failed")
+    })
+    .await?
+    .ok_or_else(|| anyhow!("browse query timed out"))?;
+
+    Ok(Json(BrowseResponse {
+        source,
+        total_hits: result.total_hits,
+        hits: result.hits,
+    }))
+}
+
+/// Every tag of a local index with its document count.
+#[utoipa::path(
+    get,
+    path = "/api/tags",
+    params(TagsParams),
+    responses(
+        (status = 200, body = TagsResponse),
+        (status = 400, body = ApiErrorBody)
+    )
+)]
+async fn tags_handler(
+    State(state): State<AppState>,
+    Query(params): Query<TagsParams>,
+) -> Result<Json<TagsResponse>, ApiError> {
+    let (_, index) = state.local_index(params.index.as_deref())?;
+    let tags = run_local(&state, index, "tag counts", |engine| {
+        engine.all_tag_counts().context("failed to count tags")
+    })
+    .await?
+    .ok_or_else(|| anyhow!("tag counts timed out"))?;
+
+    Ok(Json(TagsResponse { tags }))
+}
+
+/// Document counts, size and schema of a local index.
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    params(StatsParams),
+    responses(
+        (status = 200, body = StatsResponse),
+        (status = 400, body = ApiErrorBody)
+    )
+)]
+async fn stats_handler(
+    State(state): State<AppState>,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<StatsResponse>, ApiError> {
+    let (index_name, index) = state.local_index(params.index.as_deref())?;
+    let index_name = index_name.to_string();
+    let indexing = indexer::indexing_in_progress(index.engine.index_dir());
+
+    let statistics = run_local(&state, index, "index statistics", |engine| {
+        engine
+            .statistics()
+            .context("failed to collect index statistics")
+    })
+    .await?
+    .ok_or_else(|| anyhow!("index statistics timed out"))?;
+
+    Ok(Json(StatsResponse {
+        index: index_name,
+        indexing,
+        statistics,
+    }))
+}
+
+/// `/api/search` restricted to the source (or `kiwix:<id>` collection) named
+/// in the path, which overrides any `source` parameter. Unknown names give an
+/// empty result.
+#[utoipa::path(
+    get,
+    path = "/api/search/source/{name}",
+    params(
+        ("name" = String, Path, description = "Source name, `kiwix` or `kiwix:<collection>`"),
+        SearchParams
+    ),
+    responses(
+        (status = 200, content(
+            (SearchResponse = "application/json"),
+            (String = NDJSON_CONTENT_TYPE)
+        )),
+        (status = 400, body = ApiErrorBody)
+    )
+)]
+async fn source_search_handler(
+    state: State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Query(mut params): Query<SearchParams>,
+) -> Result<Response, ApiError> {
+    params.source = vec![name];
+    search_handler(state, headers, Query(params)).await
+}
+
+/// Searches the local index and Kiwix, optionally with an Ollama answer.
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(SearchParams),
+    responses(
+        (status = 200, content(
+            (SearchResponse = "application/json"),
+            (String = NDJSON_CONTENT_TYPE)
+        )),
+        (status = 400, body = ApiErrorBody)
+    )
+)]
+async fn search_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, ApiError> {
+    let started = Instant::now();
+    let response = search_response(&state, &params).await?;
+    if let Some(query_log) = &state.query_log {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        query_log.record(QueryLogEntry {
+            timestamp: now,
+            index: state.local_index(params.index.as_deref())?.0.to_string(),
+            query: params.q.clone().unwrap_or_default(),
+            sources: params.sources().into_iter().map(str::to_string).collect(),
+            total_hits: response.total_hits,
+            rerank: params.rerank.unwrap_or(state.rerank),
+            latency_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+    let ndjson = match params.format {
+        Some(format) => format == ResponseFormat::Ndjson,
+        None => accepts_ndjson(&headers),
+    };
+    // Timed-out stages are retried rather than cached, as in the server
+    // cache; answers to authorized requests stay out of shared caches.
+    let cache_control = if response.partial {
+        "no-store".to_string()
+    } else {
+        cache_control(
+            state.http_cache_max_age_secs,
+            !headers.contains_key(header::AUTHORIZATION),
+        )
+    };
+    let cache_headers = [
+        (header::CACHE_CONTROL, cache_control),
+        (header::VARY, header::ACCEPT.to_string()),
+    ];
+    if ndjson {
+        return Ok((cache_headers, ndjson_response(response)?).into_response());
+    }
+
+    let body = serde_json::to_vec(&response).context("failed to serialize search response")?;
+    let (_, index) = state.local_index(params.index.as_deref())?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(index.engine.commit_generation().as_bytes());
+    hasher.update(&body);
+    let etag = format!("\"{}\"", &hasher.finalize().to_hex()[..32]);
+    if etag_matches(&headers, &etag) {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            cache_headers,
+            [(header::ETAG, etag)],
+        )
+            .into_response());
+    }
+    Ok((
+        cache_headers,
+        [
+            (header::ETAG, etag),
+            (header::CONTENT_TYPE, "application/json".to_string()),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// `Cache-Control` value allowing caches to reuse a response for
+/// `max_age_secs`; 0 makes them revalidate every time.
+fn cache_control(max_age_secs: u64, shared: bool) -> String {
+    let scope = if shared { "public" } else { "private" };
+    match max_age_secs {
+        0 => format!("{scope}, no-cache"),
+        max_age => format!("{scope}, max-age={max_age}"),
+    }
+}
+
+/// Whether `If-None-Match` names `etag` (weakly compared) or is `*`.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Whether the `Accept` header asks for NDJSON.
+fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| media.split(';').next().unwrap_or_default().trim() == NDJSON_CONTENT_TYPE)
+}
+
+/// Streams a search response as NDJSON: a first line with everything but
+/// the hits (`total_hits`, `next_cursor`, ...), then one line per hit,
+/// serialized as the client reads.
+fn ndjson_response(mut response: SearchResponse) -> Result<Response> {
+    let hits = std::mem::take(&mut response.hits);
+    let mut meta =
+        serde_json::to_value(&response).context("failed to serialize search metadata")?;
+    if let Some(meta) = meta.as_object_mut() {
+        meta.remove("hits");
+    }
+
+    let lines = std::iter::once(serde_json::to_vec(&meta))
+        .chain(hits.into_iter().map(|hit| serde_json::to_vec(&hit)))
+        .map(|line| {
+            line.map(|mut line| {
+                line.push(b'\n');
+                line
+            })
+        });
+    Ok((
+        [(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        Body::from_stream(stream::iter(lines)),
+    )
+        .into_response())
+}
+
+async fn search_response(
+    state: &AppState,
+    params: &SearchParams,
+) -> Result<SearchResponse, ApiError> {
+    let request = params.to_request(state)?;
+    let want_answer = params.answer.unwrap_or(false);
+
+    let cache_key = state.cache.as_ref().map(|_| SearchCacheKey {
+        index: request.index_name.to_string(),
+        query: request.query.to_string(),
+        source: request.source_key(),
+        limit: request.limit,
+        offset: request.offset,
+        cursor: params.cursor.clone().filter(|raw| !raw.trim().is_empty()),
+        semantic: request.semantic,
+        mode: request.mode,
+        fuzzy: request.fuzzy,
+        sort: request.sort,
+        after: request.after,
+        before: request.before,
+        lang: request.lang.clone(),
+        tag: request.tag.clone(),
+        min_score: request.min_score.to_bits(),
+        min_score_ratio: request.min_score_ratio.to_bits(),
+        rerank: request.rerank,
+        facets: request.facets,
+        explain: request.explain,
+        answer: want_answer,
+    });
+    if let (Some(cache), Some(key)) = (&state.cache, &cache_key) {
+        if let Some(response) = cache.get(key) {
+            metrics::counter!("bunker_search_cache_hits_total").increment(1);
+            return Ok(response);
+        }
+    }
+
+    let MergedHits {
+        total_hits,
+        hits: paged_hits,
+        facets,
+        tag_facets,
+        next_cursor,
+        mut partial,
+    } = merged_hits(state, &request).await?;
+
+    let suggestion = if total_hits < state.did_you_mean_below_hits
+        && request.searches_local()
+        && request.mode != QueryMode::Regex
+    {
+        let query = request.query.to_string();
+        let suggestion = run_local(state, request.index, "did-you-mean", move |engine| {
+            engine
+                .did_you_mean(&query)
+                .context("did-you-mean lookup failed")
+        })
+        .await?;
+        partial |= suggestion.is_none();
+        suggestion.flatten()
+    } else {
+        None
+    };
+
+    let answer = if want_answer {
+        if let Some(ollama_client) = &state.ollama {
+            let started = Instant::now();
+            let generated = within_timeout(
+                state,
+                "ollama answer",
+                ollama_client.synthesize_answer(request.query, &paged_hits),
+            )
+            .await
+            .transpose()
+            .context("failed generating answer from Ollama")?;
+            metrics::histogram!("bunker_search_ollama_answer_seconds").record(started.elapsed());
+            partial |= generated.is_none();
+            generated.filter(|generated| !generated.is_empty())
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let sort_note = (request.sort != SortOrder::Relevance && request.searches_kiwix(state))
+        .then(|| KIWIX_SORT_NOTE.to_string());
+
+    let response = SearchResponse {
+        total_hits,
+        hits: paged_hits,
+        answer,
+        suggestion,
+        facets,
+        tag_facets,
+        next_cursor,
+        partial,
+        sort_note,
+    };
+
+    // Partial responses are not cached, so a timed-out stage is retried.
+    if let (Some(cache), Some(key)) = (&state.cache, cache_key) {
+        if !response.partial {
+            cache.insert(key, response.clone());
+        }
+    }
+
+    Ok(response)
+}
+
+/// Everything that shapes a `/api/search` response; identical requests are
+/// answered from the cache until their entry expires.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SearchCacheKey {
+    index: String,
+    query: String,
+    source: Option<String>,
+    limit: usize,
+    offset: usize,
+    cursor: Option<String>,
+    semantic: bool,
+    mode: QueryMode,
+    fuzzy: bool,
+    sort: SortOrder,
+    after: Option<i64>,
+    before: Option<i64>,
+    lang: Option<String>,
+    tag: Option<String>,
+    /// `f32::to_bits` of the thresholds, which aren't `Eq`.
+    min_score: u32,
+    min_score_ratio: u32,
+    rerank: bool,
+    facets: bool,
+    explain: bool,
+    answer: bool,
+}
+
+/// A validated `/api/search`-style request.
+struct SearchRequest<'a> {
+    index_name: &'a str,
+    index: &'a LocalIndex,
+    query: &'a str,
+    /// Source filters; a hit from any of them matches.
+    sources: Vec<&'a str>,
+    /// The `sources` that name local sources rather than Kiwix.
+    local_sources: Vec<String>,
+    limit: usize,
+    offset: usize,
+    semantic: bool,
+    mode: QueryMode,
+    fuzzy: bool,
+    sort: SortOrder,
+    after: Option<i64>,
+    before: Option<i64>,
+    lang: Option<String>,
+    tag: Option<String>,
+    min_score: f32,
+    min_score_ratio: f32,
+    rerank: bool,
+    facets: bool,
+    explain: bool,
+    cursor: Option<SearchCursor>,
+}
+
+/// Scores below which reranked hits are dropped, from `min_score` and
+/// `min_score_ratio`. Kiwix scores are synthetic (500 minus the rank), so
+/// Kiwix hits get a floor of their own relative to the top Kiwix hit.
+#[derive(Debug, Clone, Copy)]
+struct ScoreFloor {
+    local: f32,
+    kiwix: f32,
+}
+
+impl ScoreFloor {
+    fn accepts(&self, hit: &SearchHit) -> bool {
+        let floor = if is_kiwix_filter(&hit.source) {
+            self.kiwix
+        } else {
+            self.local
+        };
+        hit.score >= floor
+    }
+}
+
+/// The reranked page of a merged search, plus optional per-source counts.
+struct MergedHits {
+    total_hits: usize,
+    hits: Vec<SearchHit>,
+    facets: Option<BTreeMap<String, usize>>,
+    tag_facets: Option<BTreeMap<String, usize>>,
+    next_cursor: Option<String>,
+    partial: bool,
+}
+
+/// Forward-pagination state handed to clients as an opaque `next_cursor`.
+/// Resuming after the last hit (rather than at an offset) keeps pages
+/// stable when reranking or interleaved Kiwix hits shift positions.
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchCursor {
+    index: String,
+    q: String,
+    source: Option<String>,
+    #[serde(default)]
+    sort: SortOrder,
+    #[serde(default = "default_cursor_rerank")]
+    rerank: bool,
+    score: f32,
+    doc_id: String,
+    /// Candidate block holding the cursor's hit (see `CandidateBlock`).
+    block: usize,
+    /// Block size fixed by the first page, so later pages see the same blocks.
+    window: usize,
+}
+
+fn default_cursor_rerank() -> bool {
+    true
+}
+
+impl SearchCursor {
+    fn encode(&self) -> Result<String> {
+        let json = serde_json::to_vec(self).context("failed to serialize cursor")?;
+        Ok(URL_SAFE_NO_PAD.encode(json))
+    }
+
+    fn decode(raw: &str) -> Result<Self> {
+        let json = URL_SAFE_NO_PAD
+            .decode(raw.trim())
+            .map_err(|_| anyhow!("invalid cursor"))?;
+        serde_json::from_slice(&json).map_err(|_| anyhow!("invalid cursor"))
+    }
+}
+
+/// Owned copy of the local-index part of a request, for the blocking pool.
+struct LocalQuery {
+    text: String,
+    sources: Vec<String>,
+    after: Option<i64>,
+    before: Option<i64>,
+    lang: Option<String>,
+    tag: Option<String>,
+    mode: QueryMode,
+    fuzzy: bool,
+    sort: SortOrder,
+}
+
+impl LocalQuery {
+    fn filters(&self) -> SearchFilters<'_> {
+        SearchFilters {
+            sources: &self.sources,
+            after: self.after,
+            before: self.before,
+            lang: self.lang.as_deref(),
+            tag: self.tag.as_deref(),
+        }
+    }
+}
+
+impl SearchRequest<'_> {
+    fn local_query(&self) -> LocalQuery {
+        let filters = self.local_filters();
+        LocalQuery {
+            text: self.query.to_string(),
+            sources: filters.sources.to_vec(),
+            after: filters.after,
+            before: filters.before,
+            lang: filters.lang.map(str::to_string),
+            tag: filters.tag.map(str::to_string),
+            mode: self.mode,
+            fuzzy: self.fuzzy,
+            sort: self.sort,
+        }
+    }
+
+    fn searches_local(&self) -> bool {
+        self.sources.is_empty() || !self.local_sources.is_empty()
+    }
+
+    /// Kiwix collections asked for by the source filters.
+    fn kiwix_sources(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .copied()
+            .filter(|filter| is_kiwix_filter(filter))
+            .collect()
+    }
+
+    /// The source filters as one comma-separated value, for cursors and
+    /// cache keys.
+    fn source_key(&self) -> Option<String> {
+        (!self.sources.is_empty()).then(|| self.sources.join(","))
+    }
+
+    /// Kiwix's full-text search understands quoted phrases but not regexes,
+    /// and its results carry no dates, detected language or tags, so a date
+    /// range, language or tag filter excludes them.
+    fn kiwix_query(&self) -> Option<String> {
+        if (!self.sources.is_empty() && self.kiwix_sources().is_empty())
+            || self.local_filters().has_date_range()
+            || self.lang.is_some()
+            || self.tag.is_some()
+        {
+            return None;
+        }
+        match self.mode {
+            QueryMode::Phrase => Some(format!("\"{}\"", self.query.trim().replace('"', ""))),
+            QueryMode::Any | QueryMode::All => Some(self.query.to_string()),
+            QueryMode::Regex => None,
+        }
+    }
+
+    /// The score floor for hits ranked like `hits` (the reranked first
+    /// block), or `None` without thresholds or under a date or title sort,
+    /// whose scores don't order the results.
+    fn score_floor(&self, hits: &[SearchHit]) -> Option<ScoreFloor> {
+        if (self.min_score <= 0.0 && self.min_score_ratio <= 0.0)
+            || self.sort != SortOrder::Relevance
+        {
+            return None;
+        }
+        let top_score = |kiwix: bool| {
+            hits.iter()
+                .filter(|hit| is_kiwix_filter(&hit.source) == kiwix)
+                .map(|hit| hit.score)
+                .fold(0.0f32, f32::max)
+        };
+        Some(ScoreFloor {
+            local: self.min_score.max(self.min_score_ratio * top_score(false)),
+            kiwix: self.min_score_ratio * top_score(true),
+        })
+    }
+
+    fn searches_kiwix(&self, state: &AppState) -> bool {
+        state.kiwix.is_some() && self.kiwix_query().is_some()
+    }
+
+    /// Applies source weights, reranking (or, with `rerank` off, Kiwix score
+    /// normalization) and per-page source caps to the hits of a
+    /// relevance-ordered search; other orders are kept as the index returned
+    /// them.
+    fn rank_hits(&self, hits: Vec<SearchHit>) -> Vec<SearchHit> {
+        let mut hits = hits;
+        if self.sort == SortOrder::Relevance && !self.rerank {
+            normalize_kiwix_scores(&mut hits);
+        }
+        if self.explain {
+            for hit in &mut hits {
+                hit.explanation = Some(vec![("base_score".to_string(), hit.score)]);
+            }
+        }
+        if self.sort != SortOrder::Relevance {
+            return hits;
+        }
+        self.index.weigh_hits(&mut hits);
+        if self.explain {
+            for hit in &mut hits {
+                if let Some(explanation) = &mut hit.explanation {
+                    let weighted = hit.score - explanation[0].1;
+                    if weighted != 0.0 {
+                        explanation.push(("source_weight".to_string(), weighted));
+                    }
+                }
+            }
+        }
+        if self.rerank {
+            // Excluded words are absent from every hit, so only the
+            // included part of the query earns coverage and title boosts.
+            let included = match self.mode {
+                QueryMode::Any | QueryMode::All => search::split_exclusions(self.query).0,
+                QueryMode::Phrase | QueryMode::Regex => self.query.to_string(),
+            };
+            rerank_hits(&included, &mut hits, self.index);
+        } else {
+            sort_by_score(&mut hits, self.index);
+        }
+        self.index.spread_capped_sources(hits, self.limit)
+    }
+
+    /// Filters for the local index; Kiwix source filters don't apply there.
+    fn local_filters(&self) -> SearchFilters<'_> {
+        SearchFilters {
+            sources: &self.local_sources,
+            after: self.after,
+            before: self.before,
+            lang: self.lang.as_deref(),
+            tag: self.tag.as_deref(),
+        }
+    }
+}
+
+impl LocalIndex {
+    /// The reranking rules a source configured, or its defaults.
+    fn rerank_rules(&self, source: &str) -> Cow<'_, [RerankRule]> {
+        match self
+            .rankings
+            .get(source)
+            .and_then(|ranking| ranking.rerank_rules.as_deref())
+        {
+            Some(rules) => Cow::Borrowed(rules),
+            None => Cow::Owned(RerankRule::defaults_for(source)),
+        }
+    }
+
+    /// Score change that halves a dated hit's score every
+    /// `recency_half_life_days` of age; `None` for undated hits and sources
+    /// without a half-life. Future dates count as age zero.
+    fn recency_decay(&self, hit: &SearchHit, now: i64) -> Option<f32> {
+        let half_life_days = self.rankings.get(&hit.source)?.recency_half_life_days?;
+        let age_days = (now - hit.date?).max(0) as f64 / 86_400.0;
+        let factor = 0.5f64.powf(age_days / f64::from(half_life_days)) as f32;
+        Some(hit.score.max(0.0) * (factor - 1.0))
+    }
+
+    /// Position of `source` in `source_priority`, where `kiwix` stands for
+    /// the collections not listed by name; unlisted sources come last.
+    fn source_priority(&self, source: &str) -> usize {
+        let position = |name: &str| {
+            self.source_priority
+                .iter()
+                .position(|listed| listed.eq_ignore_ascii_case(name))
+        };
+        position(source)
+            .or_else(|| is_kiwix_filter(source).then(|| position("kiwix")).flatten())
+            .unwrap_or(self.source_priority.len())
+    }
+
+    /// Multiplies the score of each hit by its source's `weight`.
+    fn weigh_hits(&self, hits: &mut [SearchHit]) {
+        for hit in hits {
+            if let Some(ranking) = self.rankings.get(&hit.source) {
+                hit.score *= ranking.weight;
+            }
+        }
+    }
+
+    /// Reorders ranked hits so no page of `page_size` holds more hits of a
+    /// source than its `max_results`; hits over the cap move down to the
+    /// first later page with room for them.
+    fn spread_capped_sources(&self, hits: Vec<SearchHit>, page_size: usize) -> Vec<SearchHit> {
+        if self
+            .rankings
+            .values()
+            .all(|ranking| ranking.max_results == 0)
+        {
+            return hits;
+        }
+
+        let mut pending: VecDeque<SearchHit> = hits.into();
+        let mut ordered = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            let mut per_source: HashMap<String, usize> = HashMap::new();
+            let mut page_len = 0;
+            let mut deferred = VecDeque::new();
+            while let Some(hit) = pending.pop_front() {
+                if page_len == page_size {
+                    deferred.push_back(hit);
+                    deferred.append(&mut pending);
+                    break;
+                }
+                let cap = self
+                    .rankings
+                    .get(&hit.source)
+                    .map_or(0, |ranking| ranking.max_results);
+                let count = per_source.entry(hit.source.clone()).or_default();
+                if cap > 0 && *count >= cap {
+                    deferred.push_back(hit);
+                    continue;
+                }
+                *count += 1;
+                page_len += 1;
+                ordered.push(hit);
+            }
+            pending = deferred;
+        }
+        ordered
+    }
+}
+
+impl AppState {
+    /// The named index, or the default one when `name` is absent.
+    fn local_index<'a>(&'a self, name: Option<&'a str>) -> Result<(&'a str, &'a LocalIndex)> {
+        let name = name
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .unwrap_or(&self.default_index);
+        let index = self
+            .indexes
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown index `{name}`"))?;
+        Ok((name, index))
+    }
+}
 
-use crate::config::{AppConfig, SourceConfig};
-use crate::kiwix::KiwixClient;
-use crate::ollama::OllamaClient;
-use crate::search::{SearchEngine, SearchHit};
+impl SearchParams {
+    /// The distinct source names of the `source` filter.
+    fn sources(&self) -> Vec<&str> {
+        let mut sources: Vec<&str> = Vec::new();
+        for source in self.source.iter().flat_map(|value| value.split(',')) {
+            let source = source.trim();
+            if !source.is_empty() && !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+        sources
+    }
 
-const EMBED_JS: &str = include_str!("static/bunker-search.js");
+    fn to_request<'a>(&'a self, state: &'a AppState) -> Result<SearchRequest<'a>> {
+        let (index_name, index) = state.local_index(self.index.as_deref())?;
+        let after = parse_date_param("after", self.after.as_deref())?;
+        let before = parse_date_param("before", self.before.as_deref())?;
+
+        let query = self.q.as_deref().unwrap_or_default();
+        let sources = self.sources();
+        let source_key = (!sources.is_empty()).then(|| sources.join(","));
+
+        let cursor = match self.cursor.as_deref().filter(|raw| !raw.trim().is_empty()) {
+            Some(raw) => {
+                let cursor = SearchCursor::decode(raw)?;
+                if cursor.index != index_name
+                    || cursor.q != query
+                    || cursor.source != source_key
+                    || cursor.sort != self.sort.unwrap_or_default()
+                    || cursor.rerank != self.rerank.unwrap_or(state.rerank)
+                {
+                    bail!(
+                        "cursor does not belong to this index, query, source filter, sort and rerank"
+                    );
+                }
+                Some(cursor)
+            }
+            None => None,
+        };
 
-#[derive(Clone)]
-struct AppState {
-    engine: SearchEngine,
-    kiwix: Option<KiwixClient>,
-    ollama: Option<OllamaClient>,
-    default_limit: usize,
-    max_limit: usize,
-    sources: Vec<String>,
+        let semantic = self.semantic.unwrap_or(false);
+        let mode = if self.regex.unwrap_or(false) {
+            if semantic {
+                bail!("regex=true cannot be combined with semantic=true");
+            }
+            index.engine.check_regex(query)?;
+            QueryMode::Regex
+        } else {
+            let mode = self.mode.unwrap_or_default();
+            if mode != QueryMode::Phrase {
+                search::check_field_prefixes(query)?;
+            }
+            mode
+        };
+        let fuzzy = self.fuzzy.unwrap_or(false);
+        if fuzzy && matches!(mode, QueryMode::Phrase | QueryMode::Regex) {
+            bail!("fuzzy=true only works with mode=any or mode=all");
+        }
+        let sort = self.sort.unwrap_or_default();
+        if semantic && sort != SortOrder::Relevance {
+            bail!("semantic=true only works with sort=relevance");
+        }
+        let min_score = self.min_score.unwrap_or(state.min_score);
+        if !min_score.is_finite() || min_score < 0.0 {
+            bail!("invalid min_score={min_score}; expected a score of 0 or more");
+        }
+        let min_score_ratio = self.min_score_ratio.unwrap_or(state.min_score_ratio);
+        if !(0.0..=1.0).contains(&min_score_ratio) {
+            bail!("invalid min_score_ratio={min_score_ratio}; expected a fraction from 0 to 1");
+        }
+
+        Ok(SearchRequest {
+            index_name,
+            index,
+            query,
+            local_sources: sources
+                .iter()
+                .filter(|filter| !is_kiwix_filter(filter))
+                .map(|filter| filter.to_string())
+                .collect(),
+            sources,
+            limit: self
+                .limit
+                .unwrap_or(state.default_limit)
+                .clamp(1, state.max_limit),
+            offset: self.offset.unwrap_or(0),
+            semantic,
+            mode,
+            fuzzy,
+            sort,
+            after,
+            before,
+            lang: self
+                .lang
+                .as_deref()
+                .map(|lang| lang.trim().to_ascii_lowercase())
+                .filter(|lang| !lang.is_empty()),
+            tag: self
+                .tag
+                .as_deref()
+                .map(|tag| tag.trim().to_lowercase())
+                .filter(|tag| !tag.is_empty()),
+            min_score,
+            min_score_ratio,
+            rerank: self.rerank.unwrap_or(state.rerank),
+            facets: self.facets.unwrap_or(false),
+            explain: self.explain.unwrap_or(false),
+            cursor,
+        })
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct SearchParams {
-    q: Option<String>,
-    limit: Option<usize>,
-    offset: Option<usize>,
-    source: Option<String>,
-    answer: Option<bool>,
+fn parse_date_param(name: &str, value: Option<&str>) -> Result<Option<i64>> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => parse_timestamp(value).map(Some).ok_or_else(|| {
+            anyhow!("invalid {name}={value}; expected unix seconds or YYYY-MM-DD[THH:MM:SS]")
+        }),
+        None => Ok(None),
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct ApiInfo {
-    service: &'static str,
-    docs: &'static str,
+/// Deepest hit a request may page to: `/api/search` clamps its window to
+/// it and `/api/browse` refuses offsets past it.
+fn max_window(max_limit: usize, limit: usize) -> usize {
+    max_limit.saturating_mul(20).max(limit)
 }
 
-#[derive(Debug, Serialize)]
-struct SourcesResponse {
-    sources: Vec<String>,
+/// Runs the query against the local index and Kiwix, reranks the merged
+/// hits, and returns the total hit count plus the requested page (after the
+/// cursor if one was given, else at `offset`). Facet counts are only
+/// computed when the request asks for them.
+async fn merged_hits(state: &AppState, request: &SearchRequest<'_>) -> Result<MergedHits> {
+    metrics::counter!("bunker_search_searches_total").increment(1);
+    metrics::counter!(
+        "bunker_search_searches_by_source_total",
+        "source" => source_label(state, &request.sources)
+    )
+    .increment(1);
+
+    let SearchRequest {
+        query,
+        limit,
+        offset,
+        facets: want_facets,
+        ..
+    } = *request;
+
+    let max_window = max_window(state.max_limit, limit);
+    let window = match &request.cursor {
+        Some(cursor) => cursor.window.clamp(1, max_window),
+        None => offset
+            .saturating_add(limit)
+            .saturating_mul(3)
+            .clamp(1, max_window),
+    };
+
+    let mut first = first_block(state, request, window).await?;
+    let total_hits = first.total_hits;
+    let mut partial = first.partial;
+    let facets = want_facets.then(|| std::mem::take(&mut first.facets));
+    let tag_facets = want_facets.then(|| std::mem::take(&mut first.tag_facets));
+
+    let (mut paged_hits, last_block, has_more) = match &request.cursor {
+        Some(cursor) => {
+            let page = page_after_cursor(state, request, cursor, window, first).await?;
+            partial |= page.partial;
+            (page.hits, page.last_block, page.has_more)
+        }
+        None => {
+            let has_more = first.hits.len() > offset.saturating_add(limit) || first.local_full;
+            let page: Vec<SearchHit> = first.hits.into_iter().skip(offset).take(limit).collect();
+            (page, 0, has_more)
+        }
+    };
+
+    highlight_titles(query, &mut paged_hits);
+
+    let next_cursor = match paged_hits.last() {
+        Some(last) if has_more => Some(
+            SearchCursor {
+                index: request.index_name.to_string(),
+                q: query.to_string(),
+                source: request.source_key(),
+                sort: request.sort,
+                rerank: request.rerank,
+                score: last.score,
+                doc_id: last.doc_id.clone(),
+                block: last_block,
+                window,
+            }
+            .encode()?,
+        ),
+        _ => None,
+    };
+
+    Ok(MergedHits {
+        total_hits,
+        hits: paged_hits,
+        facets,
+        tag_facets,
+        next_cursor,
+        partial,
+    })
 }
 
-#[derive(Debug, Serialize)]
-struct SearchResponse {
+/// One reranked slice of the result order. Block 0 merges the top `window`
+/// local hits with semantic and Kiwix hits; later blocks hold the next
+/// `window` local hits each, so cursor pages never reshuffle earlier ones.
+#[derive(Default)]
+struct CandidateBlock {
     total_hits: usize,
     hits: Vec<SearchHit>,
-    answer: Option<String>,
+    /// True when the local index filled the whole window, i.e. a further
+    /// block may have more hits.
+    local_full: bool,
+    facets: BTreeMap<String, usize>,
+    tag_facets: BTreeMap<String, usize>,
+    partial: bool,
+    /// Floor set by the first block, applied to later blocks too.
+    score_floor: Option<ScoreFloor>,
+    /// Hits that belong after the last local block (Kiwix hits of a date or
+    /// title sort).
+    trailing: Vec<SearchHit>,
 }
 
-#[derive(Debug, Serialize)]
-struct ApiErrorBody {
-    error: String,
+impl CandidateBlock {
+    /// Appends the trailing hits once no local block follows this one.
+    fn close_with_trailing(&mut self) {
+        if !self.local_full {
+            self.hits.append(&mut self.trailing);
+        }
+    }
 }
 
-struct ApiError(anyhow::Error);
+async fn first_block(
+    state: &AppState,
+    request: &SearchRequest<'_>,
+    window: usize,
+) -> Result<CandidateBlock> {
+    let SearchRequest {
+        query,
+        semantic,
+        facets: want_facets,
+        ..
+    } = *request;
+
+    let mut block = CandidateBlock::default();
+
+    if request.searches_local() {
+        let local_query = request.local_query();
+        let local = run_local(state, request.index, "local search", move |engine| {
+            let filters = local_query.filters();
+            let result = engine
+                .search(
+                    &local_query.text,
+                    window,
+                    0,
+                    &filters,
+                    local_query.mode,
+                    local_query.fuzzy,
+                    local_query.sort,
+                )
+                .context("local search query failed")?;
+            let (facets, tag_facets) = if want_facets {
+                let facets = engine
+                    .source_counts(
+                        &local_query.text,
+                        &filters,
+                        local_query.mode,
+                        local_query.fuzzy,
+                    )
+                    .context("facet count query failed")?;
+                let tag_facets = engine
+                    .tag_counts(
+                        &local_query.text,
+                        &filters,
+                        local_query.mode,
+                        local_query.fuzzy,
+                    )
+                    .context("tag facet count query failed")?;
+                (facets, tag_facets)
+            } else {
+                (BTreeMap::new(), BTreeMap::new())
+            };
+            Ok((result, facets, tag_facets))
+        })
+        .await?;
+
+        match local {
+            Some((local_result, facets, tag_facets)) => {
+                block.total_hits += local_result.total_hits;
+                block.local_full = local_result.hits.len() == window;
+                block.hits.extend(local_result.hits);
+                block.facets.extend(facets);
+                block.tag_facets = tag_facets;
+            }
+            None => block.partial = true,
+        }
+
+        if semantic && !query.trim().is_empty() {
+            let (Some(ollama_client), Some(store)) = (&state.ollama, &request.index.embeddings)
+            else {
+                bail!("semantic search requires ollama.embedding_model to be configured");
+            };
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ApiErrorBody {
-                error: self.0.to_string(),
-            }),
-        )
-            .into_response()
+            let query_vector = within_timeout(state, "query embedding", ollama_client.embed(query))
+                .await
+                .transpose()
+                .context("failed to embed query")?
+                .unwrap_or_default();
+            block.partial |= query_vector.is_empty();
+            let scored_ids: Vec<(String, f32)> = store
+                .nearest(&query_vector, window)
+                .into_iter()
+                .map(|(doc_id, similarity)| (doc_id, similarity * SEMANTIC_SCORE_SCALE))
+                .collect();
+            let local_query = request.local_query();
+            let semantic_hits = run_local(state, request.index, "semantic lookup", move |engine| {
+                engine
+                    .hits_for_doc_ids(&scored_ids, &local_query.filters())
+                    .context("semantic search lookup failed")
+            })
+            .await?;
+            block.partial |= semantic_hits.is_none();
+
+            for semantic_hit in semantic_hits.unwrap_or_default() {
+                match block
+                    .hits
+                    .iter_mut()
+                    .find(|hit| hit.doc_id == semantic_hit.doc_id)
+                {
+                    Some(existing) => existing.score += semantic_hit.score,
+                    None => {
+                        block.total_hits += 1;
+                        block.hits.push(semantic_hit);
+                    }
+                }
+            }
+        }
     }
-}
 
-impl<E> From<E> for ApiError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(value: E) -> Self {
-        Self(value.into())
+    if let (Some(kiwix_client), Some(kiwix_query)) = (&state.kiwix, request.kiwix_query()) {
+        let started = Instant::now();
+        let kiwix_result = kiwix_client
+            .search(
+                &kiwix_query,
+                &request.kiwix_sources(),
+                window,
+                state.query_timeout,
+            )
+            .await
+            .context("Kiwix search failed")?;
+        metrics::histogram!("bunker_search_kiwix_seconds").record(started.elapsed());
+
+        block.partial |= kiwix_result.partial;
+        block.total_hits += kiwix_result.total_hits;
+        block.facets.extend(kiwix_result.source_totals);
+        // Kiwix hits have no date or sort key, so sorted searches list them
+        // after the last local hit.
+        if request.sort == SortOrder::Relevance {
+            block.hits.extend(kiwix_result.hits);
+        } else {
+            block.trailing = kiwix_result.hits;
+        }
     }
-}
-
-pub async fn serve(config: AppConfig) -> Result<()> {
-    let engine = SearchEngine::open(&config.index_dir).with_context(|| {
-        format!(
-            "failed to open search index at {}",
-            config.index_dir.display()
-        )
-    })?;
 
-    let kiwix = if let Some(kiwix_config) = config.kiwix.clone() {
-        let client = KiwixClient::from_config(kiwix_config)
-            .await
-            .context("failed to initialize Kiwix integration")?;
-        tracing::info!(
-            collections = client.collection_count(),
-            "Kiwix integration enabled"
-        );
-        Some(client)
-    } else {
-        None
-    };
+    block.hits = request.rank_hits(block.hits);
+    if let Some(floor) = request.score_floor(&block.hits) {
+        let ranked = block.hits.len();
+        block.hits.retain(|hit| floor.accepts(hit));
+        block.total_hits = block.total_hits.saturating_sub(ranked - block.hits.len());
+        block.score_floor = Some(floor);
+    }
+    block.close_with_trailing();
 
-    let ollama = if let Some(ollama_config) = config.ollama.clone() {
-        Some(
-            OllamaClient::from_config(ollama_config)
-                .context("failed to initialize Ollama integration")?,
-        )
-    } else {
-        None
-    };
+    Ok(block)
+}
 
-    let mut sources = collect_local_sources(&config.sources);
-    if let Some(kiwix_client) = &kiwix {
-        sources.extend(kiwix_client.source_names());
+/// Local hits ranked `block * window` onwards by tantivy, reranked. Hits
+/// already placed in block 0 (e.g. semantic matches) or under the first
+/// block's `score_floor` are dropped, and `trailing` follows the hits of the
+/// last block.
+async fn local_block(
+    state: &AppState,
+    request: &SearchRequest<'_>,
+    block: usize,
+    window: usize,
+    exclude: &HashSet<String>,
+    score_floor: Option<ScoreFloor>,
+    trailing: &[SearchHit],
+) -> Result<CandidateBlock> {
+    if !request.searches_local() {
+        return Ok(CandidateBlock::default());
     }
-    sources.sort();
-    sources.dedup();
 
-    let app_state = AppState {
-        engine,
-        kiwix,
-        ollama,
-        default_limit: config.default_result_limit,
-        max_limit: config.max_result_limit,
-        sources,
+    let local_query = request.local_query();
+    let Some(local_result) = run_local(state, request.index, "local search", move |engine| {
+        engine
+            .search(
+                &local_query.text,
+                window,
+                block.saturating_mul(window),
+                &local_query.filters(),
+                local_query.mode,
+                local_query.fuzzy,
+                local_query.sort,
+            )
+            .context("local search query failed")
+    })
+    .await?
+    else {
+        return Ok(CandidateBlock {
+            partial: true,
+            ..CandidateBlock::default()
+        });
     };
 
-    let app = Router::new()
-        .route("/", get(api_info))
-        .route("/healthz", get(healthz))
-        .route("/api/search", get(search_handler))
-        .route("/api/sources", get(sources_handler))
-        .route("/embed/bunker-search.js", get(embed_js))
-        .with_state(app_state)
-        .layer(build_cors(&config.cors_allowed_origins));
+    let local_full = local_result.hits.len() == window;
+    let hits: Vec<SearchHit> = local_result
+        .hits
+        .into_iter()
+        .filter(|hit| !exclude.contains(&hit.doc_id))
+        .collect();
 
-    let listener = tokio::net::TcpListener::bind(&config.bind)
-        .await
-        .with_context(|| format!("failed to bind {}", config.bind))?;
+    let mut hits = request.rank_hits(hits);
+    if let Some(floor) = score_floor {
+        hits.retain(|hit| floor.accepts(hit));
+    }
+    let mut candidates = CandidateBlock {
+        total_hits: local_result.total_hits,
+        hits,
+        local_full,
+        trailing: trailing.to_vec(),
+        ..CandidateBlock::default()
+    };
+    candidates.close_with_trailing();
+    Ok(candidates)
+}
 
-    tracing::info!(bind = %config.bind, "search API listening");
+/// A page read after a cursor.
+struct CursorPage {
+    hits: Vec<SearchHit>,
+    /// Block of the last hit, for the next cursor.
+    last_block: usize,
+    has_more: bool,
+    partial: bool,
+}
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .context("HTTP server failed")?;
+/// Collects the page following `cursor`, moving on to later blocks when the
+/// cursor's block runs out.
+async fn page_after_cursor(
+    state: &AppState,
+    request: &SearchRequest<'_>,
+    cursor: &SearchCursor,
+    window: usize,
+    mut first: CandidateBlock,
+) -> Result<CursorPage> {
+    let first_ids: HashSet<String> = first.hits.iter().map(|hit| hit.doc_id.clone()).collect();
+    let trailing = std::mem::take(&mut first.trailing);
+    let score_floor = first.score_floor;
+
+    let mut block = cursor.block;
+    let mut candidates = if block == 0 {
+        first
+    } else {
+        local_block(
+            state,
+            request,
+            block,
+            window,
+            &first_ids,
+            score_floor,
+            &trailing,
+        )
+        .await?
+    };
+    let mut resume_after = Some(cursor);
+    let mut last_block = block;
+    let mut partial = false;
+    let mut page = Vec::with_capacity(request.limit);
+
+    loop {
+        partial |= candidates.partial;
+        let start = resume_after
+            .take()
+            .map_or(0, |cursor| resume_position(&candidates.hits, cursor));
+        let remaining = candidates.hits.len().saturating_sub(start);
+        let room = request.limit - page.len();
+        if remaining > 0 {
+            last_block = block;
+        }
+        page.extend(candidates.hits.into_iter().skip(start).take(room));
+
+        let has_more = if remaining > room {
+            Some(true)
+        } else if !candidates.local_full {
+            Some(false)
+        } else if page.len() == request.limit {
+            Some(true)
+        } else {
+            None
+        };
+        if let Some(has_more) = has_more {
+            return Ok(CursorPage {
+                hits: page,
+                last_block,
+                has_more,
+                partial,
+            });
+        }
 
-    Ok(())
+        block += 1;
+        candidates = local_block(
+            state,
+            request,
+            block,
+            window,
+            &first_ids,
+            score_floor,
+            &trailing,
+        )
+        .await?;
+    }
 }
 
-async fn api_info() -> Json<ApiInfo> {
-    Json(ApiInfo {
-        service: "bunker-search",
-        docs: "GET /api/search?q=...&limit=20&source=kiwix OR source=<local>; GET /api/sources",
-    })
+/// Index just past the cursor's hit. If that hit dropped out of the block,
+/// resume at the first hit scored below it instead.
+fn resume_position(hits: &[SearchHit], cursor: &SearchCursor) -> usize {
+    hits.iter()
+        .position(|hit| hit.doc_id == cursor.doc_id)
+        .map(|index| index + 1)
+        .or_else(|| hits.iter().position(|hit| hit.score < cursor.score))
+        .unwrap_or(hits.len())
 }
 
-async fn healthz() -> &'static str {
-    "ok"
+/// Awaits `future` for at most the configured query timeout. `None` means
+/// the stage timed out and the caller should continue with partial results.
+async fn within_timeout<T>(
+    state: &AppState,
+    stage: &'static str,
+    future: impl Future<Output = T>,
+) -> Option<T> {
+    match tokio::time::timeout(state.query_timeout, future).await {
+        Ok(output) => Some(output),
+        Err(_) => {
+            tracing::warn!(
+                stage,
+                timeout_ms = state.query_timeout.as_millis() as u64,
+                "search stage timed out; returning partial results"
+            );
+            None
+        }
+    }
 }
 
-async fn sources_handler(State(state): State<AppState>) -> Json<SourcesResponse> {
-    Json(SourcesResponse {
-        sources: state.sources,
-    })
+/// Runs a synchronous index query on the blocking pool under the query
+/// timeout, so a slow query doesn't hold the request hostage.
+async fn run_local<T, F>(
+    state: &AppState,
+    index: &LocalIndex,
+    stage: &'static str,
+    job: F,
+) -> Result<Option<T>>
+where
+    F: FnOnce(&SearchEngine) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let engine = Arc::clone(&index.engine);
+    let started = Instant::now();
+    let task = tokio::task::spawn_blocking(move || job(&engine));
+    let joined = within_timeout(state, stage, task).await;
+    metrics::histogram!("bunker_search_local_seconds").record(started.elapsed());
+    match joined {
+        Some(joined) => joined.context("local search task failed")?.map(Some),
+        None => Ok(None),
+    }
 }
 
-async fn search_handler(
+async fn answer_stream_handler(
     State(state): State<AppState>,
     Query(params): Query<SearchParams>,
-) -> Result<Json<SearchResponse>, ApiError> {
-    let limit = params
-        .limit
-        .unwrap_or(state.default_limit)
-        .clamp(1, state.max_limit);
-    let offset = params.offset.unwrap_or(0);
-    let query = params.q.unwrap_or_default();
-    let source_filter = params
-        .source
-        .as_deref()
-        .map(str::trim)
-        .filter(|value| !value.is_empty());
-    let want_answer = params.answer.unwrap_or(false);
+) -> Result<Sse<BoxStream<'static, Result<Event, Infallible>>>, ApiError> {
+    let Some(ollama_client) = state.ollama.clone() else {
+        return Err(anyhow!("Ollama integration is not configured").into());
+    };
 
-    let fetch_count = offset
-        .saturating_add(limit)
-        .saturating_mul(3)
-        .min(state.max_limit.saturating_mul(20).max(limit));
+    let request = params.to_request(&state)?;
+    let MergedHits { hits, .. } = merged_hits(&state, &request).await?;
 
-    let mut total_hits = 0usize;
-    let mut hits = Vec::new();
+    let fragments = ollama_client
+        .stream_answer(request.query, &hits)
+        .await
+        .context("failed generating answer from Ollama")?;
 
-    let local_filter = match source_filter {
-        Some(filter) if is_kiwix_filter(filter) => None,
-        _ => source_filter,
-    };
+    let events = fragments
+        .map(|fragment| {
+            Ok(match fragment {
+                Ok(text) => Event::default().data(text),
+                Err(err) => Event::default().event("error").data(format!("{err:#}")),
+            })
+        })
+        .chain(stream::once(async {
+            Ok(Event::default().event("done").data(""))
+        }))
+        .boxed();
 
-    if source_filter.is_none() || local_filter.is_some() {
-        let local_result = state
-            .engine
-            .search(&query, fetch_count.max(1), 0, local_filter)
-            .context("local search query failed")?;
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
 
-        total_hits += local_result.total_hits;
-        hits.extend(local_result.hits);
+/// The widget script under its content-hashed name, with any query
+/// parameters baked in as defaults. It is cached for good and honors a
+/// single `Range`. The stable name (and the hashed name of another build)
+/// redirects there, keeping the query, without being cached.
+async fn embed_js(
+    Path(file): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(params): Query<EmbedParams>,
+) -> Response {
+    if file != *EMBED_JS_FILE {
+        if file != EMBED_JS_STABLE_FILE
+            && !(file.starts_with("bunker-search.") && file.ends_with(".js"))
+        {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        // Relative, so it also resolves behind a path-prefixing proxy.
+        let location = match uri.query() {
+            Some(query) => format!("{}?{query}", *EMBED_JS_FILE),
+            None => EMBED_JS_FILE.clone(),
+        };
+        return (
+            StatusCode::TEMPORARY_REDIRECT,
+            [
+                (header::LOCATION, location),
+                (header::CACHE_CONTROL, cache_control(0, true)),
+            ],
+        )
+            .into_response();
     }
 
-    if let Some(kiwix_client) = &state.kiwix {
-        if source_filter.is_none() || source_filter.is_some_and(is_kiwix_filter) {
-            let kiwix_result = kiwix_client
-                .search(&query, source_filter, fetch_count.max(1))
-                .await
-                .context("Kiwix search failed")?;
+    let defaults = serde_json::to_string(&params).unwrap_or_else(|_| "{}".to_string());
+    let (script, etag) = if defaults == "{}" {
+        (Cow::Borrowed(EMBED_JS), EMBED_JS_ETAG.clone())
+    } else {
+        let script = EMBED_JS.replacen(
+            EMBED_JS_DEFAULTS,
+            &format!("const defaults = {defaults};"),
+            1,
+        );
+        let etag = format!("\"{}\"", &blake3::hash(script.as_bytes()).to_hex()[..32]);
+        (Cow::Owned(script), etag)
+    };
 
-            total_hits += kiwix_result.total_hits;
-            hits.extend(kiwix_result.hits);
-        }
+    let cache_headers = [
+        (
+            header::CACHE_CONTROL,
+            format!("{}, immutable", cache_control(EMBED_JS_MAX_AGE_SECS, true)),
+        ),
+        (header::ETAG, etag.clone()),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+    ];
+    if etag_matches(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    let content_type = [(
+        header::CONTENT_TYPE,
+        "application/javascript; charset=utf-8",
+    )];
+    let len = script.len();
+    match byte_range(&headers, &etag, len) {
+        ByteRange::Whole => (cache_headers, content_type, Body::from(script)).into_response(),
+        ByteRange::Part(start, end) => (
+            StatusCode::PARTIAL_CONTENT,
+            cache_headers,
+            content_type,
+            [(header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))],
+            Body::from(script.as_bytes()[start..=end].to_vec()),
+        )
+            .into_response(),
+        ByteRange::Unsatisfiable => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            cache_headers,
+            [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+        )
+            .into_response(),
     }
+}
 
-    rerank_hits(&query, &mut hits);
+/// What a `Range` header asks of a body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    Whole,
+    /// Inclusive byte offsets.
+    Part(usize, usize),
+    Unsatisfiable,
+}
 
-    let paged_hits: Vec<SearchHit> = hits.into_iter().skip(offset).take(limit).collect();
+/// The single byte range `headers` ask of a `len`-byte body with `etag`.
+/// Malformed or multiple ranges, and an `If-Range` naming another version,
+/// get the whole body, as RFC 9110 allows.
+fn byte_range(headers: &HeaderMap, etag: &str, len: usize) -> ByteRange {
+    let Some(range) = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return ByteRange::Whole;
+    };
+    if headers
+        .get(header::IF_RANGE)
+        .is_some_and(|value| value.as_bytes() != etag.as_bytes())
+    {
+        return ByteRange::Whole;
+    }
+    let Some((first, last)) = range
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.split_once('-'))
+    else {
+        return ByteRange::Whole;
+    };
 
-    let answer = if want_answer {
-        if let Some(ollama_client) = &state.ollama {
-            let generated = ollama_client
-                .synthesize_answer(&query, &paged_hits)
-                .await
-                .context("failed generating answer from Ollama")?;
-            if generated.is_empty() {
-                None
+    let (first, last) = (first.trim(), last.trim());
+    let bounds = if first.is_empty() {
+        // `-n`: the last n bytes.
+        last.parse::<usize>().ok().map(|suffix| {
+            (
+                len.saturating_sub(suffix),
+                len.saturating_sub(1),
+                suffix > 0,
+            )
+        })
+    } else {
+        first.parse::<usize>().ok().and_then(|start| {
+            if last.is_empty() {
+                Some((start, len.saturating_sub(1), true))
             } else {
-                Some(generated)
+                let end = last.parse::<usize>().ok()?;
+                (end >= start).then(|| (start, end.min(len.saturating_sub(1)), true))
             }
-        } else {
-            None
-        }
-    } else {
-        None
+        })
     };
-
-    Ok(Json(SearchResponse {
-        total_hits,
-        hits: paged_hits,
-        answer,
-    }))
-}
-
-async fn embed_js() -> impl IntoResponse {
-    (
-        [(
-            header::CONTENT_TYPE,
-            "application/javascript; charset=utf-8",
-        )],
-        EMBED_JS,
-    )
+    match bounds {
+        None => ByteRange::Whole,
+        Some((start, end, true)) if start < len && start <= end => ByteRange::Part(start, end),
+        Some(_) => ByteRange::Unsatisfiable,
+    }
 }
 
-fn build_cors(origins: &[String]) -> CorsLayer {
-    let base = CorsLayer::new()
+/// CORS policy from the `cors_*` settings. Answer requests (the stream and
+/// `answer=true` searches) are held to `answer_allowed_origins` when it is
+/// set.
+fn build_cors(config: &AppConfig) -> Result<CorsLayer> {
+    let credentials = config.cors_allow_credentials;
+    let mut cors = CorsLayer::new()
         .allow_methods([Method::GET])
-        .allow_headers(Any);
+        .allow_credentials(credentials);
 
-    if origins.is_empty() {
-        return base.allow_origin(Any);
-    }
+    let any_header = config.cors_allowed_headers.is_empty()
+        || config.cors_allowed_headers.iter().any(|name| name == "*");
+    cors = if !any_header {
+        let headers = config
+            .cors_allowed_headers
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes()).with_context(|| {
+                    format!("invalid header name `{name}` in cors_allowed_headers")
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        cors.allow_headers(headers)
+    } else if credentials {
+        // `*` is not honored on credentialed requests; echo the requested
+        // headers instead.
+        cors.allow_headers(AllowHeaders::mirror_request())
+    } else {
+        cors.allow_headers(Any)
+    };
+
+    let search = allowed_origins(
+        config,
+        &config.cors_allowed_origins,
+        "cors_allowed_origins",
+        true,
+    )?;
+    let answer = config
+        .answer_allowed_origins
+        .as_ref()
+        .map(|origins| allowed_origins(config, origins, "answer_allowed_origins", false))
+        .transpose()?;
+
+    Ok(cors.allow_origin(match (search, answer) {
+        (None, None) => AllowOrigin::from(Any),
+        (Some(search), None) => AllowOrigin::list(search),
+        // Answers, streamed or from `answer=true` searches, are checked
+        // against their own list.
+        (search, Some(answer)) => AllowOrigin::predicate(move |origin, parts| {
+            let allowed = if is_answer_uri(&parts.uri) {
+                &answer
+            } else {
+                &search
+            };
+            allowed.as_ref().is_none_or(|list| list.contains(origin))
+        }),
+    }))
+}
 
-    let parsed: Vec<HeaderValue> = origins
+/// The origins listed in `setting`, or `None` when any origin is allowed. An
+/// empty list allows any origin when `empty_allows_any`, else none.
+///
+/// Browsers reject credentialed responses that allow any origin, so that
+/// combination is refused at startup rather than failing in every browser.
+fn allowed_origins(
+    config: &AppConfig,
+    origins: &[String],
+    setting: &str,
+    empty_allows_any: bool,
+) -> Result<Option<Vec<HeaderValue>>> {
+    let allowed: Vec<HeaderValue> = origins
         .iter()
+        .filter(|origin| origin.as_str() != "*")
         .filter_map(|origin| HeaderValue::from_str(origin).ok())
         .collect();
-
-    if parsed.is_empty() {
-        base.allow_origin(Any)
-    } else {
-        base.allow_origin(AllowOrigin::list(parsed))
+    let any_origin =
+        origins.iter().any(|origin| origin == "*") || (origins.is_empty() && empty_allows_any);
+    if any_origin && config.cors_allow_credentials {
+        bail!(
+            "cors_allow_credentials cannot be combined with a wildcard origin; list the allowed origins in {setting}"
+        );
     }
+
+    Ok((!any_origin).then_some(allowed))
 }
 
 fn collect_local_sources(sources: &[SourceConfig]) -> Vec<String> {
     sources
         .iter()
-        .map(|source| match source {
-            SourceConfig::Filesystem { name, .. }
-            | SourceConfig::Jsonl { name, .. }
-            | SourceConfig::StackExchangeXml { name, .. } => name.clone(),
-        })
+        .map(|source| source.name().to_string())
         .collect()
 }
 
@@ -292,7 +2604,7 @@ fn is_kiwix_filter(value: &str) -> bool {
     value.eq_ignore_ascii_case("kiwix") || value.starts_with("kiwix:")
 }
 
-fn rerank_hits(query: &str, hits: &mut [SearchHit]) {
+fn rerank_hits(query: &str, hits: &mut [SearchHit], index: &LocalIndex) {
     let normalized_query = normalize_for_matching(query);
     if normalized_query.is_empty() || hits.is_empty() {
         return;
@@ -303,87 +2615,145 @@ fn rerank_hits(query: &str, hits: &mut [SearchHit]) {
         return;
     }
 
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default();
     for hit in hits.iter_mut() {
-        hit.score = rerank_score(hit, &normalized_query, &query_tokens);
+        let rules = index.rerank_rules(&hit.source);
+        let mut boosts = rerank_boosts(hit, &rules, &normalized_query, &query_tokens);
+        hit.score = hit.score.max(0.0) + boosts.iter().map(|(_, boost)| boost).sum::<f32>();
+        // Decays the reranked score, so age weighs the same against every
+        // boost; the difference is listed like a boost.
+        if let Some(decay) = index.recency_decay(hit, now).filter(|decay| *decay != 0.0) {
+            hit.score += decay;
+            boosts.push(("recency_decay", decay));
+        }
+        if let Some(explanation) = &mut hit.explanation {
+            explanation.extend(
+                boosts
+                    .into_iter()
+                    .map(|(name, boost)| (name.to_string(), boost)),
+            );
+        }
     }
 
+    sort_by_score(hits, index);
+}
+
+/// Highest score first; ties go to the source listed first in
+/// `source_priority`, then to the shorter title.
+fn sort_by_score(hits: &mut [SearchHit], index: &LocalIndex) {
     hits.sort_by(|left, right| {
         right
             .score
             .total_cmp(&left.score)
+            .then_with(|| {
+                index
+                    .source_priority(&left.source)
+                    .cmp(&index.source_priority(&right.source))
+            })
             .then_with(|| left.title.len().cmp(&right.title.len()))
             .then_with(|| left.title.cmp(&right.title))
+            .then_with(|| left.doc_id.cmp(&right.doc_id))
     });
 }
 
-fn rerank_score(hit: &SearchHit, normalized_query: &str, query_tokens: &[String]) -> f32 {
-    let base_score = hit.score.max(0.0);
+/// Kiwix scores are synthetic (500 minus the rank), so without reranking
+/// every Kiwix hit would outrank every local one. Rescales them to the top
+/// local score divided by one plus the rank: each collection's first hit
+/// ties with the top local hit and later ones fall off like reciprocal
+/// ranks. Left alone when there are no local hits to compare with.
+fn normalize_kiwix_scores(hits: &mut [SearchHit]) {
+    let top_local = hits
+        .iter()
+        .filter(|hit| !is_kiwix_filter(&hit.source))
+        .map(|hit| hit.score)
+        .fold(0.0f32, f32::max);
+    if top_local <= 0.0 {
+        return;
+    }
+    for hit in hits.iter_mut().filter(|hit| is_kiwix_filter(&hit.source)) {
+        let rank = (kiwix::TOP_HIT_SCORE - hit.score).max(0.0);
+        hit.score = top_local / (1.0 + rank);
+    }
+}
 
+/// Named boosts (and penalties) `rerank_hits` adds to a hit's base score:
+/// the title and coverage boosts, then those of the source's `rules`. Boosts
+/// that don't apply are left out.
+fn rerank_boosts(
+    hit: &SearchHit,
+    rules: &[RerankRule],
+    normalized_query: &str,
+    query_tokens: &[String],
+) -> Vec<(&'static str, f32)> {
     let normalized_title = normalize_for_matching(&hit.title);
     let normalized_preview = normalize_for_matching(&hit.preview);
     let normalized_location = normalize_for_matching(&hit.location);
     let location_lc = hit.location.to_lowercase();
     let title_lc = hit.title.to_lowercase();
-    let source_lc = hit.source.to_lowercase();
 
     let title_coverage = token_coverage(query_tokens, &normalized_title);
     let preview_coverage = token_coverage(query_tokens, &normalized_preview);
 
-    let mut boost = 0.0;
+    let mut boosts = Vec::new();
 
     if normalized_title == normalized_query {
-        boost += 320.0;
+        boosts.push(("title_exact", 320.0));
     }
     if normalized_title.contains(normalized_query) && normalized_query.len() >= 5 {
-        boost += 210.0;
+        boosts.push(("title_contains_query", 210.0));
     }
 
     // Title coverage gets stronger weight than snippet coverage.
-    boost += title_coverage * 340.0;
-    boost += preview_coverage * 90.0;
+    boosts.push(("title_coverage", title_coverage * 340.0));
+    boosts.push(("preview_coverage", preview_coverage * 90.0));
 
-    let is_gutenberg = source_lc.contains("gutenberg");
-    if is_gutenberg {
-        boost += title_coverage * 240.0;
+    if rules.contains(&RerankRule::Gutenberg) {
+        boosts.push(("gutenberg_title_coverage", title_coverage * 240.0));
         if title_coverage >= 0.6 {
-            boost += 80.0;
+            boosts.push(("gutenberg_title_coverage_60", 80.0));
         }
         if title_coverage >= 0.75 {
-            boost += 220.0;
+            boosts.push(("gutenberg_title_coverage_75", 220.0));
         }
         if title_coverage >= 0.9 {
-            boost += 160.0;
+            boosts.push(("gutenberg_title_coverage_90", 160.0));
         }
 
         if !normalized_query.contains("chapter")
             && (title_lc.contains(", chapters") || location_lc.contains("chapters%20"))
         {
-            boost -= 130.0;
+            boosts.push(("gutenberg_chapters_penalty", -130.0));
         }
 
         if !normalized_query.contains("cover")
             && (title_lc.contains('(') || title_lc.contains("edition"))
         {
-            boost -= 35.0;
+            boosts.push(("gutenberg_edition_penalty", -35.0));
         }
 
         if location_lc.ends_with(".html")
             && !location_lc.contains("chapters%20")
             && !location_lc.contains("_cover")
         {
-            boost += 90.0;
+            boosts.push(("gutenberg_full_book", 90.0));
         }
     }
 
     // Prefer full book page over cover page for normal title searches.
-    let is_cover = normalized_title.contains(" cover")
-        || normalized_location.contains(" cover")
-        || location_lc.contains("_cover");
-    if is_cover && !normalized_query.contains("cover") {
-        boost -= 90.0;
+    if rules.contains(&RerankRule::Cover) {
+        let is_cover = normalized_title.contains(" cover")
+            || normalized_location.contains(" cover")
+            || location_lc.contains("_cover");
+        if is_cover && !normalized_query.contains("cover") {
+            boosts.push(("cover_penalty", -90.0));
+        }
     }
 
-    base_score + boost
+    boosts.retain(|(_, boost)| *boost != 0.0);
+    boosts
 }
 
 fn token_coverage(query_tokens: &[String], target_text: &str) -> f32 {
@@ -400,10 +2770,7 @@ fn token_coverage(query_tokens: &[String], target_text: &str) -> f32 {
     let mut prefix_hits = 0usize;
 
     for query_token in query_tokens {
-        if target_tokens
-            .iter()
-            .any(|target| *target == query_token.as_str())
-        {
+        if target_tokens.contains(&query_token.as_str()) {
             exact_hits += 1;
             continue;
         }
@@ -420,6 +2787,39 @@ fn token_coverage(query_tokens: &[String], target_text: &str) -> f32 {
     (exact_hits as f32 + prefix_hits as f32 * 0.7) / query_tokens.len() as f32
 }
 
+/// Sets each hit's `highlighted_title`: its title, HTML-escaped, with
+/// `<mark>` around the words that equal a query word once normalized the
+/// way `rerank_hits` compares them.
+fn highlight_titles(query: &str, hits: &mut [SearchHit]) {
+    let query_tokens: HashSet<String> = tokenize(&normalize_for_matching(query))
+        .into_iter()
+        .collect();
+    if query_tokens.is_empty() {
+        return;
+    }
+
+    for hit in hits {
+        let mut highlighted = String::with_capacity(hit.title.len() + 16);
+        let mut rest = hit.title.as_str();
+        while !rest.is_empty() {
+            let word = rest.starts_with(|ch: char| ch.is_ascii_alphanumeric());
+            let end = rest
+                .find(|ch: char| ch.is_ascii_alphanumeric() != word)
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(end);
+            if word && query_tokens.contains(&run.to_ascii_lowercase()) {
+                highlighted.push_str("<mark>");
+                highlighted.push_str(&search::escape_html(run));
+                highlighted.push_str("</mark>");
+            } else {
+                highlighted.push_str(&search::escape_html(run));
+            }
+            rest = tail;
+        }
+        hit.highlighted_title = highlighted;
+    }
+}
+
 fn tokenize(normalized_text: &str) -> Vec<String> {
     normalized_text
         .split_whitespace()
@@ -470,3 +2870,51 @@ async fn shutdown_signal() {
 
     tracing::info!("shutdown signal received");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn browse_refuses_offsets_past_the_search_window() {
+        let (_dir, mut config) = search::index_fixture(&[
+            ("alpha.txt", "alpha notes"),
+            ("beta.txt", "beta notes"),
+            ("gamma.txt", "gamma notes"),
+        ]);
+        config.max_result_limit = 10;
+        let state = app_state(&config).await.unwrap();
+
+        let browse = |offset: usize| {
+            browse_handler(
+                State(state.clone()),
+                Query(BrowseParams {
+                    source: Some("docs".to_string()),
+                    index: None,
+                    limit: Some(5),
+                    offset: Some(offset),
+                }),
+            )
+        };
+
+        let Ok(Json(first)) = browse(1).await else {
+            panic!("browsing from offset 1 failed");
+        };
+        assert_eq!(first.total_hits, 3);
+        let titles: Vec<&str> = first.hits.iter().map(|hit| hit.title.as_str()).collect();
+        assert_eq!(titles, ["beta", "gamma"]);
+
+        // 20 × max_result_limit deep, less the page itself.
+        let Ok(Json(last)) = browse(195).await else {
+            panic!("browsing at the deepest offset failed");
+        };
+        assert!(last.hits.is_empty());
+
+        for offset in [196, 1_000_000_000_000] {
+            let Err(err) = browse(offset).await else {
+                panic!("offset {offset} was accepted");
+            };
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        }
+    }
+}
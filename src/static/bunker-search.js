@@ -1,11 +1,18 @@
 (function () {
   const script = document.currentScript;
-  const targetSelector = script?.dataset?.target || "#bunker-search";
-  const resultLimit = Number(script?.dataset?.limit || "10");
-  const source = script?.dataset?.source || "";
-  const wantAnswer = script?.dataset?.answer === "true";
-
-  let apiBase = script?.dataset?.api;
+  // Filled in by the server from the script URL's query parameters
+  // (`/embed/bunker-search.js?limit=5`); data attributes still win.
+  const defaults = {};
+  const dataset = script?.dataset || {};
+  const flag = (name) => (dataset[name] ? dataset[name] === "true" : defaults[name] === true);
+
+  const targetSelector = dataset.target || defaults.target || "#bunker-search";
+  const resultLimit = Number(dataset.limit || defaults.limit || "10");
+  const source = dataset.source || defaults.source || "";
+  const wantAnswer = flag("answer");
+  const wantFuzzy = flag("fuzzy");
+
+  let apiBase = dataset.api || defaults.api;
   if (!apiBase && script?.src) {
     try {
       apiBase = new URL(script.src, window.location.href).origin;
@@ -38,7 +45,11 @@
       .bunker-search-title a { color:inherit; text-decoration:none; }
       .bunker-search-title a:hover { text-decoration:underline; }
       .bunker-search-preview { margin-top:4px; font-size:13px; opacity:.92; }
+      .bunker-search-title mark,
+      .bunker-search-preview mark { background:rgba(253,224,71,.35); color:inherit; border-radius:3px; padding:0 1px; }
       .bunker-search-foot { margin-top:5px; font-size:12px; opacity:.75; display:flex; gap:8px; flex-wrap:wrap; }
+      .bunker-search-expand { border:0; background:none; color:inherit; font:inherit; padding:0; cursor:pointer; text-decoration:underline; }
+      .bunker-search-full { margin-top:6px; max-height:320px; overflow:auto; white-space:pre-wrap; font-size:13px; border-top:1px solid rgba(255,255,255,.12); padding-top:6px; }
       .bunker-search-empty { font-size:13px; opacity:.8; }
     `;
     document.head.appendChild(style);
@@ -75,14 +86,20 @@
 
     const html = payload.hits
       .map((hit) => {
-        const title = escapeHtml(hit.title || "Untitled");
+        // highlighted_title and highlighted_preview are already HTML-escaped
+        // server-side, with <mark> around matches.
+        const title = hit.highlighted_title || escapeHtml(hit.title || "Untitled");
         const sourceLabel = escapeHtml(hit.source || "source");
-        const preview = escapeHtml(hit.preview || "");
+        const preview = hit.highlighted_preview || escapeHtml(hit.preview || "");
         const location = escapeHtml(hit.location || "");
 
         const titleHtml = hit.url
           ? `<a href="${escapeAttr(hit.url)}" target="_blank" rel="noopener">${title}</a>`
           : title;
+        // Kiwix hits aren't in the local index, so they have no stored text.
+        const expandHtml = hit.source?.startsWith("kiwix")
+          ? ""
+          : `<button type="button" class="bunker-search-expand" data-doc-id="${escapeAttr(hit.doc_id)}">Full text</button>`;
 
         return `
           <article class="bunker-search-hit">
@@ -91,7 +108,9 @@
             <div class="bunker-search-foot">
               <span>${sourceLabel}</span>
               <span>${location}</span>
+              ${expandHtml}
             </div>
+            <div class="bunker-search-full" style="display:none;"></div>
           </article>
         `;
       })
@@ -122,6 +141,9 @@
     if (wantAnswer) {
       params.set("answer", "true");
     }
+    if (wantFuzzy) {
+      params.set("fuzzy", "true");
+    }
 
     try {
       const response = await fetch(`${apiBase}/api/search?${params.toString()}`);
@@ -154,6 +176,43 @@
     }
   }
 
+  async function toggleFullText(button) {
+    const full = button.closest(".bunker-search-hit").querySelector(".bunker-search-full");
+    if (full.style.display !== "none") {
+      full.style.display = "none";
+      button.textContent = "Full text";
+      return;
+    }
+    full.style.display = "block";
+    button.textContent = "Hide text";
+    if (full.dataset.loaded) {
+      return;
+    }
+
+    full.textContent = "Loading...";
+    try {
+      const params = new URLSearchParams();
+      params.set("doc_id", button.dataset.docId);
+      const response = await fetch(`${apiBase}/api/document?${params.toString()}`);
+      if (!response.ok) {
+        throw new Error(`HTTP ${response.status}`);
+      }
+      const payload = await response.json();
+      full.textContent = payload.body || "(no text)";
+      full.dataset.loaded = "true";
+    } catch (err) {
+      full.textContent = "Could not fetch the document text.";
+      console.error("bunker-search:", err);
+    }
+  }
+
+  results.addEventListener("click", function (event) {
+    const button = event.target.closest(".bunker-search-expand");
+    if (button) {
+      toggleFullText(button);
+    }
+  });
+
   input.addEventListener("input", function () {
     clearTimeout(debounceTimer);
     debounceTimer = setTimeout(runSearch, 170);
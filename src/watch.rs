@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::config::{AppConfig, SourceConfig, TitleFrom};
+use crate::ingest::FilesystemWalker;
+use crate::{embeddings, indexer};
+
+/// Upper bound on paths collected into one batch, so a steady stream of
+/// changes still gets committed instead of being debounced forever.
+const MAX_WATCH_BATCH_PATHS: usize = 1000;
+
+struct WatchedRoot<'a> {
+    /// Config of the index the source belongs to.
+    config: &'a AppConfig,
+    index: &'a str,
+    name: &'a str,
+    root: PathBuf,
+    walker: FilesystemWalker,
+    extensions: &'a [String],
+    title_from: TitleFrom,
+}
+
+/// Watches the filesystem source roots of every given index and re-indexes
+/// changed paths in debounced batches until interrupted. Other source types
+/// are not watched.
+pub async fn watch_indexes(indexes: &[(String, AppConfig)]) -> Result<()> {
+    let mut roots = Vec::new();
+    let mut index_dirs = Vec::with_capacity(indexes.len());
+    for (index, config) in indexes {
+        for source in &config.sources {
+            match source {
+                SourceConfig::Filesystem {
+                    name,
+                    path,
+                    extensions,
+                    follow_symlinks,
+                    ignore,
+                    respect_gitignore,
+                    exclude,
+                    title_from,
+                    ..
+                } => {
+                    let root = path.canonicalize().with_context(|| {
+                        format!("failed to resolve source root {}", path.display())
+                    })?;
+                    let walker = FilesystemWalker::new(
+                        &root,
+                        *follow_symlinks,
+                        ignore,
+                        *respect_gitignore,
+                        exclude,
+                    )?;
+                    roots.push(WatchedRoot {
+                        config,
+                        index,
+                        name,
+                        root,
+                        walker,
+                        extensions,
+                        title_from: *title_from,
+                    });
+                }
+                other => {
+                    tracing::info!(
+                        index = %index,
+                        source = other.name(),
+                        "not a filesystem source; excluded from watch mode"
+                    );
+                }
+            }
+        }
+
+        // Commits inside an index dir must not trigger another batch.
+        index_dirs.push(
+            config
+                .index_dir
+                .canonicalize()
+                .unwrap_or_else(|_| config.index_dir.clone()),
+        );
+    }
+
+    let Some(first) = roots.first() else {
+        bail!("--watch requires at least one filesystem source");
+    };
+    let debounce = Duration::from_millis(first.config.watch_debounce_ms);
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })
+    .context("failed to create filesystem watcher")?;
+
+    for root in &roots {
+        watcher
+            .watch(&root.root, RecursiveMode::Recursive)
+            .with_context(|| format!("failed to watch {}", root.root.display()))?;
+    }
+
+    tracing::info!(
+        roots = roots.len(),
+        "watching filesystem sources for changes"
+    );
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        let event = tokio::select! {
+            event = receiver.recv() => event,
+            _ = &mut ctrl_c => {
+                tracing::info!("stopping watch mode");
+                return Ok(());
+            }
+        };
+        let Some(event) = event else {
+            return Ok(());
+        };
+
+        let mut pending: BTreeMap<usize, BTreeSet<PathBuf>> = BTreeMap::new();
+        collect_paths(&roots, &index_dirs, event, &mut pending);
+
+        while pending.values().map(BTreeSet::len).sum::<usize>() < MAX_WATCH_BATCH_PATHS {
+            match tokio::time::timeout(debounce, receiver.recv()).await {
+                Ok(Some(event)) => collect_paths(&roots, &index_dirs, event, &mut pending),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let mut changed = BTreeMap::new();
+        for (root_index, paths) in pending {
+            let root = &roots[root_index];
+            match indexer::apply_filesystem_changes(
+                root.config,
+                root.name,
+                &root.walker,
+                root.extensions,
+                root.title_from,
+                &paths,
+            ) {
+                Ok(stats) => {
+                    if stats.indexed > 0 || stats.removed > 0 {
+                        changed.insert(root.index, root.config);
+                    }
+                    tracing::info!(
+                        index = %root.index,
+                        source = root.name,
+                        scanned = stats.scanned,
+                        indexed = stats.indexed,
+                        skipped = stats.skipped,
+                        removed = stats.removed,
+                        evicted = stats.evicted,
+                        elapsed_ms = stats.elapsed.as_millis() as u64,
+                        "applied filesystem changes"
+                    );
+                }
+                Err(err) => {
+                    tracing::warn!(source = root.name, error = %err, "failed to apply filesystem changes");
+                }
+            }
+        }
+
+        for (index, config) in changed {
+            if let Err(err) = embeddings::update_embeddings(config, false).await {
+                tracing::warn!(index = %index, error = %err, "failed to update embeddings");
+            }
+        }
+    }
+}
+
+fn collect_paths(
+    roots: &[WatchedRoot<'_>],
+    index_dirs: &[PathBuf],
+    event: notify::Result<Event>,
+    pending: &mut BTreeMap<usize, BTreeSet<PathBuf>>,
+) {
+    let event = match event {
+        Ok(event) => event,
+        Err(err) => {
+            tracing::warn!(%err, "filesystem watcher error");
+            return;
+        }
+    };
+
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+
+    for path in event.paths {
+        if index_dirs
+            .iter()
+            .any(|index_dir| path.starts_with(index_dir))
+        {
+            continue;
+        }
+        if let Some(root_index) = roots.iter().position(|root| path.starts_with(&root.root)) {
+            pending.entry(root_index).or_default().insert(path);
+        }
+    }
+}